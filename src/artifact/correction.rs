@@ -0,0 +1,248 @@
+use std::collections::HashSet;
+
+use edit_distance::edit_distance;
+use log::warn;
+
+use crate::artifact::internal_artifact::{
+    ArtifactSetName, ArtifactSlot, ArtifactStat, ArtifactStatName, ARTIFACT_NAMES_CHS,
+};
+use crate::artifact::validation::main_stat_slots;
+
+// most edits tried on a misread number or title
+const NUMBER_BUDGET: usize = 2;
+const TITLE_BUDGET: usize = 2;
+
+// every string up to `budget` edits away from `value` with the number of edits, an
+// edit being what the model gets wrong with numbers: a dropped or extra decimal
+// point, or a 1 read as 7 and the other way round
+fn number_edits(value: &str, budget: usize) -> Vec<(String, usize)> {
+    let mut seen: HashSet<String> = HashSet::new();
+    seen.insert(String::from(value));
+    let mut frontier = vec![String::from(value)];
+    let mut result = Vec::new();
+
+    for edits in 1..=budget {
+        let mut next = Vec::new();
+        for s in frontier.iter() {
+            let chars: Vec<char> = s.chars().collect();
+            let mut variants: Vec<Vec<char>> = Vec::new();
+            for (i, &c) in chars.iter().enumerate() {
+                let swapped = match c {
+                    '1' => '7',
+                    '7' => '1',
+                    '.' => {
+                        let mut v = chars.clone();
+                        v.remove(i);
+                        variants.push(v);
+                        continue;
+                    }
+                    _ => continue,
+                };
+                let mut v = chars.clone();
+                v[i] = swapped;
+                variants.push(v);
+            }
+            if !chars.contains(&'.') {
+                for i in 1..chars.len() {
+                    if chars[i - 1].is_ascii_digit() && chars[i].is_ascii_digit() {
+                        let mut v = chars.clone();
+                        v.insert(i, '.');
+                        variants.push(v);
+                    }
+                }
+            }
+
+            for v in variants {
+                let v: String = v.into_iter().collect();
+                if seen.insert(v.clone()) {
+                    result.push((v.clone(), edits));
+                    next.push(v);
+                }
+            }
+        }
+        frontier = next;
+    }
+    result
+}
+
+// the panel shows percentages with at most one decimal and other stats with none
+fn is_displayable(value: &str) -> bool {
+    match value.find('.') {
+        Some(i) => value.ends_with('%') && value.len() - i <= 3,
+        None => true,
+    }
+}
+
+// `text` is `name+value` as on the panel. The literal stat when `is_valid` accepts
+// it, else the accepted edit of the value with the fewest edits, closest to the
+// literal value on a tie. Corrections are logged with `field`
+pub fn correct_stat(
+    field: &str,
+    text: &str,
+    is_valid: impl Fn(&ArtifactStat) -> bool,
+) -> Option<ArtifactStat> {
+    let literal = ArtifactStat::from_zh_cn_raw(text);
+    if let Some(ref stat) = literal {
+        if is_valid(stat) {
+            return literal;
+        }
+    }
+    let (name, value) = match text.find('+') {
+        Some(i) => (&text[..i], &text[i + 1..]),
+        None => return literal,
+    };
+    let original = literal.as_ref().map(|s| s.value);
+
+    let mut best: Option<(ArtifactStat, String, usize, f64)> = None;
+    for (candidate, edits) in number_edits(value, NUMBER_BUDGET) {
+        if !is_displayable(&candidate) {
+            continue;
+        }
+        let stat = match ArtifactStat::from_zh_cn_raw(&format!("{}+{}", name, candidate)) {
+            Some(v) => v,
+            None => continue,
+        };
+        if !is_valid(&stat) {
+            continue;
+        }
+        let distance = original.map_or(0.0, |v| (stat.value - v).abs());
+        let better = match best {
+            Some((_, _, e, d)) => edits < e || (edits == e && distance < d),
+            None => true,
+        };
+        if better {
+            best = Some((stat, candidate, edits, distance));
+        }
+    }
+
+    match best {
+        Some((stat, candidate, _, _)) => {
+            warn!(
+                "corrected {}: `{}` -> `{}+{}`",
+                field, text, name, candidate
+            );
+            Some(stat)
+        }
+        None => literal,
+    }
+}
+
+// a misread title: the closest titles within `TITLE_BUDGET` edits have to agree on
+// the set, and on the slot unless the main stat leaves only one of theirs
+pub fn correct_title(
+    title: &str,
+    main_stat: &ArtifactStatName,
+) -> Option<(ArtifactSetName, ArtifactSlot)> {
+    let distances: Vec<usize> = ARTIFACT_NAMES_CHS
+        .iter()
+        .map(|name| edit_distance(title, name))
+        .collect();
+    let min = *distances.iter().min()?;
+    if min > TITLE_BUDGET {
+        return None;
+    }
+    let closest: Vec<&str> = ARTIFACT_NAMES_CHS
+        .iter()
+        .zip(distances.iter())
+        .filter(|(_, &d)| d == min)
+        .map(|(&name, _)| name)
+        .collect();
+
+    let set_name = ArtifactSetName::from_zh_cn(closest[0])?;
+    if closest
+        .iter()
+        .any(|&name| ArtifactSetName::from_zh_cn(name).as_ref() != Some(&set_name))
+    {
+        return None;
+    }
+
+    let mut slots: Vec<ArtifactSlot> = Vec::new();
+    for slot in closest
+        .iter()
+        .filter_map(|&name| ArtifactSlot::from_zh_cn(name))
+    {
+        if !slots.contains(&slot) {
+            slots.push(slot);
+        }
+    }
+    if slots.len() > 1 {
+        slots.retain(|slot| main_stat_slots(main_stat).contains(slot));
+    }
+    if slots.len() != 1 {
+        return None;
+    }
+
+    let slot = slots.remove(0);
+    warn!(
+        "corrected title: `{}` -> {:?} {:?} (from {})",
+        title,
+        set_name,
+        slot,
+        closest.join(", ")
+    );
+    Some((set_name, slot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::validation::{is_main_stat_valid, is_sub_stat_valid};
+
+    fn sub_stat(text: &str) -> Option<ArtifactStat> {
+        correct_stat("sub_stat_1", text, |s| is_sub_stat_valid(5, 0, s))
+    }
+
+    #[test]
+    fn restores_a_dropped_decimal_point() {
+        let stat = sub_stat("暴击率+39%").unwrap();
+        assert_eq!(stat.name, ArtifactStatName::Critical);
+        assert!((stat.value - 0.039).abs() < 1e-9);
+    }
+
+    #[test]
+    fn swaps_a_misread_one_or_seven() {
+        let stat = sub_stat("元素精通+79").unwrap();
+        assert_eq!(stat.value, 19.0);
+
+        let main_stat = correct_stat("main_stat", "攻击力+317", |s| {
+            is_main_stat_valid(5, 20, s)
+        })
+        .unwrap();
+        assert_eq!(main_stat.name, ArtifactStatName::Atk);
+        assert_eq!(main_stat.value, 311.0);
+    }
+
+    #[test]
+    fn a_valid_literal_is_never_changed() {
+        // 17 is a valid roll, 11 is not, and 1.7 is no flat value
+        assert_eq!(sub_stat("攻击力+17").unwrap().value, 17.0);
+        assert!((sub_stat("暴击率+3.9%").unwrap().value - 0.039).abs() < 1e-9);
+        // nothing within the budget is valid, the literal is kept
+        assert!((sub_stat("暴击率+50%").unwrap().value - 0.5).abs() < 1e-9);
+        assert!(sub_stat("暴击率").is_none());
+    }
+
+    #[test]
+    fn corrects_a_misread_title() {
+        use ArtifactStatName::*;
+
+        assert_eq!(
+            correct_title("磐陀裂生之化", &Hp),
+            Some((ArtifactSetName::ArchaicPetra, ArtifactSlot::Flower))
+        );
+        // as close to the flower as to the feather, the main stat decides
+        let title = "染血的铁之羽";
+        let set = ArtifactSetName::BloodstainedChivalry;
+        assert_eq!(
+            correct_title(title, &Hp),
+            Some((set.clone(), ArtifactSlot::Flower))
+        );
+        assert_eq!(
+            correct_title(title, &Atk),
+            Some((set, ArtifactSlot::Feather))
+        );
+        assert_eq!(correct_title(title, &CriticalDamage), None);
+        // too far from every title
+        assert_eq!(correct_title("完全不认识", &Hp), None);
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::artifact::internal_artifact::{ArtifactStat, InternalArtifact};
+use crate::artifact::merge::{match_key, KeyFields};
+
+#[derive(Serialize)]
+pub struct ArtifactChange {
+    pub old: InternalArtifact,
+    pub new: InternalArtifact,
+}
+
+// artifacts serialize as mona entries
+#[derive(Serialize, Default)]
+pub struct InventoryDiff {
+    pub added: Vec<InternalArtifact>,
+    pub removed: Vec<InternalArtifact>,
+    pub levelled: Vec<ArtifactChange>,
+    pub equip_changed: Vec<ArtifactChange>,
+}
+
+fn sub_stats(artifact: &InternalArtifact) -> Vec<&ArtifactStat> {
+    [
+        &artifact.sub_stat_1,
+        &artifact.sub_stat_2,
+        &artifact.sub_stat_3,
+        &artifact.sub_stat_4,
+    ]
+    .iter()
+    .filter_map(|s| s.as_ref())
+    .collect()
+}
+
+// `new` can be `old` after levelling: same identity, every substat kept and not
+// smaller, and a 3 line artifact may have gained its 4th substat. The main stat
+// grows with every level and each +4 rolls a substat, so something must have grown
+fn is_levelled(old: &InternalArtifact, new: &InternalArtifact) -> bool {
+    if old.set_name != new.set_name
+        || old.slot != new.slot
+        || old.star != new.star
+        || old.main_stat.name != new.main_stat.name
+        || new.level <= old.level
+    {
+        return false;
+    }
+    // 0 when the export has no main stat value
+    if old.main_stat.value != 0.0
+        && new.main_stat.value != 0.0
+        && new.main_stat.value <= old.main_stat.value
+    {
+        return false;
+    }
+
+    let old_subs = sub_stats(old);
+    let new_subs = sub_stats(new);
+    if new_subs.len() < old_subs.len() {
+        return false;
+    }
+    let kept = old_subs
+        .iter()
+        .zip(new_subs.iter())
+        .all(|(o, n)| o.name == n.name && n.value >= o.value - 1e-6);
+    if !kept {
+        return false;
+    }
+
+    let rolled = new.level / 4 > old.level / 4;
+    let grown = new_subs.len() > old_subs.len()
+        || old_subs
+            .iter()
+            .zip(new_subs.iter())
+            .any(|(o, n)| n.value > o.value + 1e-6);
+    !rolled || grown
+}
+
+pub fn diff(old: &[InternalArtifact], new: &[InternalArtifact]) -> InventoryDiff {
+    let mut result = InventoryDiff::default();
+
+    // unchanged artifacts (up to equip), several identical ones pair up in order
+    let fields = KeyFields {
+        main_value: KeyFields::of(old).main_value && KeyFields::of(new).main_value,
+        equip: false,
+    };
+    let mut old_by_key: HashMap<InternalArtifact, Vec<usize>> = HashMap::new();
+    for (i, a) in old.iter().enumerate().rev() {
+        old_by_key.entry(match_key(a, fields)).or_default().push(i);
+    }
+    let mut old_used = vec![false; old.len()];
+    let mut new_left: Vec<&InternalArtifact> = Vec::new();
+    for a in new.iter() {
+        match old_by_key
+            .get_mut(&match_key(a, fields))
+            .and_then(|v| v.pop())
+        {
+            Some(i) => {
+                old_used[i] = true;
+                if old[i].equip != a.equip {
+                    result.equip_changed.push(ArtifactChange {
+                        old: old[i].clone(),
+                        new: a.clone(),
+                    });
+                }
+            }
+            None => new_left.push(a),
+        }
+    }
+
+    let mut new_used = vec![false; new_left.len()];
+    for (i, o) in old.iter().enumerate() {
+        if old_used[i] {
+            continue;
+        }
+        let found = (0..new_left.len()).find(|&j| !new_used[j] && is_levelled(o, new_left[j]));
+        match found {
+            Some(j) => {
+                new_used[j] = true;
+                if o.equip != new_left[j].equip {
+                    result.equip_changed.push(ArtifactChange {
+                        old: o.clone(),
+                        new: new_left[j].clone(),
+                    });
+                }
+                result.levelled.push(ArtifactChange {
+                    old: o.clone(),
+                    new: new_left[j].clone(),
+                });
+            }
+            None => result.removed.push(o.clone()),
+        }
+    }
+
+    for (j, n) in new_left.iter().enumerate() {
+        if !new_used[j] {
+            result.added.push((*n).clone());
+        }
+    }
+
+    result
+}
+
+fn describe(artifact: &InternalArtifact) -> String {
+    let subs = sub_stats(artifact)
+        .iter()
+        .map(|s| {
+            if s.name.is_percentage() {
+                format!("{:?} {:.1}%", s.name, s.value * 100.0)
+            } else {
+                format!("{:?} {}", s.name, s.value)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+    format!(
+        "{} {:?} {}★ +{} {:?} [{}]",
+        artifact.set_name,
+        artifact.slot,
+        artifact.star,
+        artifact.level,
+        artifact.main_stat.name,
+        subs
+    )
+}
+
+fn equip_name(artifact: &InternalArtifact) -> &str {
+    match artifact.equip {
+        Some(ref s) => s.as_str(),
+        None => "无",
+    }
+}
+
+impl fmt::Display for InventoryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "新增 {}，移除 {}，升级 {}，更换装备 {}",
+            self.added.len(),
+            self.removed.len(),
+            self.levelled.len(),
+            self.equip_changed.len()
+        )?;
+        for a in self.added.iter() {
+            writeln!(f, "+ {}", describe(a))?;
+        }
+        for a in self.removed.iter() {
+            writeln!(f, "- {}", describe(a))?;
+        }
+        for c in self.levelled.iter() {
+            writeln!(f, "↑ {}\n  -> {}", describe(&c.old), describe(&c.new))?;
+        }
+        for c in self.equip_changed.iter() {
+            writeln!(
+                f,
+                "* {}: {} -> {}",
+                describe(&c.new),
+                equip_name(&c.old),
+                equip_name(&c.new)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::internal_artifact::{ArtifactSetName, ArtifactSlot, ArtifactStatName};
+
+    fn stat(name: ArtifactStatName, value: f64) -> Option<ArtifactStat> {
+        Some(ArtifactStat::from_display(name, value))
+    }
+
+    fn artifact(level: u32, main_value: f64, crit: f64, equip: Option<&str>) -> InternalArtifact {
+        InternalArtifact {
+            set_name: ArtifactSetName::CrimsonWitch,
+            slot: ArtifactSlot::Head,
+            star: 5,
+            level,
+            main_stat: ArtifactStat::from_display(ArtifactStatName::CriticalDamage, main_value),
+            sub_stat_1: stat(ArtifactStatName::Critical, crit),
+            sub_stat_2: stat(ArtifactStatName::Atk, 16.0),
+            sub_stat_3: stat(ArtifactStatName::Recharge, 5.8),
+            sub_stat_4: None,
+            equip: equip.map(String::from),
+        }
+    }
+
+    #[test]
+    fn levelled_needs_something_to_grow() {
+        let old = artifact(0, 9.3, 3.1, None);
+
+        // +4 rolled crit and gave the 4th line
+        let mut new = artifact(4, 18.6, 6.6, None);
+        new.sub_stat_4 = stat(ArtifactStatName::DefPercentage, 5.1);
+        assert!(is_levelled(&old, &new));
+
+        // +4 without a rolled substat
+        assert!(!is_levelled(&old, &artifact(4, 18.6, 3.1, None)));
+        // +2 does not roll, but the main stat still grows
+        assert!(is_levelled(&old, &artifact(2, 13.9, 3.1, None)));
+        assert!(!is_levelled(&old, &artifact(2, 9.3, 3.1, None)));
+        // a lower level or a smaller substat is a different artifact
+        assert!(!is_levelled(&artifact(4, 18.6, 6.6, None), &old));
+        assert!(!is_levelled(&old, &artifact(4, 18.6, 2.7, None)));
+    }
+
+    #[test]
+    fn levelled_skips_the_main_value_when_it_is_unknown() {
+        let old = artifact(0, 0.0, 3.1, None);
+        assert!(is_levelled(&old, &artifact(4, 18.6, 6.6, None)));
+        assert!(is_levelled(&old, &artifact(2, 0.0, 3.1, None)));
+    }
+
+    #[test]
+    fn diff_sorts_artifacts_into_changes() {
+        let kept = artifact(20, 62.2, 10.1, Some("胡桃"));
+        let moved = artifact(20, 62.2, 14.0, Some("胡桃"));
+        let levelled = artifact(0, 9.3, 3.5, None);
+        let removed = artifact(8, 27.9, 7.0, None);
+
+        let mut moved_new = moved.clone();
+        moved_new.equip = Some(String::from("迪卢克"));
+        let levelled_new = artifact(4, 18.6, 7.4, None);
+        let added = artifact(0, 9.3, 3.9, None);
+
+        let old = vec![
+            kept.clone(),
+            moved.clone(),
+            levelled.clone(),
+            removed.clone(),
+        ];
+        let new = vec![added.clone(), levelled_new.clone(), moved_new.clone(), kept];
+        let result = diff(&old, &new);
+
+        assert_eq!(result.added, vec![added]);
+        assert_eq!(result.removed, vec![removed]);
+        assert_eq!(result.levelled.len(), 1);
+        assert_eq!(result.levelled[0].old, levelled);
+        assert_eq!(result.levelled[0].new, levelled_new);
+        assert_eq!(result.equip_changed.len(), 1);
+        assert_eq!(result.equip_changed[0].old, moved);
+        assert_eq!(result.equip_changed[0].new, moved_new);
+    }
+
+    #[test]
+    fn identical_artifacts_pair_up_in_order() {
+        let a = artifact(0, 9.3, 3.1, None);
+        let result = diff(&[a.clone(), a.clone()], &[a.clone(), a.clone(), a.clone()]);
+
+        assert_eq!(result.added, vec![a]);
+        assert!(result.removed.is_empty());
+        assert!(result.levelled.is_empty());
+    }
+}
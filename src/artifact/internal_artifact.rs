@@ -2,9 +2,9 @@ use regex::Regex;
 use std::hash::{Hash, Hasher};
 use edit_distance;
 use log::error;
-use strum_macros::Display;
+use strum_macros::{Display, EnumIter};
 
-#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[derive(Debug, Hash, Clone, PartialEq, Eq, EnumIter)]
 pub enum ArtifactStatName {
     HealingBonus,
     CriticalDamage,
@@ -27,7 +27,7 @@ pub enum ArtifactStatName {
     DendroBonus,
 }
 
-#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[derive(Debug, Hash, Clone, PartialEq, Eq, EnumIter)]
 pub enum ArtifactSlot {
     Flower,
     Feather,
@@ -36,8 +36,7 @@ pub enum ArtifactSlot {
     Head,
 }
 
-#[derive(Debug, Hash, Clone, PartialEq, Eq)]
-#[derive(Display)]
+#[derive(Debug, Hash, Clone, PartialEq, Eq, Display, EnumIter)]
 pub enum ArtifactSetName {
     ArchaicPetra,
     HeartOfDepth,
@@ -127,7 +126,38 @@ impl PartialEq for ArtifactStat {
 
 impl Eq for ArtifactStat {}
 
+// stat names as they appear on the panel
+pub const STAT_NAMES_CHS: &[&str] = &[
+    "治疗加成",
+    "暴击伤害",
+    "暴击率",
+    "攻击力",
+    "元素精通",
+    "元素充能效率",
+    "生命值",
+    "防御力",
+    "雷元素伤害加成",
+    "火元素伤害加成",
+    "水元素伤害加成",
+    "冰元素伤害加成",
+    "风元素伤害加成",
+    "岩元素伤害加成",
+    "草元素伤害加成",
+    "物理伤害加成",
+];
+
 impl ArtifactStatName {
+    // flat stats are stored as is, the others as fractions (0.466 for 46.6%)
+    pub fn is_percentage(&self) -> bool {
+        !matches!(
+            self,
+            ArtifactStatName::Atk
+                | ArtifactStatName::ElementalMastery
+                | ArtifactStatName::Hp
+                | ArtifactStatName::Def
+        )
+    }
+
     pub fn from_zh_cn(name: &str, is_percentage: bool) -> Option<ArtifactStatName> {
         match name {
             "治疗加成" => Some(ArtifactStatName::HealingBonus),
@@ -152,6 +182,17 @@ impl ArtifactStatName {
 }
 
 impl ArtifactStat {
+    // `value` as shown in game (46.6 for 46.6%), the result is bit-identical to
+    // `from_zh_cn_raw` so that imported and scanned stats hash the same
+    pub fn from_display(name: ArtifactStatName, value: f64) -> ArtifactStat {
+        let value = if name.is_percentage() {
+            (value * 10.0).round() / 10.0 / 100.0
+        } else {
+            value
+        };
+        ArtifactStat { name, value }
+    }
+
     // e.g "生命值+4,123", "暴击率+10%"
     pub fn from_zh_cn_raw(s: &str) -> Option<ArtifactStat> {
         let temp: Vec<&str> = s.split("+").collect();
@@ -160,10 +201,7 @@ impl ArtifactStat {
         }
 
         let is_percentage = temp[1].contains("%");
-        let stat_name = match ArtifactStatName::from_zh_cn(temp[0], is_percentage) {
-            Some(v) => v,
-            None => return None,
-        };
+        let stat_name = ArtifactStatName::from_zh_cn(temp[0], is_percentage)?;
 
         let re = Regex::new("[%,]").unwrap();
         let mut value = match re.replace_all(temp[1], "").parse::<f64>() {
@@ -184,49 +222,213 @@ impl ArtifactStat {
     }
 }
 
+// every artifact title, including the two spellings of 星罗圭璧之晷
+pub const ARTIFACT_NAMES_CHS: &[&str] = &[
+    "磐陀裂生之花",
+    "嵯峨群峰之翼",
+    "星罗圭壁之晷",
+    "星罗圭璧之晷",
+    "巉岩琢塑之樽",
+    "不动玄石之相",
+    "历经风雪的思念",
+    "摧冰而行的执望",
+    "冰雪故园的终期",
+    "遍结寒霜的傲骨",
+    "破冰踏雪的回音",
+    "染血的铁之心",
+    "染血的黑之羽",
+    "骑士染血之时",
+    "染血骑士之杯",
+    "染血的铁假面",
+    "魔女的炎之花",
+    "魔女常燃之羽",
+    "魔女破灭之时",
+    "魔女的心之火",
+    "焦灼的魔女帽",
+    "角斗士的留恋",
+    "角斗士的归宿",
+    "角斗士的希冀",
+    "角斗士的酣醉",
+    "角斗士的凯旋",
+    "饰金胸花",
+    "追忆之风",
+    "坚铜罗盘",
+    "沉波之盏",
+    "酒渍船帽",
+    "渡火者的决绝",
+    "渡火者的解脱",
+    "渡火者的煎熬",
+    "渡火者的醒悟",
+    "渡火者的智慧",
+    "远方的少女之心",
+    "少女飘摇的思念",
+    "少女苦短的良辰",
+    "少女片刻的闲暇",
+    "少女易逝的芳颜",
+    "宗室之花",
+    "宗室之翎",
+    "宗室时计",
+    "宗室银瓮",
+    "宗室面具",
+    "夏祭之花",
+    "夏祭终末",
+    "夏祭之刻",
+    "夏祭水玉",
+    "夏祭之面",
+    "平雷之心",
+    "平雷之羽",
+    "平雷之刻",
+    "平雷之器",
+    "平雷之冠",
+    "雷鸟的怜悯",
+    "雷灾的孑遗",
+    "雷霆的时计",
+    "降雷的凶兆",
+    "唤雷的头冠",
+    "野花记忆的绿野",
+    "猎人青翠的箭羽",
+    "翠绿猎人的笃定",
+    "翠绿猎人的容器",
+    "翠绿的猎人之冠",
+    "乐团的晨光",
+    "琴师的箭羽",
+    "终幕的时计",
+    "终末的时计",
+    "吟游者之壶",
+    "指挥的礼帽",
+    "战狂的蔷薇",
+    "战狂的翎羽",
+    "战狂的时计",
+    "战狂的骨杯",
+    "战狂的鬼面",
+    "勇士的勋章",
+    "勇士的期许",
+    "勇士的坚毅",
+    "勇士的壮行",
+    "勇士的冠冕",
+    "守护之花",
+    "守护徽印",
+    "守护座钟",
+    "守护之皿",
+    "守护束带",
+    "流放者之花",
+    "流放者之羽",
+    "流放者怀表",
+    "流放者之杯",
+    "流放者头冠",
+    "赌徒的胸花",
+    "赌徒的羽饰",
+    "赌徒的怀表",
+    "赌徒的骰盅",
+    "赌徒的耳环",
+    "教官的胸花",
+    "教官的羽饰",
+    "教官的怀表",
+    "教官的茶杯",
+    "教官的帽子",
+    "武人的红花",
+    "武人的羽饰",
+    "武人的水漏",
+    "武人的酒杯",
+    "武人的头巾",
+    "祭水礼冠",
+    "祭火礼冠",
+    "祭雷礼冠",
+    "祭冰礼冠",
+    "故人之心",
+    "归乡之羽",
+    "逐光之石",
+    "异国之盏",
+    "感别之冠",
+    "学士的书签",
+    "学士的羽笔",
+    "学士的时钟",
+    "学士的墨杯",
+    "学士的镜片",
+    "奇迹之花",
+    "奇迹之羽",
+    "奇迹之沙",
+    "奇迹之杯",
+    "奇迹耳坠",
+    "冒险家之花",
+    "冒险家尾羽",
+    "冒险家怀表",
+    "冒险家金杯",
+    "冒险家头带",
+    "幸运儿绿花",
+    "幸运儿鹰羽",
+    "幸运儿沙漏",
+    "幸运儿之杯",
+    "幸运儿银冠",
+    "游医的银莲",
+    "游医的枭羽",
+    "游医的怀钟",
+    "游医的药壶",
+    "游医的方巾",
+    "勋绩之花",
+    "昭武翎羽",
+    "金铜时晷",
+    "盟誓金爵",
+    "将帅兜鍪",
+    "无垢之花",
+    "贤医之羽",
+    "停摆之刻",
+    "超越之盏",
+    "嗤笑之面",
+    "明威之镡",
+    "切落之羽",
+    "雷云之笼",
+    "绯花之壶",
+    "华饰之兜",
+    "羁缠之花",
+    "思忆之矢",
+    "朝露之时",
+    "祈望之心",
+    "无常之面",
+    "荣花之期",
+    "华馆之羽",
+    "众生之谣",
+    "梦醒之瓢",
+    "形骸之笠",
+    "海染之花",
+    "渊宫之羽",
+    "离别之贝",
+    "真珠之笼",
+    "海祇之冠",
+    "生灵之华",
+    "阳辔之遗",
+    "潜光片羽",
+    "结契之刻",
+    "虺雷之姿",
+    "魂香之花",
+    "祝祀之凭",
+    "垂玉之叶",
+    "涌泉之盏",
+    "浮溯之珏",
+    "迷宫的游人",
+    "翠蔓的智者",
+    "贤智的定期",
+    "迷误者之灯",
+    "月桂的宝冠",
+    "梦中的铁花",
+    "裁断的翎羽",
+    "沉金的岁月",
+    "如蜜的终宴",
+    "沙王的投影",
+    "月女的华彩",
+    "谢落的筵席",
+    "凝结的时刻",
+    "守秘的魔瓶",
+    "紫晶的花冠",
+    "众王之都的开端",
+    "黄金邦国的结末",
+    "失落迷途的机芯",
+    "迷醉长梦的守护",
+    "流沙贵嗣的遗宝",
+];
+
 pub fn get_real_artifact_name_chs(raw: &str) -> Option<String> {
-    let all_artifact_chs = [
-        "磐陀裂生之花", "嵯峨群峰之翼", "星罗圭壁之晷", "星罗圭璧之晷", "巉岩琢塑之樽", "不动玄石之相",
-        "历经风雪的思念", "摧冰而行的执望", "冰雪故园的终期", "遍结寒霜的傲骨", "破冰踏雪的回音",
-        "染血的铁之心", "染血的黑之羽", "骑士染血之时", "染血骑士之杯", "染血的铁假面",
-        "魔女的炎之花", "魔女常燃之羽", "魔女破灭之时", "魔女的心之火", "焦灼的魔女帽",
-        "角斗士的留恋", "角斗士的归宿", "角斗士的希冀", "角斗士的酣醉", "角斗士的凯旋",
-        "饰金胸花", "追忆之风", "坚铜罗盘", "沉波之盏", "酒渍船帽",
-        "渡火者的决绝", "渡火者的解脱", "渡火者的煎熬", "渡火者的醒悟", "渡火者的智慧",
-        "远方的少女之心", "少女飘摇的思念", "少女苦短的良辰", "少女片刻的闲暇", "少女易逝的芳颜",
-        "宗室之花", "宗室之翎", "宗室时计", "宗室银瓮", "宗室面具",
-        "夏祭之花", "夏祭终末", "夏祭之刻", "夏祭水玉", "夏祭之面",
-        "平雷之心", "平雷之羽", "平雷之刻", "平雷之器", "平雷之冠",
-        "雷鸟的怜悯", "雷灾的孑遗", "雷霆的时计", "降雷的凶兆", "唤雷的头冠",
-        "野花记忆的绿野", "猎人青翠的箭羽", "翠绿猎人的笃定", "翠绿猎人的容器", "翠绿的猎人之冠",
-        "乐团的晨光", "琴师的箭羽", "终幕的时计", "终末的时计", "吟游者之壶", "指挥的礼帽",
-        "战狂的蔷薇", "战狂的翎羽", "战狂的时计", "战狂的骨杯", "战狂的鬼面",
-        "勇士的勋章", "勇士的期许", "勇士的坚毅", "勇士的壮行", "勇士的冠冕",
-        "守护之花", "守护徽印", "守护座钟", "守护之皿", "守护束带",
-        "流放者之花", "流放者之羽", "流放者怀表", "流放者之杯", "流放者头冠",
-        "赌徒的胸花", "赌徒的羽饰", "赌徒的怀表", "赌徒的骰盅", "赌徒的耳环",
-        "教官的胸花", "教官的羽饰", "教官的怀表", "教官的茶杯", "教官的帽子",
-        "武人的红花", "武人的羽饰", "武人的水漏", "武人的酒杯", "武人的头巾",
-        "祭水礼冠", "祭火礼冠", "祭雷礼冠", "祭冰礼冠",
-        "故人之心", "归乡之羽", "逐光之石", "异国之盏", "感别之冠",
-        "学士的书签", "学士的羽笔", "学士的时钟", "学士的墨杯", "学士的镜片",
-        "奇迹之花", "奇迹之羽", "奇迹之沙", "奇迹之杯", "奇迹耳坠",
-        "冒险家之花", "冒险家尾羽", "冒险家怀表", "冒险家金杯", "冒险家头带",
-        "幸运儿绿花", "幸运儿鹰羽", "幸运儿沙漏", "幸运儿之杯", "幸运儿银冠",
-        "游医的银莲", "游医的枭羽", "游医的怀钟", "游医的药壶", "游医的方巾",
-        "勋绩之花", "昭武翎羽", "金铜时晷", "盟誓金爵", "将帅兜鍪",
-        "无垢之花", "贤医之羽", "停摆之刻", "超越之盏", "嗤笑之面",
-        "明威之镡", "切落之羽", "雷云之笼", "绯花之壶", "华饰之兜",
-        "羁缠之花", "思忆之矢", "朝露之时", "祈望之心", "无常之面",
-        "荣花之期", "华馆之羽", "众生之谣", "梦醒之瓢", "形骸之笠",
-        "海染之花", "渊宫之羽", "离别之贝", "真珠之笼", "海祇之冠",
-        "生灵之华", "阳辔之遗", "潜光片羽", "结契之刻", "虺雷之姿",
-        "魂香之花", "祝祀之凭", "垂玉之叶", "涌泉之盏", "浮溯之珏",
-        "迷宫的游人", "翠蔓的智者", "贤智的定期", "迷误者之灯", "月桂的宝冠",
-        "梦中的铁花", "裁断的翎羽", "沉金的岁月", "如蜜的终宴", "沙王的投影",
-        "月女的华彩", "谢落的筵席", "凝结的时刻", "守秘的魔瓶", "紫晶的花冠",
-        "众王之都的开端", "黄金邦国的结末", "失落迷途的机芯", "迷醉长梦的守护", "流沙贵嗣的遗宝",
-    ];
+    let all_artifact_chs = ARTIFACT_NAMES_CHS;
 
     let mut min_index = 0;
     let mut min_dis = edit_distance::edit_distance(raw, all_artifact_chs[0]);
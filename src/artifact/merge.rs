@@ -0,0 +1,161 @@
+use std::collections::HashSet;
+
+use crate::artifact::internal_artifact::{ArtifactStat, InternalArtifact};
+
+// the fields that tell artifacts apart besides set, slot, rarity, level and substats.
+// GOOD and MingyuLab exports have no main stat value and no character name we can
+// read, so a field is only compared when the previous inventory has it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyFields {
+    pub main_value: bool,
+    pub equip: bool,
+}
+
+impl KeyFields {
+    pub fn of(artifacts: &[InternalArtifact]) -> KeyFields {
+        KeyFields {
+            main_value: artifacts.iter().all(|a| a.main_stat.value != 0.0),
+            equip: artifacts.iter().any(|a| a.equip.is_some()),
+        }
+    }
+}
+
+// what identifies an artifact across exports, the fields left out of `fields` are cleared
+pub fn match_key(artifact: &InternalArtifact, fields: KeyFields) -> InternalArtifact {
+    InternalArtifact {
+        main_stat: ArtifactStat {
+            name: artifact.main_stat.name.clone(),
+            value: if fields.main_value {
+                artifact.main_stat.value
+            } else {
+                0.0
+            },
+        },
+        equip: if fields.equip {
+            artifact.equip.clone()
+        } else {
+            None
+        },
+        ..artifact.clone()
+    }
+}
+
+pub fn key_set(artifacts: &[InternalArtifact], fields: KeyFields) -> HashSet<InternalArtifact> {
+    artifacts.iter().map(|a| match_key(a, fields)).collect()
+}
+
+// newly scanned artifacts first (the game lists newest first), then the previous
+// inventory; scanned ones replace their previous entry since they are more complete
+pub fn merge(previous: &[InternalArtifact], scanned: &[InternalArtifact]) -> Vec<InternalArtifact> {
+    let fields = KeyFields::of(previous);
+    let scanned_keys = key_set(scanned, fields);
+
+    let mut result: Vec<InternalArtifact> = scanned.to_vec();
+    for artifact in previous.iter() {
+        if !scanned_keys.contains(&match_key(artifact, fields)) {
+            result.push(artifact.clone());
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::internal_artifact::{ArtifactSetName, ArtifactSlot, ArtifactStatName};
+
+    fn artifact(level: u32, crit: f64, equip: Option<&str>) -> InternalArtifact {
+        InternalArtifact {
+            set_name: ArtifactSetName::GladiatorFinale,
+            slot: ArtifactSlot::Flower,
+            star: 5,
+            level,
+            main_stat: ArtifactStat {
+                name: ArtifactStatName::Hp,
+                value: 4780.0,
+            },
+            sub_stat_1: Some(ArtifactStat {
+                name: ArtifactStatName::Critical,
+                value: crit,
+            }),
+            sub_stat_2: None,
+            sub_stat_3: None,
+            sub_stat_4: None,
+            equip: equip.map(String::from),
+        }
+    }
+
+    // as read back from GOOD
+    fn without_value_and_equip(artifact: &InternalArtifact) -> InternalArtifact {
+        InternalArtifact {
+            main_stat: ArtifactStat {
+                name: artifact.main_stat.name.clone(),
+                value: 0.0,
+            },
+            equip: None,
+            ..artifact.clone()
+        }
+    }
+
+    #[test]
+    fn key_has_main_value_and_equip_when_the_inventory_does() {
+        let a = artifact(20, 0.039, Some("迪卢克"));
+        let b = artifact(20, 0.039, Some("刻晴"));
+        let fields = KeyFields::of(&[a.clone(), artifact(0, 0.031, None)]);
+        assert_eq!(
+            fields,
+            KeyFields {
+                main_value: true,
+                equip: true
+            }
+        );
+        assert_ne!(match_key(&a, fields), match_key(&b, fields));
+
+        let c = InternalArtifact {
+            main_stat: ArtifactStat {
+                name: ArtifactStatName::Hp,
+                value: 717.0,
+            },
+            ..a.clone()
+        };
+        assert_ne!(match_key(&a, fields), match_key(&c, fields));
+    }
+
+    #[test]
+    fn key_ignores_fields_the_inventory_lacks() {
+        let scanned = artifact(20, 0.039, Some("迪卢克"));
+        let previous = without_value_and_equip(&scanned);
+        let fields = KeyFields::of(std::slice::from_ref(&previous));
+        assert_eq!(
+            fields,
+            KeyFields {
+                main_value: false,
+                equip: false
+            }
+        );
+        assert_eq!(match_key(&scanned, fields), match_key(&previous, fields));
+    }
+
+    #[test]
+    fn merge_puts_scanned_first_and_replaces_their_previous_entries() {
+        let old = artifact(0, 0.031, None);
+        let kept = artifact(4, 0.066, None);
+        let new = artifact(8, 0.097, Some("迪卢克"));
+        let previous = vec![
+            without_value_and_equip(&kept),
+            without_value_and_equip(&old),
+        ];
+
+        let merged = merge(&previous, &[new.clone(), kept.clone()]);
+        assert_eq!(merged, vec![new, kept, previous[1].clone()]);
+    }
+
+    #[test]
+    fn merge_keeps_artifacts_equipped_on_different_characters() {
+        let a = artifact(20, 0.039, Some("迪卢克"));
+        let b = artifact(20, 0.039, Some("刻晴"));
+
+        let merged = merge(std::slice::from_ref(&a), std::slice::from_ref(&b));
+        assert_eq!(merged, vec![b, a]);
+    }
+}
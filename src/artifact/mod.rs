@@ -1 +1,5 @@
-pub mod internal_artifact;
\ No newline at end of file
+pub mod internal_artifact;
+pub mod merge;
+pub mod diff;
+pub mod validation;
+pub mod correction;
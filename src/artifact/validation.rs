@@ -0,0 +1,446 @@
+use crate::artifact::internal_artifact::{
+    ArtifactSlot, ArtifactStat, ArtifactStatName, InternalArtifact,
+};
+
+// the value as shown in game, 46.6 for 46.6%
+pub fn display_value(stat: &ArtifactStat) -> f64 {
+    if stat.name.is_percentage() {
+        stat.value * 100.0
+    } else {
+        stat.value
+    }
+}
+
+// the step the game rounds shown values to
+fn display_step(name: &ArtifactStatName) -> f64 {
+    if name.is_percentage() {
+        0.1
+    } else {
+        1.0
+    }
+}
+
+// the largest roll of a substat, None for stats that cannot be substats and
+// rarities below 3 stars
+pub fn max_roll(star: u32, name: &ArtifactStatName) -> Option<f64> {
+    use ArtifactStatName::*;
+
+    let rolls = match star {
+        5 => [
+            298.75, 19.45, 23.15, 5.83, 5.83, 7.29, 23.31, 6.48, 3.89, 7.77,
+        ],
+        4 => [
+            239.0, 15.56, 18.52, 4.66, 4.66, 5.83, 18.65, 5.18, 3.11, 6.22,
+        ],
+        3 => [
+            143.40, 9.34, 11.11, 3.50, 3.50, 4.37, 13.99, 3.89, 2.33, 4.66,
+        ],
+        _ => return None,
+    };
+    let i = match name {
+        Hp => 0,
+        Atk => 1,
+        Def => 2,
+        HpPercentage => 3,
+        AtkPercentage => 4,
+        DefPercentage => 5,
+        ElementalMastery => 6,
+        Recharge => 7,
+        Critical => 8,
+        CriticalDamage => 9,
+        _ => return None,
+    };
+    Some(rolls[i])
+}
+
+pub fn max_level(star: u32) -> u32 {
+    star * 4
+}
+
+// the main stat at level 0 and at the highest level, only known for 4 and 5 star
+fn main_stat_range(star: u32, name: &ArtifactStatName) -> Option<(f64, f64)> {
+    use ArtifactStatName::*;
+
+    let five = star == 5;
+    let range = match name {
+        Hp => {
+            if five {
+                (717.0, 4780.0)
+            } else {
+                (645.0, 3571.0)
+            }
+        }
+        Atk => {
+            if five {
+                (47.0, 311.0)
+            } else {
+                (42.0, 232.0)
+            }
+        }
+        HpPercentage | AtkPercentage | ElectroBonus | PyroBonus | HydroBonus | CryoBonus
+        | AnemoBonus | GeoBonus | DendroBonus => {
+            if five {
+                (7.0, 46.6)
+            } else {
+                (6.3, 34.8)
+            }
+        }
+        DefPercentage | PhysicalBonus => {
+            if five {
+                (8.7, 58.3)
+            } else {
+                (7.9, 43.5)
+            }
+        }
+        ElementalMastery => {
+            if five {
+                (28.0, 186.5)
+            } else {
+                (25.2, 139.3)
+            }
+        }
+        Recharge => {
+            if five {
+                (7.8, 51.8)
+            } else {
+                (7.0, 38.7)
+            }
+        }
+        Critical => {
+            if five {
+                (4.7, 31.1)
+            } else {
+                (4.2, 23.2)
+            }
+        }
+        CriticalDamage => {
+            if five {
+                (9.3, 62.2)
+            } else {
+                (8.4, 46.4)
+            }
+        }
+        HealingBonus => {
+            if five {
+                (5.4, 35.9)
+            } else {
+                (4.8, 26.8)
+            }
+        }
+        Def => return None,
+    };
+    match star {
+        4 | 5 => Some(range),
+        _ => None,
+    }
+}
+
+// the main stat grows by the same amount every level
+pub fn main_stat_value(star: u32, level: u32, name: &ArtifactStatName) -> Option<f64> {
+    let (base, max) = main_stat_range(star, name)?;
+    Some(base + (max - base) * level as f64 / max_level(star) as f64)
+}
+
+pub fn is_main_stat_valid(star: u32, level: u32, stat: &ArtifactStat) -> bool {
+    match main_stat_value(star, level, &stat.name) {
+        Some(expected) => {
+            let tolerance = display_step(&stat.name) + expected * 0.005;
+            (display_value(stat) - expected).abs() <= tolerance
+        }
+        None => false,
+    }
+}
+
+// the fewest and most rolls that add up to the substat, None when no sum of roll
+// tiers gives the shown value
+pub fn sub_stat_rolls(star: u32, stat: &ArtifactStat) -> Option<(u32, u32)> {
+    let max = max_roll(star, &stat.name)?;
+    // a roll is 0.7, 0.8, 0.9 or 1.0 times the largest one, so any sum of rolls is a
+    // multiple of a tenth of it
+    let step = max / 10.0;
+    let value = display_value(stat);
+    let k = (value / step).round();
+    // the shown value is rounded and the tiers are rounded in game too
+    let tolerance = display_step(&stat.name) / 2.0 + max * 0.01;
+    if k < 7.0 || (value - k * step).abs() > tolerance {
+        return None;
+    }
+
+    let k = k as u32;
+    // u32::div_ceil needs rust 1.73
+    #[allow(clippy::manual_div_ceil)]
+    let min = (k + 9) / 10;
+    let max = k / 7;
+    if min > max {
+        None
+    } else {
+        Some((min, max))
+    }
+}
+
+// whether one substat could have this value at `level`, it gets at most its first
+// roll and every upgrade. Rarities without roll tables accept anything
+pub fn is_sub_stat_valid(star: u32, level: u32, stat: &ArtifactStat) -> bool {
+    if !(3..=5).contains(&star) {
+        return true;
+    }
+    match sub_stat_rolls(star, stat) {
+        Some((min, _)) => min <= 1 + level / 4,
+        None => false,
+    }
+}
+
+// the slots that can have `name` as main stat
+pub fn main_stat_slots(name: &ArtifactStatName) -> &'static [ArtifactSlot] {
+    use ArtifactStatName::*;
+
+    match name {
+        Hp => &[ArtifactSlot::Flower],
+        Atk => &[ArtifactSlot::Feather],
+        Recharge => &[ArtifactSlot::Sand],
+        HpPercentage | AtkPercentage | DefPercentage | ElementalMastery => {
+            &[ArtifactSlot::Sand, ArtifactSlot::Goblet, ArtifactSlot::Head]
+        }
+        ElectroBonus | PyroBonus | HydroBonus | CryoBonus | AnemoBonus | GeoBonus | DendroBonus
+        | PhysicalBonus => &[ArtifactSlot::Goblet],
+        Critical | CriticalDamage | HealingBonus => &[ArtifactSlot::Head],
+        Def => &[],
+    }
+}
+
+pub struct SubStatRolls {
+    pub name: ArtifactStatName,
+    pub min: u32,
+    pub max: u32,
+}
+
+#[derive(Default)]
+pub struct Validation {
+    // narrowed down to what the total number of rolls allows
+    pub rolls: Vec<SubStatRolls>,
+    pub problems: Vec<String>,
+}
+
+impl Validation {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+// an artifact starts with star - 2 or star - 1 substat rolls and gets one more every
+// 4 levels, which adds a substat until there are 4. Only 3 to 5 star artifacts are
+// checked, and the main stat only for 4 and 5 star
+pub fn validate(artifact: &InternalArtifact) -> Validation {
+    let mut validation = Validation::default();
+    let star = artifact.star;
+    if !(3..=5).contains(&star) {
+        return validation;
+    }
+    let problems = &mut validation.problems;
+
+    if artifact.level > max_level(star) {
+        problems.push(format!(
+            "level {} above {}",
+            artifact.level,
+            max_level(star)
+        ));
+        return validation;
+    }
+
+    let main_stat = &artifact.main_stat;
+    if star >= 4 && !is_main_stat_valid(star, artifact.level, main_stat) {
+        match main_stat_value(star, artifact.level, &main_stat.name) {
+            Some(expected) => problems.push(format!(
+                "main stat {:?} {} does not match level {}, expected {:.1}",
+                main_stat.name,
+                display_value(main_stat),
+                artifact.level,
+                expected
+            )),
+            None => problems.push(format!("{:?} cannot be a main stat", main_stat.name)),
+        }
+    }
+
+    let subs: Vec<&ArtifactStat> = [
+        &artifact.sub_stat_1,
+        &artifact.sub_stat_2,
+        &artifact.sub_stat_3,
+        &artifact.sub_stat_4,
+    ]
+    .iter()
+    .filter_map(|s| s.as_ref())
+    .collect();
+
+    let upgrades = artifact.level / 4;
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for (i, sub) in subs.iter().enumerate() {
+        if sub.name == main_stat.name || subs[..i].iter().any(|s| s.name == sub.name) {
+            problems.push(format!("substat {:?} appears twice", sub.name));
+            continue;
+        }
+        match sub_stat_rolls(star, sub) {
+            // a substat gets at most its first roll and every upgrade
+            Some((min, _)) if min > 1 + upgrades => problems.push(format!(
+                "substat {:?} {} needs at least {} rolls",
+                sub.name,
+                display_value(sub),
+                min
+            )),
+            Some(range) => ranges.push(range),
+            None => problems.push(format!(
+                "substat {:?} {} is no sum of rolls",
+                sub.name,
+                display_value(sub)
+            )),
+        }
+    }
+    if !problems.is_empty() {
+        return validation;
+    }
+
+    let lowest: u32 = ranges.iter().map(|r| r.0).sum();
+    let highest: u32 = ranges.iter().map(|r| r.1).sum();
+    let total = (star - 2..=star - 1)
+        .map(|initial| initial + upgrades)
+        .find(|&total| subs.len() as u32 == total.min(4) && lowest <= total && total <= highest);
+    let total = match total {
+        Some(v) => v,
+        None => {
+            problems.push(format!(
+                "{} substats with {} to {} rolls do not fit level {}",
+                subs.len(),
+                lowest,
+                highest,
+                artifact.level
+            ));
+            return validation;
+        }
+    };
+
+    // what is left for one substat after the others took their fewest or most rolls
+    for (sub, &(min, max)) in subs.iter().zip(ranges.iter()) {
+        validation.rolls.push(SubStatRolls {
+            name: sub.name.clone(),
+            min: min.max(total.saturating_sub(highest - max)),
+            max: max.min(total - (lowest - min)),
+        });
+    }
+    validation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::internal_artifact::ArtifactSetName;
+    use ArtifactStatName::*;
+
+    fn stat(name: ArtifactStatName, value: f64) -> ArtifactStat {
+        ArtifactStat { name, value }
+    }
+
+    fn artifact(level: u32, main_value: f64, subs: Vec<ArtifactStat>) -> InternalArtifact {
+        let mut subs = subs.into_iter();
+        InternalArtifact {
+            set_name: ArtifactSetName::GladiatorFinale,
+            slot: ArtifactSlot::Flower,
+            star: 5,
+            level,
+            main_stat: stat(Hp, main_value),
+            sub_stat_1: subs.next(),
+            sub_stat_2: subs.next(),
+            sub_stat_3: subs.next(),
+            sub_stat_4: subs.next(),
+            equip: None,
+        }
+    }
+
+    #[test]
+    fn rolls_of_a_substat() {
+        // one roll of each tier
+        assert_eq!(sub_stat_rolls(5, &stat(Critical, 0.039)), Some((1, 1)));
+        assert_eq!(sub_stat_rolls(5, &stat(Critical, 0.027)), Some((1, 1)));
+        // 3.5 + 3.9 + 3.1, rounded in game
+        assert_eq!(sub_stat_rolls(5, &stat(Critical, 0.105)), Some((3, 3)));
+        // 6 top rolls or 8 low ones
+        assert_eq!(sub_stat_rolls(5, &stat(Critical, 0.233)), Some((6, 8)));
+        assert_eq!(sub_stat_rolls(4, &stat(Atk, 16.0)), Some((1, 1)));
+
+        // below the lowest roll, between two sums, or not a substat
+        assert_eq!(sub_stat_rolls(5, &stat(Critical, 0.01)), None);
+        assert_eq!(sub_stat_rolls(5, &stat(Critical, 0.049)), None);
+        assert_eq!(sub_stat_rolls(5, &stat(PyroBonus, 0.05)), None);
+        assert_eq!(sub_stat_rolls(2, &stat(Critical, 0.039)), None);
+    }
+
+    #[test]
+    fn a_five_star_at_twenty_has_nine_rolls() {
+        let a = artifact(
+            20,
+            4780.0,
+            vec![
+                stat(Critical, 0.233),
+                stat(CriticalDamage, 0.078),
+                stat(AtkPercentage, 0.058),
+                stat(Recharge, 0.065),
+            ],
+        );
+        let validation = validate(&a);
+        assert!(validation.is_valid(), "{:?}", validation.problems);
+        // 9 rolls leave exactly 6 for the crit rate
+        let rolls: Vec<(u32, u32)> = validation.rolls.iter().map(|r| (r.min, r.max)).collect();
+        assert_eq!(rolls, vec![(6, 6), (1, 1), (1, 1), (1, 1)]);
+
+        // 8 crit rate rolls would need 11
+        let mut a = a;
+        a.sub_stat_1 = Some(stat(Critical, 0.311));
+        assert!(!validate(&a).is_valid());
+    }
+
+    #[test]
+    fn substat_count_has_to_fit_the_level() {
+        let three = vec![
+            stat(Critical, 0.039),
+            stat(CriticalDamage, 0.078),
+            stat(AtkPercentage, 0.058),
+        ];
+        assert!(validate(&artifact(0, 717.0, three.clone())).is_valid());
+        // a fourth line is added at +4
+        assert!(!validate(&artifact(4, 1530.0, three)).is_valid());
+        // a +0 5 star starts with at most 4 rolls
+        let two = vec![stat(Critical, 0.078), stat(CriticalDamage, 0.078)];
+        assert!(!validate(&artifact(0, 717.0, two)).is_valid());
+    }
+
+    #[test]
+    fn main_stat_has_to_match_the_level() {
+        let subs = vec![
+            stat(Critical, 0.039),
+            stat(CriticalDamage, 0.078),
+            stat(AtkPercentage, 0.058),
+            stat(Recharge, 0.065),
+        ];
+        assert!(validate(&artifact(0, 717.0, subs.clone())).is_valid());
+        assert!(!validate(&artifact(0, 4780.0, subs.clone())).is_valid());
+        assert_eq!(main_stat_value(5, 20, &Hp), Some(4780.0));
+        assert!(is_main_stat_valid(4, 16, &stat(AtkPercentage, 0.348)));
+        assert!(!is_main_stat_valid(5, 20, &stat(Def, 100.0)));
+
+        let mut a = artifact(24, 4780.0, subs);
+        assert!(!validate(&a).is_valid());
+        a.level = 20;
+        a.sub_stat_4 = Some(stat(Critical, 0.039));
+        let problems = validate(&a).problems;
+        assert_eq!(
+            problems,
+            vec![String::from("substat Critical appears twice")]
+        );
+    }
+
+    #[test]
+    fn other_rarities_are_not_checked() {
+        let mut a = artifact(0, 1.0, vec![stat(Critical, 0.5)]);
+        a.star = 2;
+        assert!(validate(&a).is_valid());
+        assert!(is_sub_stat_valid(1, 0, &stat(Critical, 0.5)));
+    }
+}
@@ -0,0 +1,41 @@
+use image::RgbImage;
+
+use crate::capture::{crop_bgra, pixel_color, CaptureBackend};
+use crate::common::color::Color;
+use crate::common::PixelRect;
+
+// treats a saved screenshot as the whole screen
+pub struct ImageFileCapture {
+    image: RgbImage,
+}
+
+impl ImageFileCapture {
+    pub fn new(path: &str) -> Result<ImageFileCapture, String> {
+        match image::open(path) {
+            Ok(v) => Ok(ImageFileCapture { image: v.to_rgb8() }),
+            Err(e) => Err(format!("cannot open {}: {}", path, e)),
+        }
+    }
+
+    pub fn from_image(image: RgbImage) -> ImageFileCapture {
+        ImageFileCapture { image }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+}
+
+impl CaptureBackend for ImageFileCapture {
+    fn capture(&self, rect: &PixelRect) -> Result<Vec<u8>, String> {
+        crop_bgra(&self.image, rect)
+    }
+
+    fn get_color(&self, x: u32, y: u32) -> Result<Color, String> {
+        pixel_color(&self.image, x, y)
+    }
+}
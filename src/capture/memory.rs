@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use image::RgbImage;
+
+use crate::capture::{crop_bgra, pixel_color, CaptureBackend};
+use crate::common::color::Color;
+use crate::common::PixelRect;
+
+// an in-memory screen for fixtures, clones share the same frame
+// so the frame can be swapped while a scanner owns another clone
+#[derive(Clone)]
+pub struct MemoryCapture {
+    frame: Arc<Mutex<RgbImage>>,
+    capture_count: Arc<AtomicUsize>,
+}
+
+impl MemoryCapture {
+    pub fn new(frame: RgbImage) -> MemoryCapture {
+        MemoryCapture {
+            frame: Arc::new(Mutex::new(frame)),
+            capture_count: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn set_frame(&self, frame: RgbImage) {
+        *self.frame.lock().unwrap() = frame;
+    }
+
+    pub fn frame(&self) -> RgbImage {
+        self.frame.lock().unwrap().clone()
+    }
+
+    // number of `capture` and `get_color` calls so far
+    pub fn capture_count(&self) -> usize {
+        self.capture_count.load(Ordering::SeqCst)
+    }
+}
+
+impl CaptureBackend for MemoryCapture {
+    fn capture(&self, rect: &PixelRect) -> Result<Vec<u8>, String> {
+        self.capture_count.fetch_add(1, Ordering::SeqCst);
+        crop_bgra(&self.frame.lock().unwrap(), rect)
+    }
+
+    fn get_color(&self, x: u32, y: u32) -> Result<Color, String> {
+        self.capture_count.fetch_add(1, Ordering::SeqCst);
+        pixel_color(&self.frame.lock().unwrap(), x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, (x + y) as u8]))
+    }
+
+    fn rgb(color: Color) -> (u8, u8, u8) {
+        (color.0, color.1, color.2)
+    }
+
+    #[test]
+    fn capture_is_bgra_of_the_rect() {
+        let capture = MemoryCapture::new(gradient(8, 6));
+        let rect = PixelRect {
+            left: 2,
+            top: 3,
+            width: 3,
+            height: 2,
+        };
+        let buffer = capture.capture(&rect).unwrap();
+        assert_eq!(buffer.len(), 3 * 2 * 4);
+        // second row, third pixel is (4, 4)
+        let offset = (3 + 2) * 4;
+        assert_eq!(&buffer[offset..offset + 4], &[8, 4, 4, 255]);
+
+        let image = capture.capture_image(&rect).unwrap();
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(*image.get_pixel(2, 1), Rgb([4, 4, 8]));
+    }
+
+    #[test]
+    fn get_color_and_bounds() {
+        let capture = MemoryCapture::new(gradient(8, 6));
+        assert_eq!(rgb(capture.get_color(7, 5).unwrap()), (7, 5, 12));
+        assert!(capture.get_color(8, 0).is_err());
+        let rect = PixelRect {
+            left: 6,
+            top: 0,
+            width: 3,
+            height: 1,
+        };
+        assert!(capture.capture(&rect).is_err());
+        assert_eq!(capture.capture_count(), 3);
+    }
+
+    #[test]
+    fn clones_share_the_frame() {
+        let capture = MemoryCapture::new(gradient(4, 4));
+        let other = capture.clone();
+        capture.set_frame(RgbImage::from_pixel(4, 4, Rgb([1, 2, 3])));
+        assert_eq!(rgb(other.get_color(0, 0).unwrap()), (1, 2, 3));
+        assert_eq!(other.frame().dimensions(), (4, 4));
+        assert_eq!(capture.capture_count(), 1);
+    }
+}
@@ -3,63 +3,83 @@ use image::{Rgb, RgbImage};
 use crate::common::color::Color;
 use crate::common::PixelRect;
 
-/// retures Ok(buf) on success
-/// buf contains pixels in [b:u8, g:u8, r:u8, a:u8] format, as an `[[i32;width];height]`.
-pub fn capture_absolute(
-    PixelRect {
-        left,
-        top,
-        width,
-        height,
-    }: &PixelRect,
-) -> Result<Vec<u8>, String> {
-    let screen = screenshots::Screen::all().ok_or("cannot get DisplayInfo")?[0];
-    let (mut buffer, is_bgra) = screen
-        .capture_area(*left, *top, *width as u32, *height as u32)
-        .ok_or("capture failed")?;
+pub mod image_file;
+pub mod memory;
+pub mod screenshots;
 
-    if !is_bgra {
-        for chunk in buffer.chunks_mut(4) {
-            let temp = chunk[0];
-            chunk[0] = chunk[2];
-            chunk[2] = temp;
+pub use self::image_file::ImageFileCapture;
+pub use self::memory::MemoryCapture;
+pub use self::screenshots::ScreenshotsCapture;
+
+/// Where the pixels come from. The scanner only talks to the screen through this trait,
+/// so a scan can be driven by a live screen, a saved screenshot or an in-memory fixture.
+pub trait CaptureBackend: Send {
+    /// retures Ok(buf) on success
+    /// buf contains pixels in [b:u8, g:u8, r:u8, a:u8] format, as an `[[i32;width];height]`.
+    fn capture(&self, rect: &PixelRect) -> Result<Vec<u8>, String>;
+
+    fn capture_image(&self, rect: &PixelRect) -> Result<RgbImage, String> {
+        let buffer = self.capture(rect)?;
+        let width = rect.width as u32;
+        Ok(RgbImage::from_fn(width, rect.height as u32, |x, y| {
+            let offset = ((y * width + x) * 4) as usize;
+            Rgb([buffer[offset + 2], buffer[offset + 1], buffer[offset]])
+        }))
+    }
+
+    fn get_color(&self, x: u32, y: u32) -> Result<Color, String> {
+        let im = self.capture(&PixelRect {
+            left: x as i32,
+            top: y as i32,
+            width: 1,
+            height: 1,
+        })?;
+        Ok(Color::from(im[2], im[1], im[0]))
+    }
+}
+
+// crop `rect` out of an rgb image into the bgra layout of `CaptureBackend::capture`
+pub(crate) fn crop_bgra(img: &RgbImage, rect: &PixelRect) -> Result<Vec<u8>, String> {
+    if rect.left < 0
+        || rect.top < 0
+        || rect.width < 0
+        || rect.height < 0
+        || (rect.left + rect.width) as u32 > img.width()
+        || (rect.top + rect.height) as u32 > img.height()
+    {
+        return Err(format!("capture {:?} out of bounds", rect));
+    }
+
+    let mut buffer: Vec<u8> = Vec::with_capacity((rect.width * rect.height * 4) as usize);
+    for y in rect.top..rect.top + rect.height {
+        for x in rect.left..rect.left + rect.width {
+            let p = img.get_pixel(x as u32, y as u32);
+            buffer.push(p.0[2]);
+            buffer.push(p.0[1]);
+            buffer.push(p.0[0]);
+            buffer.push(255);
         }
     }
 
     Ok(buffer)
 }
 
-pub fn capture_absolute_image(
-    PixelRect {
-        left,
-        top,
-        width,
-        height,
-    }: &PixelRect,
-) -> Result<image::RgbImage, String> {
-    // simply use the first screen.
-    // todo: multi-screen support
-    let screen = screenshots::Screen::all().ok_or("cannot get DisplayInfo")?[0];
-    let (buffer, is_bgra) = screen
-        .capture_area(*left, *top, *width as u32, *height as u32)
-        .ok_or("capture failed")?;
-    Ok(RgbImage::from_fn(*width as u32, *height as u32, |x, y| {
-        let offset = (y * (*width as u32) + x) as usize;
-        if is_bgra {
-            Rgb([buffer[offset + 2], buffer[offset + 1], buffer[offset]])
-        } else {
-            Rgb([buffer[offset], buffer[offset + 1], buffer[offset + 2]])
-        }
-    }))
+pub(crate) fn pixel_color(img: &RgbImage, x: u32, y: u32) -> Result<Color, String> {
+    if x >= img.width() || y >= img.height() {
+        return Err(format!("pixel ({}, {}) out of bounds", x, y));
+    }
+    let p = img.get_pixel(x, y);
+    Ok(Color::from(p.0[0], p.0[1], p.0[2]))
+}
+
+pub fn capture_absolute(rect: &PixelRect) -> Result<Vec<u8>, String> {
+    ScreenshotsCapture.capture(rect)
+}
+
+pub fn capture_absolute_image(rect: &PixelRect) -> Result<image::RgbImage, String> {
+    ScreenshotsCapture.capture_image(rect)
 }
 
-pub fn get_color(x: u32, y: u32) -> Color {
-    let im = capture_absolute(&PixelRect {
-        left: x as i32,
-        top: y as i32,
-        width: 1,
-        height: 1,
-    })
-    .unwrap();
-    Color::from(im[2], im[1], im[0])
+pub fn get_color(x: u32, y: u32) -> Result<Color, String> {
+    ScreenshotsCapture.get_color(x, y)
 }
@@ -0,0 +1,32 @@
+use crate::capture::CaptureBackend;
+use crate::common::PixelRect;
+
+// captures the live screen through screenshots-rs
+pub struct ScreenshotsCapture;
+
+impl CaptureBackend for ScreenshotsCapture {
+    fn capture(
+        &self,
+        PixelRect {
+            left,
+            top,
+            width,
+            height,
+        }: &PixelRect,
+    ) -> Result<Vec<u8>, String> {
+        // simply use the first screen.
+        // todo: multi-screen support
+        let screen = screenshots::Screen::all().ok_or("cannot get DisplayInfo")?[0];
+        let (mut buffer, is_bgra) = screen
+            .capture_area(*left, *top, *width as u32, *height as u32)
+            .ok_or("capture failed")?;
+
+        if !is_bgra {
+            for chunk in buffer.chunks_mut(4) {
+                chunk.swap(0, 2);
+            }
+        }
+
+        Ok(buffer)
+    }
+}
@@ -0,0 +1,50 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum YasError {
+    WindowNotFound(String),
+    UnsupportedResolution { width: i32, height: i32 },
+    Capture(String),
+    ModelLoad(String),
+    // scrolling the backpack did not land on the next row in time
+    Scroll(String),
+    // a panel could not be recognised at all, as opposed to a misread
+    Recognition(String),
+    // a layout file that does not parse or has no size
+    Layout(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for YasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            YasError::WindowNotFound(s) => write!(f, "未找到原神窗口：{}", s),
+            YasError::UnsupportedResolution { width, height } => {
+                write!(f, "不支持的分辨率：{}x{}", width, height)
+            }
+            YasError::Capture(s) => write!(f, "截图失败：{}", s),
+            YasError::ModelLoad(s) => write!(f, "模型加载失败：{}", s),
+            YasError::Scroll(s) => write!(f, "翻页出现问题：{}", s),
+            YasError::Recognition(s) => write!(f, "识别出现问题：{}", s),
+            YasError::Layout(s) => write!(f, "布局文件有误：{}", s),
+            YasError::Io(e) => write!(f, "IO错误：{}", e),
+        }
+    }
+}
+
+impl Error for YasError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            YasError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for YasError {
+    fn from(e: io::Error) -> YasError {
+        YasError::Io(e)
+    }
+}
@@ -1,15 +1,19 @@
-use crate::capture;
+use crate::capture::CaptureBackend;
 use crate::inference::pre_process::{pre_process, to_gray, raw_to_img, uint8_raw_to_img};
 use crate::info::info::ScanInfo;
 use image::{GrayImage, ImageBuffer, RgbImage};
-use crate::capture::capture_absolute;
+use std::path::Path;
 use std::time::SystemTime;
 use log::{info};
+use serde::{Deserialize, Serialize};
 
 pub mod utils;
 pub mod buffer;
 pub mod color;
 pub mod character_name;
+pub mod error;
+#[cfg(test)]
+pub mod temp_path;
 
 #[derive(Debug)]
 pub struct PixelRect {
@@ -28,7 +32,7 @@ impl PixelRect {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PixelRectBound {
     pub left: i32,
     pub top: i32,
@@ -37,7 +41,7 @@ pub struct PixelRectBound {
 }
 
 impl PixelRectBound {
-    pub fn capture_absolute(&self) -> Result<RawImage, String> {
+    pub fn capture_absolute(&self, backend: &dyn CaptureBackend) -> Result<RawImage, String> {
         let w = self.right - self.left;
         let h = self.bottom - self.top;
         let rect = PixelRect {
@@ -46,7 +50,7 @@ impl PixelRectBound {
             width: w,
             height: h,
         };
-        let raw_u8 = capture::capture_absolute(&rect).unwrap();
+        let raw_u8 = backend.capture(&rect)?;
         let raw_gray = to_gray(raw_u8, w as u32, h as u32);
         let raw_after_pp = pre_process(raw_gray);
 
@@ -56,17 +60,21 @@ impl PixelRectBound {
         }
     }
 
-    pub fn capture_relative(&self, info: &ScanInfo) -> Result<RawImage, String> {
+    pub fn capture_relative(
+        &self,
+        info: &ScanInfo,
+        backend: &dyn CaptureBackend,
+    ) -> Result<RawImage, String> {
         let w = self.right - self.left;
         let h = self.bottom - self.top;
         let rect = PixelRect {
-            left: self.left + info.left as i32,
-            top: self.top + info.top as i32,
+            left: self.left + info.left,
+            top: self.top + info.top,
             width: w,
             height: h,
         };
         let now = SystemTime::now();
-        let raw_u8 = capture::capture_absolute(&rect).unwrap();
+        let raw_u8 = backend.capture(&rect)?;
         info!("capture raw time: {}ms", now.elapsed().unwrap().as_millis());
         let raw_gray = to_gray(raw_u8, w as u32, h as u32);
         let raw_after_pp = pre_process(raw_gray);
@@ -78,20 +86,25 @@ impl PixelRectBound {
         }
     }
 
-    pub fn capture_relative_image(&self, info: &ScanInfo) -> Result<RgbImage, String> {
+    pub fn capture_relative_image(
+        &self,
+        info: &ScanInfo,
+        backend: &dyn CaptureBackend,
+    ) -> Result<RgbImage, String> {
         let w = self.right - self.left;
         let h = self.bottom - self.top;
         let rect = PixelRect {
-            left: self.left + info.left as i32,
-            top: self.top + info.top as i32,
+            left: self.left + info.left,
+            top: self.top + info.top,
             width: w,
             height: h,
         };
 
-        capture::capture_absolute_image(&rect)
+        backend.capture_image(&rect)
     }
 }
 
+#[derive(Default)]
 pub struct RawImage {
     pub data: Vec<f32>,
     pub w: u32,
@@ -106,16 +119,16 @@ pub struct RawCaptureImage {
 
 impl RawImage {
     pub fn to_gray_image(&self) -> GrayImage {
-        raw_to_img(&self)
+        raw_to_img(self)
     }
 
     pub fn grayscale_to_gray_image(&self) -> GrayImage {
-        uint8_raw_to_img(&self)
+        uint8_raw_to_img(self)
     }
 }
 
 impl RawCaptureImage {
-    pub fn save(&self, path: &str) {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
         let width = self.w;
         let height = self.h;
         let data = &self.data;
@@ -131,39 +144,99 @@ impl RawCaptureImage {
             // image::Luma([pixel])
         });
 
-        img.save(path);
+        img.save(path)
+            .map_err(|e| format!("cannot save {}: {}", path.display(), e))
+    }
+
+    // reverse of `save`, pixels are stored back as [b, g, r, a]
+    pub fn load(path: &Path) -> Result<RawCaptureImage, String> {
+        let img = match image::open(path) {
+            Ok(v) => v.to_rgb8(),
+            Err(e) => return Err(format!("cannot open {}: {}", path.display(), e)),
+        };
+        let (w, h) = img.dimensions();
+
+        let mut data: Vec<u8> = Vec::with_capacity((w * h * 4) as usize);
+        for pixel in img.pixels() {
+            data.push(pixel.0[2]);
+            data.push(pixel.0[1]);
+            data.push(pixel.0[0]);
+            data.push(255);
+        }
+
+        Ok(RawCaptureImage { data, w, h })
     }
 
     pub fn crop_to_raw_img(&self, rect: &PixelRect) -> RawImage {
-        // let now = SystemTime::now();
-        let vol = rect.width * rect.height;
-        let mut data = vec![0.0; vol as usize];
+        let mut im = RawImage::default();
+        self.crop_to_raw_img_into(rect, &mut im);
+        im
+    }
+
+    // like `crop_to_raw_img`, reusing the buffer of `im`
+    pub fn crop_to_raw_img_into(&self, rect: &PixelRect, im: &mut RawImage) {
+        let width = rect.width as usize;
+        im.data.clear();
+        for y in rect.top..rect.top + rect.height {
+            let start = ((y * self.w as i32 + rect.left) * 4) as usize;
+            let row = &self.data[start..start + width * 4];
+            im.data.extend(row.chunks_exact(4).map(|p| {
+                let (b, g, r) = (p[0], p[1], p[2]);
+                r as f32 * 0.2989 + g as f32 * 0.5870 + b as f32 * 0.1140
+            }));
+        }
+        im.w = rect.width as u32;
+        im.h = rect.height as u32;
+    }
+}
+
+// pub struct
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_to_raw_img_is_unchanged() {
+        let (w, h) = (23_u32, 11_u32);
+        let capture = RawCaptureImage {
+            data: (0..w * h * 4).map(|i| (i * 37 % 251) as u8).collect(),
+            w,
+            h,
+        };
+        let rect = PixelRect {
+            left: 3,
+            top: 2,
+            width: 15,
+            height: 7,
+        };
+
+        // the column-major loop it replaced
+        let mut expected = vec![0.0; (rect.width * rect.height) as usize];
         for i in rect.left..rect.left + rect.width {
             for j in rect.top..rect.top + rect.height {
-                let x = i;
-                let y = j;
-                let b: u8 = self.data[((y * self.w as i32 + x) * 4) as usize];
-                let g: u8 = self.data[((y * self.w as i32 + x) * 4 + 1) as usize];
-                let r: u8 = self.data[((y * self.w as i32 + x) * 4 + 2) as usize];
-
+                let index = ((j * w as i32 + i) * 4) as usize;
+                let (b, g, r) = (
+                    capture.data[index],
+                    capture.data[index + 1],
+                    capture.data[index + 2],
+                );
                 let gray = r as f32 * 0.2989 + g as f32 * 0.5870 + b as f32 * 0.1140;
-                let new_index = ((j - rect.top) * rect.width + i - rect.left) as usize;
-                data[new_index] = gray;
+                expected[((j - rect.top) * rect.width + i - rect.left) as usize] = gray;
             }
         }
 
-        let im = RawImage {
-            data,
-            w: rect.width as u32,
-            h: rect.height as u32,
-        };
-        // let im = pre_process(im);
-        // No preprocess!
+        let im = capture.crop_to_raw_img(&rect);
+        assert_eq!((im.w, im.h), (15, 7));
+        assert_eq!(im.data, expected);
 
-        // info!("preprocess time: {}ms", now.elapsed().unwrap().as_millis());
-        // im.to_gray_image().save("test.png");
-        im
+        // a reused buffer of another size gives the same
+        let mut reused = RawImage {
+            data: vec![1.0; 1000],
+            w: 40,
+            h: 25,
+        };
+        capture.crop_to_raw_img_into(&rect, &mut reused);
+        assert_eq!(reused.data, expected);
     }
 }
-
-// pub struct
\ No newline at end of file
@@ -0,0 +1,66 @@
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+// `yas-<name>-<pid>` in the system temp dir for a test, the file or directory there
+// is removed when this is dropped, so a failing assert does not leave it behind
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    // whatever an earlier run that was killed left at the path is removed first
+    pub fn new(name: &str) -> TempPath {
+        let path = std::env::temp_dir().join(format!("yas-{}-{}", name, std::process::id()));
+        let temp = TempPath(path);
+        temp.remove();
+        temp
+    }
+
+    fn remove(&self) {
+        if self.0.is_dir() {
+            fs::remove_dir_all(&self.0).ok();
+        } else {
+            fs::remove_file(&self.0).ok();
+        }
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropping_removes_the_file_or_directory() {
+        let file = TempPath::new("temp-file");
+        fs::write(&file, "x").unwrap();
+        let path = file.to_path_buf();
+        drop(file);
+        assert!(!path.exists());
+
+        let dir = TempPath::new("temp-dir");
+        fs::create_dir_all(dir.join("inner")).unwrap();
+        fs::write(dir.join("inner/file"), "x").unwrap();
+        let path = dir.to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
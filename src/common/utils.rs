@@ -4,6 +4,8 @@ use std::process;
 use std::time::Duration;
 use std::{thread, time};
 
+use crate::common::error::YasError;
+use crate::common::PixelRect;
 use crate::dto::GithubTag;
 use log::error;
 use reqwest::blocking::Client;
@@ -20,14 +22,13 @@ pub fn sleep(ms: u32) {
 }
 
 pub fn read_file_to_string(path: String) -> String {
-    let content = fs::read_to_string(path).unwrap();
-    content
+    fs::read_to_string(path).unwrap()
 }
 
 pub fn error_and_quit(msg: &str) -> ! {
     error!("{}, 按Enter退出", msg);
     let mut s: String = String::new();
-    stdin().read_line(&mut s);
+    let _ = stdin().read_line(&mut s);
     process::exit(0);
 }
 
@@ -36,6 +37,47 @@ pub fn is_rmb_down() -> bool {
     false
 }
 
+#[cfg(target_os = "linux")]
+fn run_sh(args: &str) -> Result<String, YasError> {
+    let output = process::Command::new("sh").arg("-c").arg(args).output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// asks the user to click the game window, returns its rect and whether it is cloud genshin
+#[cfg(target_os = "linux")]
+pub fn find_gi_window() -> Result<(PixelRect, bool), YasError> {
+    let window_id = run_sh(r#" xwininfo|grep "Window id"|cut -d " " -f 4 "#)?;
+    let window_id = window_id.trim_end_matches("\n");
+    if window_id.is_empty() {
+        return Err(YasError::WindowNotFound(String::from("xwininfo未返回窗口")));
+    }
+
+    let position_size = run_sh(&format!(
+        r#" xwininfo -id {window_id}|cut -f 2 -d :|tr -cd "0-9\n"|grep -v "^$"|sed -n "1,2p;5,6p" "#
+    ))?;
+
+    let values = position_size
+        .split("\n")
+        .take(4)
+        .map(|s| s.parse::<i32>())
+        .collect::<Result<Vec<i32>, _>>()
+        .map_err(|e| YasError::WindowNotFound(format!("无法解析xwininfo输出：{}", e)))?;
+    if values.len() < 4 {
+        return Err(YasError::WindowNotFound(String::from(
+            "无法解析xwininfo输出",
+        )));
+    }
+
+    let rect = PixelRect {
+        left: values[0],
+        top: values[1],
+        width: values[2],
+        height: values[3],
+    };
+    // todo: detect cloud genshin by title
+    Ok((rect, false))
+}
+
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub fn check_update() -> Option<String> {
@@ -50,14 +92,14 @@ pub fn check_update() -> Option<String> {
         .json::<Vec<GithubTag>>()
         .ok()?;
 
-    let latest = if resp.len() == 0 {
+    let latest = if resp.is_empty() {
         return None;
     } else {
         resp[0].name.clone()
     };
     let latest = &latest[1..];
 
-    let latest_sem: semver::Version = semver::Version::parse(&latest).unwrap();
+    let latest_sem: semver::Version = semver::Version::parse(latest).unwrap();
     let current_sem: semver::Version = semver::Version::parse(VERSION).unwrap();
 
     if latest_sem > current_sem {
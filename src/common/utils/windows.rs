@@ -3,6 +3,7 @@ use std::iter::once;
 use std::mem::transmute;
 use std::ptr::null_mut;
 
+use crate::common::error::YasError;
 use crate::common::PixelRect;
 use log::{info, warn};
 
@@ -199,3 +200,22 @@ pub fn show_window_and_set_foreground(hwnd: HWND) {
         SetForegroundWindow(hwnd);
     }
 }
+
+// returns the client rect of the game window and whether it is cloud genshin
+pub fn find_gi_window() -> Result<(PixelRect, bool), YasError> {
+    set_dpi_awareness();
+
+    let (hwnd, is_cloud) = match find_window_local() {
+        Ok(h) => (h, false),
+        Err(_) => match find_window_cloud() {
+            Ok(h) => (h, true),
+            Err(_) => return Err(YasError::WindowNotFound(String::from("请确认原神已经开启"))),
+        },
+    };
+
+    show_window_and_set_foreground(hwnd);
+    crate::common::utils::sleep(1000);
+
+    let rect = get_client_rect(hwnd).map_err(YasError::WindowNotFound)?;
+    Ok((rect, is_cloud))
+}
@@ -0,0 +1,56 @@
+use crate::artifact::internal_artifact::InternalArtifact;
+use crate::expo::ExportFormat;
+
+// fields that do not survive a conversion, as messages for the user
+pub fn lost_fields(
+    from: ExportFormat,
+    to: ExportFormat,
+    artifacts: &[InternalArtifact],
+) -> Vec<String> {
+    let mut lost: Vec<String> = Vec::new();
+    if artifacts.is_empty() || from == to {
+        return lost;
+    }
+
+    // only mona stores the main stat value
+    if from != ExportFormat::Mona && to == ExportFormat::Mona {
+        lost.push(format!(
+            "{}不包含主词条数值，{}个圣遗物的主词条数值将被写为0",
+            from.name(),
+            artifacts.len()
+        ));
+    }
+
+    let equipped = artifacts.iter().filter(|a| a.equip.is_some()).count();
+    if equipped > 0 {
+        match to {
+            ExportFormat::GOOD => lost.push(format!(
+                "{}个圣遗物的装备角色无法写入GOOD的location",
+                equipped
+            )),
+            ExportFormat::MingyuLab => lost.push(format!(
+                "mingyulab不支持装备角色，{}个圣遗物的装备信息将丢失",
+                equipped
+            )),
+            ExportFormat::Mona => (),
+        }
+    }
+    if from == ExportFormat::GOOD {
+        lost.push(String::from("GOOD中的location和lock不会被保留"));
+    }
+
+    if to == ExportFormat::MingyuLab {
+        let unsupported = artifacts
+            .iter()
+            .filter(|a| !a.set_name.is_supported_by_mingyu_lab())
+            .count();
+        if unsupported > 0 {
+            lost.push(format!(
+                "mingyulab不支持冒险家、幸运儿和游医套装，{}个圣遗物将被跳过",
+                unsupported
+            ));
+        }
+    }
+
+    lost
+}
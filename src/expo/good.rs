@@ -2,9 +2,10 @@ use crate::artifact::internal_artifact::{
     ArtifactSetName, ArtifactSlot, ArtifactStat, ArtifactStatName, InternalArtifact,
 };
 use serde::ser::{SerializeMap, Serializer};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::prelude::*;
+use strum::IntoEnumIterator;
 
 struct GOODArtifact<'a> {
     artifact: &'a InternalArtifact,
@@ -55,7 +56,7 @@ struct GOODStat<'a> {
 }
 
 impl<'a> GOODStat<'a> {
-    fn new(stat: &ArtifactStat) -> GOODStat {
+    fn new(stat: &ArtifactStat) -> GOODStat<'_> {
         GOODStat {
             key: stat.name.to_good(),
             value: match stat.name {
@@ -70,6 +71,10 @@ impl<'a> GOODStat<'a> {
 }
 
 impl ArtifactStatName {
+    pub fn from_good(s: &str) -> Option<ArtifactStatName> {
+        ArtifactStatName::iter().find(|x| x.to_good() == s)
+    }
+
     pub fn to_good(&self) -> &'static str {
         match self {
             ArtifactStatName::HealingBonus => "heal_",
@@ -96,6 +101,10 @@ impl ArtifactStatName {
 }
 
 impl ArtifactSlot {
+    pub fn from_good(s: &str) -> Option<ArtifactSlot> {
+        ArtifactSlot::iter().find(|x| x.to_good() == s)
+    }
+
     pub fn to_good(&self) -> &'static str {
         match self {
             ArtifactSlot::Flower => "flower",
@@ -108,6 +117,10 @@ impl ArtifactSlot {
 }
 
 impl ArtifactSetName {
+    pub fn from_good(s: &str) -> Option<ArtifactSetName> {
+        ArtifactSetName::iter().find(|x| x.to_good() == s)
+    }
+
     pub fn to_good(&self) -> &'static str {
         match self {
             ArtifactSetName::ArchaicPetra => "ArchaicPetra",
@@ -166,9 +179,9 @@ pub struct GOODFormat<'a> {
 }
 
 impl<'a> GOODFormat<'a> {
-    pub fn new(results: &'a Vec<InternalArtifact>) -> GOODFormat {
+    pub fn new(results: &'a [InternalArtifact]) -> GOODFormat<'a> {
         let artifacts: Vec<GOODArtifact<'a>> = results
-            .into_iter()
+            .iter()
             .map(|artifact| GOODArtifact { artifact })
             .collect();
         GOODFormat {
@@ -179,15 +192,105 @@ impl<'a> GOODFormat<'a> {
         }
     }
 
-    pub fn save(&self, path: &str) {
-        let mut file = match File::create(&path) {
-            Err(why) => panic!("couldn't create {}: {}", path, why),
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut file = match File::create(path) {
+            Err(why) => return Err(format!("couldn't create {}: {}", path, why)),
             Ok(file) => file,
         };
         let s = serde_json::to_string(&self).unwrap();
         match file.write_all(s.as_bytes()) {
-            Err(why) => panic!("couldn't write to {}: {}", path, why),
-            _ => {}
+            Err(why) => Err(format!("couldn't write to {}: {}", path, why)),
+            _ => Ok(()),
         }
     }
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GOODArtifactEntry {
+    set_key: String,
+    slot_key: String,
+    level: u32,
+    rarity: u32,
+    main_stat_key: String,
+    #[serde(default)]
+    substats: Vec<GOODStatEntry>,
+}
+
+#[derive(Deserialize)]
+struct GOODStatEntry {
+    key: String,
+    value: f64,
+}
+
+#[derive(Deserialize)]
+struct GOODFile {
+    #[serde(default)]
+    artifacts: Vec<GOODArtifactEntry>,
+}
+
+impl GOODStatEntry {
+    fn to_stat(&self) -> Result<ArtifactStat, String> {
+        match ArtifactStatName::from_good(&self.key) {
+            Some(name) => Ok(ArtifactStat::from_display(name, self.value)),
+            None => Err(format!("unknown GOOD stat `{}`", self.key)),
+        }
+    }
+}
+
+impl GOODArtifactEntry {
+    fn to_internal_artifact(&self) -> Result<InternalArtifact, String> {
+        let set_name = match ArtifactSetName::from_good(&self.set_key) {
+            Some(v) => v,
+            None => return Err(format!("unknown GOOD set `{}`", self.set_key)),
+        };
+        let slot = match ArtifactSlot::from_good(&self.slot_key) {
+            Some(v) => v,
+            None => return Err(format!("unknown GOOD slot `{}`", self.slot_key)),
+        };
+        let main_stat_name = match ArtifactStatName::from_good(&self.main_stat_key) {
+            Some(v) => v,
+            None => return Err(format!("unknown GOOD stat `{}`", self.main_stat_key)),
+        };
+
+        let mut sub_stats: Vec<Option<ArtifactStat>> = Vec::new();
+        for stat in self.substats.iter() {
+            // GOOD pads missing substats with an empty key
+            if stat.key.is_empty() {
+                continue;
+            }
+            sub_stats.push(Some(stat.to_stat()?));
+        }
+        sub_stats.resize(4, None);
+
+        // GOOD stores neither the main stat value nor a character name we understand
+        // (`location` is a GOOD character key), they are left empty
+        Ok(InternalArtifact {
+            set_name,
+            slot,
+            star: self.rarity,
+            level: self.level,
+            main_stat: ArtifactStat {
+                name: main_stat_name,
+                value: 0.0,
+            },
+            sub_stat_1: sub_stats[0].take(),
+            sub_stat_2: sub_stats[1].take(),
+            sub_stat_3: sub_stats[2].take(),
+            sub_stat_4: sub_stats[3].take(),
+            equip: None,
+        })
+    }
+}
+
+pub fn parse_good(s: &str) -> Result<Vec<InternalArtifact>, String> {
+    let file: GOODFile = match serde_json::from_str(s) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("invalid GOOD file: {}", e)),
+    };
+
+    file.artifacts
+        .iter()
+        .map(|a| a.to_internal_artifact())
+        .collect()
+}
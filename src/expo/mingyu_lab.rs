@@ -2,8 +2,10 @@ use crate::artifact::internal_artifact::{
     ArtifactSetName, ArtifactSlot, ArtifactStat, ArtifactStatName, InternalArtifact,
 };
 use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde::Deserialize;
 use std::fs::File;
 use std::io::prelude::*;
+use strum::IntoEnumIterator;
 
 struct MingyuLabArtifact<'a> {
     artifact: &'a InternalArtifact,
@@ -50,6 +52,10 @@ impl<'a> Serialize for MingyuLabArtifact<'a> {
 }
 
 impl ArtifactStatName {
+    pub fn from_mingyu_lab(s: &str) -> Option<ArtifactStatName> {
+        ArtifactStatName::iter().find(|x| x.to_mingyu_lab() == s)
+    }
+
     pub fn to_mingyu_lab(&self) -> &'static str {
         match self {
             ArtifactStatName::HealingBonus => "healing",
@@ -76,6 +82,10 @@ impl ArtifactStatName {
 }
 
 impl ArtifactSlot {
+    pub fn from_mingyu_lab(s: &str) -> Option<ArtifactSlot> {
+        ArtifactSlot::iter().find(|x| x.to_mingyu_lab() == s)
+    }
+
     pub fn to_mingyu_lab(&self) -> &'static str {
         match self {
             ArtifactSlot::Flower => "flower",
@@ -88,6 +98,21 @@ impl ArtifactSlot {
 }
 
 impl ArtifactSetName {
+    pub fn from_mingyu_lab(s: &str) -> Option<ArtifactSetName> {
+        ArtifactSetName::iter()
+            .filter(|x| x.is_supported_by_mingyu_lab())
+            .find(|x| x.to_mingyu_lab() == s)
+    }
+
+    pub fn is_supported_by_mingyu_lab(&self) -> bool {
+        !matches!(
+            self,
+            ArtifactSetName::Adventurer
+                | ArtifactSetName::LuckyDog
+                | ArtifactSetName::TravelingDoctor
+        )
+    }
+
     pub fn to_mingyu_lab(&self) -> &'static str {
         match self {
             ArtifactSetName::ArchaicPetra => "archaic_petra",
@@ -144,28 +169,96 @@ pub struct MingyuLabFormat<'a> {
 }
 
 impl<'a> MingyuLabFormat<'a> {
-    pub fn new(results: &'a Vec<InternalArtifact>) -> MingyuLabFormat {
+    pub fn new(results: &'a [InternalArtifact]) -> MingyuLabFormat<'a> {
         let artifacts: Vec<MingyuLabArtifact<'a>> = results
-            .into_iter()
-            .filter(|artifact| {
-                artifact.set_name != ArtifactSetName::Adventurer
-                    && artifact.set_name != ArtifactSetName::LuckyDog
-                    && artifact.set_name != ArtifactSetName::TravelingDoctor
-            })
+            .iter()
+            .filter(|artifact| artifact.set_name.is_supported_by_mingyu_lab())
             .map(|artifact| MingyuLabArtifact { artifact })
             .collect();
         MingyuLabFormat { artifacts }
     }
 
-    pub fn save(&self, path: &str) {
-        let mut file = match File::create(&path) {
-            Err(why) => panic!("couldn't create {}: {}", path, why),
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut file = match File::create(path) {
+            Err(why) => return Err(format!("couldn't create {}: {}", path, why)),
             Ok(file) => file,
         };
         let s = serde_json::to_string(&self.artifacts).unwrap();
         match file.write_all(s.as_bytes()) {
-            Err(why) => panic!("couldn't write to {}: {}", path, why),
-            _ => {}
+            Err(why) => Err(format!("couldn't write to {}: {}", path, why)),
+            _ => Ok(()),
         }
     }
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MingyuLabArtifactEntry {
+    as_key: String,
+    rarity: u32,
+    slot: String,
+    level: u32,
+    main_stat: String,
+    sub_stat1_type: String,
+    sub_stat1_value: f64,
+    sub_stat2_type: String,
+    sub_stat2_value: f64,
+    sub_stat3_type: String,
+    sub_stat3_value: f64,
+    sub_stat4_type: String,
+    sub_stat4_value: f64,
+}
+
+// missing substats are written as `flatATK` with value 0
+fn sub_stat_from_mingyu_lab(key: &str, value: f64) -> Result<Option<ArtifactStat>, String> {
+    if value == 0.0 {
+        return Ok(None);
+    }
+    match ArtifactStatName::from_mingyu_lab(key) {
+        Some(name) => Ok(Some(ArtifactStat::from_display(name, value))),
+        None => Err(format!("unknown mingyulab stat `{}`", key)),
+    }
+}
+
+impl MingyuLabArtifactEntry {
+    fn to_internal_artifact(&self) -> Result<InternalArtifact, String> {
+        let set_name = match ArtifactSetName::from_mingyu_lab(&self.as_key) {
+            Some(v) => v,
+            None => return Err(format!("unknown mingyulab set `{}`", self.as_key)),
+        };
+        let slot = match ArtifactSlot::from_mingyu_lab(&self.slot) {
+            Some(v) => v,
+            None => return Err(format!("unknown mingyulab slot `{}`", self.slot)),
+        };
+        let main_stat_name = match ArtifactStatName::from_mingyu_lab(&self.main_stat) {
+            Some(v) => v,
+            None => return Err(format!("unknown mingyulab stat `{}`", self.main_stat)),
+        };
+
+        // like GOOD, there is no main stat value and no equipped character
+        Ok(InternalArtifact {
+            set_name,
+            slot,
+            star: self.rarity,
+            level: self.level,
+            main_stat: ArtifactStat {
+                name: main_stat_name,
+                value: 0.0,
+            },
+            sub_stat_1: sub_stat_from_mingyu_lab(&self.sub_stat1_type, self.sub_stat1_value)?,
+            sub_stat_2: sub_stat_from_mingyu_lab(&self.sub_stat2_type, self.sub_stat2_value)?,
+            sub_stat_3: sub_stat_from_mingyu_lab(&self.sub_stat3_type, self.sub_stat3_value)?,
+            sub_stat_4: sub_stat_from_mingyu_lab(&self.sub_stat4_type, self.sub_stat4_value)?,
+            equip: None,
+        })
+    }
+}
+
+pub fn parse_mingyu_lab(s: &str) -> Result<Vec<InternalArtifact>, String> {
+    let entries: Vec<MingyuLabArtifactEntry> = match serde_json::from_str(s) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("invalid mingyulab file: {}", e)),
+    };
+
+    entries.iter().map(|a| a.to_internal_artifact()).collect()
+}
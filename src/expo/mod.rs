@@ -1,3 +1,226 @@
 pub mod mona_uranai;
 pub mod mingyu_lab;
-pub mod good;
\ No newline at end of file
+pub mod good;
+pub mod convert;
+
+use std::fs;
+
+use crate::artifact::internal_artifact::InternalArtifact;
+use good::GOODFormat;
+use mingyu_lab::MingyuLabFormat;
+use mona_uranai::MonaFormat;
+
+pub const ALL_FORMATS: [ExportFormat; 3] = [
+    ExportFormat::Mona,
+    ExportFormat::MingyuLab,
+    ExportFormat::GOOD,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Mona,
+    MingyuLab,
+    GOOD,
+}
+
+impl ExportFormat {
+    // the names used by `--output-format`
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        match name {
+            "mona" => Some(ExportFormat::Mona),
+            "mingyulab" => Some(ExportFormat::MingyuLab),
+            "good" => Some(ExportFormat::GOOD),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Mona => "mona",
+            ExportFormat::MingyuLab => "mingyulab",
+            ExportFormat::GOOD => "good",
+        }
+    }
+
+    // GOOD is tagged, mona is an object keyed by slot and mingyulab a bare array
+    pub fn detect(content: &str) -> Option<ExportFormat> {
+        let value: serde_json::Value = serde_json::from_str(content).ok()?;
+        if value.get("format").and_then(|v| v.as_str()) == Some("GOOD") {
+            Some(ExportFormat::GOOD)
+        } else if value.get("flower").is_some() {
+            Some(ExportFormat::Mona)
+        } else if value.is_array() {
+            Some(ExportFormat::MingyuLab)
+        } else {
+            None
+        }
+    }
+
+    pub fn save(&self, artifacts: &[InternalArtifact], path: &str) -> Result<(), String> {
+        match self {
+            ExportFormat::Mona => MonaFormat::new(artifacts).save(path),
+            ExportFormat::MingyuLab => MingyuLabFormat::new(artifacts).save(path),
+            ExportFormat::GOOD => GOODFormat::new(artifacts).save(path),
+        }
+    }
+
+    pub fn parse(&self, content: &str) -> Result<Vec<InternalArtifact>, String> {
+        match self {
+            ExportFormat::Mona => mona_uranai::parse_mona(content),
+            ExportFormat::MingyuLab => mingyu_lab::parse_mingyu_lab(content),
+            ExportFormat::GOOD => good::parse_good(content),
+        }
+    }
+}
+
+// reads a previous export, the format is told apart by its content
+pub fn load_artifacts(path: &str) -> Result<(ExportFormat, Vec<InternalArtifact>), String> {
+    let content = match fs::read_to_string(path) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("cannot read {}: {}", path, e)),
+    };
+    let format = match ExportFormat::detect(&content) {
+        Some(v) => v,
+        None => return Err(format!("{}: 无法识别的格式", path)),
+    };
+
+    Ok((format, format.parse(&content)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifact::internal_artifact::{
+        ArtifactSetName, ArtifactSlot, ArtifactStat, ArtifactStatName,
+    };
+    use crate::artifact::merge::{match_key, KeyFields};
+    use crate::common::temp_path::TempPath;
+
+    fn stat(name: ArtifactStatName, value: f64) -> Option<ArtifactStat> {
+        Some(ArtifactStat::from_display(name, value))
+    }
+
+    // in mona's slot order, mona groups artifacts by slot
+    fn artifacts() -> Vec<InternalArtifact> {
+        vec![
+            InternalArtifact {
+                set_name: ArtifactSetName::GladiatorFinale,
+                slot: ArtifactSlot::Flower,
+                star: 5,
+                level: 20,
+                main_stat: ArtifactStat::from_display(ArtifactStatName::Hp, 4780.0),
+                sub_stat_1: stat(ArtifactStatName::Critical, 3.9),
+                sub_stat_2: stat(ArtifactStatName::CriticalDamage, 21.8),
+                sub_stat_3: stat(ArtifactStatName::AtkPercentage, 9.9),
+                sub_stat_4: stat(ArtifactStatName::Def, 23.0),
+                equip: Some(String::from("迪卢克")),
+            },
+            InternalArtifact {
+                set_name: ArtifactSetName::CrimsonWitch,
+                slot: ArtifactSlot::Sand,
+                star: 5,
+                level: 0,
+                main_stat: ArtifactStat::from_display(ArtifactStatName::AtkPercentage, 7.0),
+                sub_stat_1: stat(ArtifactStatName::ElementalMastery, 19.0),
+                sub_stat_2: stat(ArtifactStatName::Recharge, 5.2),
+                sub_stat_3: stat(ArtifactStatName::Critical, 2.7),
+                sub_stat_4: None,
+                equip: None,
+            },
+            InternalArtifact {
+                set_name: ArtifactSetName::Berserker,
+                slot: ArtifactSlot::Goblet,
+                star: 4,
+                level: 4,
+                main_stat: ArtifactStat::from_display(ArtifactStatName::PyroBonus, 9.9),
+                sub_stat_1: stat(ArtifactStatName::Hp, 203.0),
+                sub_stat_2: stat(ArtifactStatName::CriticalDamage, 5.4),
+                sub_stat_3: None,
+                sub_stat_4: None,
+                equip: None,
+            },
+        ]
+    }
+
+    fn round_trip(format: ExportFormat, artifacts: &[InternalArtifact]) -> Vec<InternalArtifact> {
+        let path = TempPath::new(&format!("{}-round-trip", format.name()));
+        format.save(artifacts, path.to_str().unwrap()).unwrap();
+
+        let (detected, loaded) = load_artifacts(path.to_str().unwrap()).unwrap();
+        assert_eq!(detected, format);
+        loaded
+    }
+
+    #[test]
+    fn mona_round_trips_every_field() {
+        let artifacts = artifacts();
+        assert_eq!(round_trip(ExportFormat::Mona, &artifacts), artifacts);
+    }
+
+    #[test]
+    fn mona_round_trips_the_equipped_character() {
+        let mut artifacts = artifacts();
+        // the panel text, as older scans stored it
+        artifacts[1].equip = Some(String::from("迪卢克已装备"));
+        let equip: Vec<_> = round_trip(ExportFormat::Mona, &artifacts)
+            .into_iter()
+            .map(|a| a.equip)
+            .collect();
+        let diluc = Some(String::from("迪卢克"));
+        assert_eq!(equip, vec![diluc.clone(), diluc, None]);
+    }
+
+    #[test]
+    fn good_and_mingyulab_round_trip_without_main_value_and_equip() {
+        let artifacts = artifacts();
+        let fields = KeyFields {
+            main_value: false,
+            equip: false,
+        };
+        let expected: Vec<_> = artifacts.iter().map(|a| match_key(a, fields)).collect();
+
+        assert_eq!(round_trip(ExportFormat::GOOD, &artifacts), expected);
+        assert_eq!(round_trip(ExportFormat::MingyuLab, &artifacts), expected);
+    }
+
+    #[test]
+    fn mingyulab_skips_unsupported_sets() {
+        let mut artifacts = artifacts();
+        artifacts[1].set_name = ArtifactSetName::Adventurer;
+        let loaded = round_trip(ExportFormat::MingyuLab, &artifacts);
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[1].set_name, ArtifactSetName::Berserker);
+    }
+
+    #[test]
+    fn detect_tells_formats_apart() {
+        assert_eq!(
+            ExportFormat::detect(r#"{"format":"GOOD","version":1,"artifacts":[]}"#),
+            Some(ExportFormat::GOOD)
+        );
+        assert_eq!(
+            ExportFormat::detect(r#"{"version":"1","flower":[]}"#),
+            Some(ExportFormat::Mona)
+        );
+        assert_eq!(ExportFormat::detect("[]"), Some(ExportFormat::MingyuLab));
+        assert_eq!(ExportFormat::detect(r#"{"artifacts":[]}"#), None);
+        assert_eq!(ExportFormat::detect("not json"), None);
+    }
+
+    #[test]
+    fn load_reports_unknown_formats() {
+        let path = TempPath::new("unknown-format");
+        std::fs::write(&path, "{}").unwrap();
+        assert!(load_artifacts(path.to_str().unwrap()).is_err());
+
+        assert!(load_artifacts("/nonexistent/yas.json").is_err());
+    }
+
+    #[test]
+    fn save_reports_io_errors() {
+        let artifacts = artifacts();
+        for format in ALL_FORMATS.iter() {
+            assert!(format.save(&artifacts, "/nonexistent/yas.json").is_err());
+        }
+    }
+}
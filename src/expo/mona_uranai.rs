@@ -5,6 +5,8 @@ use std::io::prelude::*;
 // use rand::Rng;
 
 use serde::ser::{Serialize, Serializer, SerializeMap};
+use serde::Deserialize;
+use strum::IntoEnumIterator;
 use tract_onnx::prelude::tract_itertools::Itertools;
 
 use crate::artifact::internal_artifact::{ArtifactStatName, ArtifactSetName, ArtifactSlot, InternalArtifact, ArtifactStat};
@@ -13,6 +15,10 @@ use crate::artifact::internal_artifact::{ArtifactStatName, ArtifactSetName, Arti
 type MonaArtifact = InternalArtifact;
 
 impl ArtifactStatName {
+    pub fn from_mona(s: &str) -> Option<ArtifactStatName> {
+        ArtifactStatName::iter().find(|x| x.to_mona() == s)
+    }
+
     pub fn to_mona(&self) -> String {
         let temp = match self {
             ArtifactStatName::HealingBonus => "cureEffect",
@@ -40,6 +46,10 @@ impl ArtifactStatName {
 }
 
 impl ArtifactSetName {
+    pub fn from_mona(s: &str) -> Option<ArtifactSetName> {
+        ArtifactSetName::iter().find(|x| x.to_mona() == s)
+    }
+
     pub fn to_mona(&self) -> String {
         let same = self.to_string();
         let temp = match self {
@@ -87,6 +97,10 @@ impl ArtifactSetName {
 }
 
 impl ArtifactSlot {
+    pub fn from_mona(s: &str) -> Option<ArtifactSlot> {
+        ArtifactSlot::iter().find(|x| x.to_mona() == s)
+    }
+
     pub fn to_mona(&self) -> String {
         let temp = match self {
             ArtifactSlot::Flower => "flower",
@@ -142,6 +156,7 @@ impl Serialize for MonaArtifact {
         root.serialize_entry("level", &self.level)?;
         root.serialize_entry("star", &self.star)?;
 
+        // scanned artifacts already carry the bare character name
         let equip = match self.equip {
             Some(ref x) => {
                 if x.contains("已装备") {
@@ -149,7 +164,7 @@ impl Serialize for MonaArtifact {
                     let chars2 = &chars[..chars.len() - 3];
                     chars2.iter().collect()
                 } else {
-                    String::new()
+                    x.clone()
                 }
             },
             None => String::new(),
@@ -185,7 +200,7 @@ impl<'a> Serialize for MonaFormat<'a> {
 }
 
 impl<'a> MonaFormat<'a> {
-    pub fn new(results: &Vec<InternalArtifact>) -> MonaFormat {
+    pub fn new(results: &[InternalArtifact]) -> MonaFormat<'_> {
         let mut flower: Vec<&MonaArtifact> = Vec::new();
         let mut feather: Vec<&MonaArtifact> = Vec::new();
         let mut cup: Vec<&MonaArtifact> = Vec::new();
@@ -213,16 +228,119 @@ impl<'a> MonaFormat<'a> {
         }
     }
 
-    pub fn save(&self, path: &str) {
+    pub fn save(&self, path: &str) -> Result<(), String> {
         let mut file = match File::create(path) {
-            Err(why) => panic!("couldn't create {}: {}", path, why),
+            Err(why) => return Err(format!("couldn't create {}: {}", path, why)),
             Ok(file) => file,
         };
         let s = serde_json::to_string(&self).unwrap();
 
         match file.write_all(s.as_bytes()) {
-            Err(why) => panic!("couldn't write to {}: {}", path, why),
-            _ => {},
+            Err(why) => Err(format!("couldn't write to {}: {}", path, why)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MonaStatEntry {
+    name: String,
+    value: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MonaArtifactEntry {
+    set_name: String,
+    position: String,
+    main_tag: MonaStatEntry,
+    #[serde(default)]
+    normal_tags: Vec<MonaStatEntry>,
+    level: u32,
+    star: u32,
+    #[serde(default)]
+    equip: String,
+}
+
+#[derive(Deserialize)]
+struct MonaFile {
+    #[serde(default)]
+    flower: Vec<MonaArtifactEntry>,
+    #[serde(default)]
+    feather: Vec<MonaArtifactEntry>,
+    #[serde(default)]
+    sand: Vec<MonaArtifactEntry>,
+    #[serde(default)]
+    cup: Vec<MonaArtifactEntry>,
+    #[serde(default)]
+    head: Vec<MonaArtifactEntry>,
+}
+
+impl MonaStatEntry {
+    fn to_stat(&self) -> Result<ArtifactStat, String> {
+        match ArtifactStatName::from_mona(&self.name) {
+            // mona stores fractions (0.311 for 31.1%)
+            Some(name) => {
+                let value = if name.is_percentage() {
+                    self.value * 100.0
+                } else {
+                    self.value
+                };
+                Ok(ArtifactStat::from_display(name, value))
+            }
+            None => Err(format!("unknown mona stat `{}`", self.name)),
         }
     }
 }
+
+impl MonaArtifactEntry {
+    fn to_internal_artifact(&self) -> Result<InternalArtifact, String> {
+        let set_name = match ArtifactSetName::from_mona(&self.set_name) {
+            Some(v) => v,
+            None => return Err(format!("unknown mona set `{}`", self.set_name)),
+        };
+        let slot = match ArtifactSlot::from_mona(&self.position) {
+            Some(v) => v,
+            None => return Err(format!("unknown mona position `{}`", self.position)),
+        };
+
+        let mut sub_stats: Vec<Option<ArtifactStat>> = Vec::new();
+        for stat in self.normal_tags.iter() {
+            sub_stats.push(Some(stat.to_stat()?));
+        }
+        sub_stats.resize(4, None);
+
+        Ok(InternalArtifact {
+            set_name,
+            slot,
+            star: self.star,
+            level: self.level,
+            main_stat: self.main_tag.to_stat()?,
+            sub_stat_1: sub_stats[0].take(),
+            sub_stat_2: sub_stats[1].take(),
+            sub_stat_3: sub_stats[2].take(),
+            sub_stat_4: sub_stats[3].take(),
+            equip: if self.equip.is_empty() {
+                None
+            } else {
+                Some(self.equip.clone())
+            },
+        })
+    }
+}
+
+pub fn parse_mona(s: &str) -> Result<Vec<InternalArtifact>, String> {
+    let file: MonaFile = match serde_json::from_str(s) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("invalid mona file: {}", e)),
+    };
+
+    file.flower
+        .iter()
+        .chain(file.feather.iter())
+        .chain(file.sand.iter())
+        .chain(file.cup.iter())
+        .chain(file.head.iter())
+        .map(|a| a.to_internal_artifact())
+        .collect()
+}
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::common::error::YasError;
+use crate::common::RawImage;
+use crate::inference::ctc::Constraint;
+use crate::inference::inference::{CRNNModel, BATCH_SIZE};
+use crate::inference::pre_process::PreProcessor;
+use crate::info::info::FieldBinarization;
+
+// one crop written by `--dump` as `<field>_<n>.png`, labelled by `<field>_<n>.txt`
+pub struct BenchSample {
+    pub field: String,
+    pub name: String,
+    pub image: RawImage,
+    pub label: String,
+}
+
+// crops without a label are skipped, so are the preprocessed `p_*.png`
+pub fn load_samples(dir: &str) -> Result<Vec<BenchSample>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("cannot read {}: {}", dir, e))?;
+
+    let mut samples: Vec<BenchSample> = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("png") {
+            continue;
+        }
+        let stem = match path.file_stem().and_then(|s| s.to_str()) {
+            Some(v) => String::from(v),
+            None => continue,
+        };
+        if stem.starts_with("p_") {
+            continue;
+        }
+        let field = match stem.rsplit_once('_') {
+            Some((v, _)) => String::from(v),
+            None => continue,
+        };
+        let label = match fs::read_to_string(path.with_extension("txt")) {
+            Ok(v) => String::from(v.trim_end_matches(['\n', '\r'])),
+            Err(_) => continue,
+        };
+
+        samples.push(BenchSample {
+            field,
+            name: stem,
+            image: load_gray(&path)?,
+            label,
+        });
+    }
+
+    samples.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(samples)
+}
+
+// the same gray values `crop_to_raw_img` gives the recognizer
+fn load_gray(path: &Path) -> Result<RawImage, String> {
+    let img = match image::open(path) {
+        Ok(v) => v.to_luma8(),
+        Err(e) => return Err(format!("cannot open {}: {}", path.display(), e)),
+    };
+    let (w, h) = img.dimensions();
+    Ok(RawImage {
+        data: img.pixels().map(|p| p.0[0] as f32).collect(),
+        w,
+        h,
+    })
+}
+
+#[derive(Default)]
+pub struct FieldStats {
+    pub samples: u32,
+    pub correct: u32,
+    pub char_errors: usize,
+    pub label_chars: usize,
+    pub pre_process_time: Duration,
+}
+
+impl FieldStats {
+    fn add(&mut self, other: &FieldStats) {
+        self.samples += other.samples;
+        self.correct += other.correct;
+        self.char_errors += other.char_errors;
+        self.label_chars += other.label_chars;
+        self.pre_process_time += other.pre_process_time;
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        self.correct as f64 / self.samples.max(1) as f64
+    }
+
+    // edit distance over the number of labelled characters
+    pub fn char_error_rate(&self) -> f64 {
+        self.char_errors as f64 / self.label_chars.max(1) as f64
+    }
+
+    fn mean_ms(&self, d: Duration) -> f64 {
+        d.as_secs_f64() * 1000.0 / self.samples.max(1) as f64
+    }
+}
+
+pub struct Mistake {
+    pub name: String,
+    pub label: String,
+    pub predicted: String,
+}
+
+pub struct BenchReport {
+    pub fields: BTreeMap<String, FieldStats>,
+    pub mistakes: Vec<Mistake>,
+    // the network runs once per panel, as in a scan
+    pub batches: u32,
+    pub inference_time: Duration,
+}
+
+// the number of the panel a dump belongs to, `title_12` -> `12`
+fn panel_of(name: &str) -> &str {
+    name.rsplit('_').next().unwrap_or("")
+}
+
+// samples are preprocessed with the binarization of their field, and the fields of
+// a panel go through the network together, as in the recognizer threads
+pub fn run(
+    model: &CRNNModel,
+    samples: Vec<BenchSample>,
+    binarization: &FieldBinarization,
+) -> Result<BenchReport, YasError> {
+    let mut report = BenchReport {
+        fields: BTreeMap::new(),
+        mistakes: Vec::new(),
+        batches: 0,
+        inference_time: Duration::default(),
+    };
+
+    let mut panels: BTreeMap<String, Vec<BenchSample>> = BTreeMap::new();
+    for sample in samples {
+        panels
+            .entry(String::from(panel_of(&sample.name)))
+            .or_default()
+            .push(sample);
+    }
+
+    // the buffers are reused like in the recognizer threads
+    let mut pre_processor = PreProcessor::new();
+    let mut processed: Vec<RawImage> = Vec::new();
+    for (_, mut panel) in panels {
+        while !panel.is_empty() {
+            let batch: Vec<BenchSample> = panel.drain(..panel.len().min(BATCH_SIZE)).collect();
+            processed.resize_with(batch.len(), RawImage::default);
+
+            let mut present: Vec<bool> = Vec::with_capacity(batch.len());
+            let mut pre_process_times: Vec<Duration> = Vec::with_capacity(batch.len());
+            for (sample, image) in batch.iter().zip(processed.iter_mut()) {
+                let now = Instant::now();
+                let b = binarization.get(&sample.field);
+                present.push(pre_processor.process_into(&sample.image, b, image));
+                pre_process_times.push(now.elapsed());
+            }
+
+            // there is nothing to read in an empty field
+            let inputs: Vec<(&RawImage, Constraint)> = processed
+                .iter()
+                .zip(present.iter())
+                .filter(|(_, &p)| p)
+                .map(|(img, _)| (img, Constraint::Free))
+                .collect();
+            let mut recognized = if inputs.is_empty() {
+                Vec::new().into_iter()
+            } else {
+                let now = Instant::now();
+                let r = model.inference_batch(&inputs)?;
+                report.inference_time += now.elapsed();
+                report.batches += 1;
+                r.into_iter()
+            };
+
+            for ((sample, p), pre_process_time) in
+                batch.into_iter().zip(present).zip(pre_process_times)
+            {
+                let predicted = if p {
+                    recognized.next().unwrap().text
+                } else {
+                    String::new()
+                };
+
+                let errors = edit_distance::edit_distance(&predicted, &sample.label);
+                let stats = report.fields.entry(sample.field).or_default();
+                stats.samples += 1;
+                stats.char_errors += errors;
+                stats.label_chars += sample.label.chars().count();
+                stats.pre_process_time += pre_process_time;
+                if errors == 0 {
+                    stats.correct += 1;
+                } else {
+                    report.mistakes.push(Mistake {
+                        name: sample.name,
+                        label: sample.label,
+                        predicted,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+impl BenchReport {
+    pub fn total(&self) -> FieldStats {
+        let mut total = FieldStats::default();
+        for stats in self.fields.values() {
+            total.add(stats);
+        }
+        total
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>6} {:>8} {:>8} {:>12}",
+            "field", "count", "acc", "cer", "pre_process"
+        )?;
+        let total_name = String::from("total");
+        let total = self.total();
+        let rows = self
+            .fields
+            .iter()
+            .chain(std::iter::once((&total_name, &total)));
+        for (field, s) in rows {
+            writeln!(
+                f,
+                "{:<16} {:>6} {:>7.2}% {:>7.2}% {:>10.2}ms",
+                field,
+                s.samples,
+                s.accuracy() * 100.0,
+                s.char_error_rate() * 100.0,
+                s.mean_ms(s.pre_process_time)
+            )?;
+        }
+        writeln!(
+            f,
+            "inference: {} batches, {:.2}ms/batch",
+            self.batches,
+            self.inference_time.as_secs_f64() * 1000.0 / self.batches.max(1) as f64
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::inference::grammar::Grammar;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decoder {
+    Greedy,
+    Lexicon,
+    Grammar,
+}
+
+impl Decoder {
+    pub fn from_name(name: &str) -> Option<Decoder> {
+        match name {
+            "greedy" => Some(Decoder::Greedy),
+            "lexicon" => Some(Decoder::Lexicon),
+            "grammar" => Some(Decoder::Grammar),
+            _ => None,
+        }
+    }
+}
+
+// how far the decoder may go from the plain best path
+#[derive(Clone, Copy)]
+pub enum Constraint<'a> {
+    Free,
+    // the whole text is one of the words
+    Word(&'a Lexicon),
+    // the text before the first `+` is one of the words, e.g. "暴击率+3.9%"
+    StatName(&'a Lexicon),
+    // the whole text is accepted by the grammar, e.g. a stat name, "+" and a number
+    Grammar(&'a Grammar),
+}
+
+struct TrieNode {
+    token: usize,
+    children: HashMap<usize, usize>,
+    word: Option<usize>,
+}
+
+// valid strings as a trie over dictionary indices, node 0 is the empty prefix
+pub struct Lexicon {
+    nodes: Vec<TrieNode>,
+    words: Vec<String>,
+}
+
+impl Lexicon {
+    // words containing a character the model cannot output are left out
+    pub fn new<I, S>(words: I, index_2_word: &[String]) -> Lexicon
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let token_of: HashMap<&str, usize> = index_2_word
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (w.as_str(), i))
+            .collect();
+
+        let mut lexicon = Lexicon {
+            nodes: vec![TrieNode {
+                token: 0,
+                children: HashMap::new(),
+                word: None,
+            }],
+            words: Vec::new(),
+        };
+
+        'word: for word in words {
+            let word = word.as_ref();
+            let mut tokens: Vec<usize> = Vec::new();
+            for c in word.chars() {
+                match token_of.get(c.to_string().as_str()) {
+                    Some(&t) => tokens.push(t),
+                    None => continue 'word,
+                }
+            }
+            if tokens.is_empty() {
+                continue;
+            }
+
+            let mut node = 0;
+            for t in tokens {
+                node = match lexicon.nodes[node].children.get(&t) {
+                    Some(&child) => child,
+                    None => {
+                        lexicon.nodes.push(TrieNode {
+                            token: t,
+                            children: HashMap::new(),
+                            word: None,
+                        });
+                        let child = lexicon.nodes.len() - 1;
+                        lexicon.nodes[node].children.insert(t, child);
+                        child
+                    }
+                };
+            }
+            if lexicon.nodes[node].word.is_none() {
+                lexicon.words.push(String::from(word));
+                lexicon.nodes[node].word = Some(lexicon.words.len() - 1);
+            }
+        }
+
+        lexicon
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.iter().any(|w| w == word)
+    }
+}
+
+const BEAM_WIDTH: usize = 16;
+// a word is only taken when its probability, spread evenly over the frames, is at
+// least this per frame. A misread character or two stays well above it, text that
+// is no word at all (e.g. a crop of the wrong region) falls far below
+const MIN_FRAME_PROBABILITY: f64 = 0.5;
+
+// prefix beam search where every beam is a trie node, so only prefixes of valid
+// words survive. `probs` is one distribution per frame. Returns the most
+// probable complete word, or None if no word can be read from the frames.
+pub fn prefix_beam_search(probs: &[Vec<f32>], blank: usize, lexicon: &Lexicon) -> Option<String> {
+    // node -> (ends in blank, ends in the node's token)
+    let mut beams: HashMap<usize, (f64, f64)> = HashMap::new();
+    beams.insert(0, (1.0, 0.0));
+
+    for row in probs.iter() {
+        let mut next: HashMap<usize, (f64, f64)> = HashMap::new();
+        for (&node, &(pb, pnb)) in beams.iter() {
+            let total = pb + pnb;
+            next.entry(node).or_insert((0.0, 0.0)).0 += total * row[blank] as f64;
+
+            let last = if node == 0 {
+                None
+            } else {
+                Some(lexicon.nodes[node].token)
+            };
+            if let Some(t) = last {
+                next.entry(node).or_insert((0.0, 0.0)).1 += pnb * row[t] as f64;
+            }
+
+            for (&t, &child) in lexicon.nodes[node].children.iter() {
+                let p = row[t] as f64;
+                // the same character twice needs a blank in between
+                let from = if last == Some(t) { pb } else { total };
+                next.entry(child).or_insert((0.0, 0.0)).1 += from * p;
+            }
+        }
+
+        let mut ranked: Vec<(usize, (f64, f64))> = next.into_iter().collect();
+        ranked.sort_by(|a, b| {
+            let pa = (a.1).0 + (a.1).1;
+            let pb = (b.1).0 + (b.1).1;
+            pb.partial_cmp(&pa).unwrap_or(Ordering::Equal)
+        });
+        ranked.truncate(BEAM_WIDTH);
+        beams = ranked.into_iter().collect();
+    }
+
+    beams
+        .iter()
+        .filter_map(|(&node, &(pb, pnb))| lexicon.nodes[node].word.map(|w| (w, pb + pnb)))
+        .filter(|&(_, p)| p > 0.0 && p.powf(1.0 / probs.len() as f64) >= MIN_FRAME_PROBABILITY)
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+        .map(|(w, _)| lexicon.words[w].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLANK: usize = 0;
+
+    fn dictionary() -> Vec<String> {
+        ["-", "暴", "击", "率", "伤", "害", "攻", "力"]
+            .iter()
+            .map(|s| String::from(*s))
+            .collect()
+    }
+
+    // one frame per entry, `p` on the given token and the rest spread over the others
+    fn frames(tokens: &[(usize, f32)]) -> Vec<Vec<f32>> {
+        let n = dictionary().len();
+        tokens
+            .iter()
+            .map(|&(t, p)| {
+                let mut row = vec![(1.0 - p) / (n - 1) as f32; n];
+                row[t] = p;
+                row
+            })
+            .collect()
+    }
+
+    fn lexicon() -> Lexicon {
+        Lexicon::new(vec!["暴击率", "暴击伤害", "攻击力"], &dictionary())
+    }
+
+    #[test]
+    fn reads_the_word_in_the_frames() {
+        let probs = frames(&[(1, 0.9), (0, 0.9), (2, 0.9), (2, 0.9), (3, 0.9), (0, 0.9)]);
+        assert_eq!(
+            prefix_beam_search(&probs, BLANK, &lexicon()).as_deref(),
+            Some("暴击率")
+        );
+    }
+
+    #[test]
+    fn corrects_a_misread_character() {
+        // "暴击力" is no word, the third character is unsure
+        let mut probs = frames(&[(1, 0.95), (2, 0.95), (7, 0.5), (0, 0.95), (0, 0.95)]);
+        probs[2][3] = 0.4;
+        assert_eq!(
+            prefix_beam_search(&probs, BLANK, &lexicon()).as_deref(),
+            Some("暴击率")
+        );
+    }
+
+    #[test]
+    fn repeated_characters_need_a_blank() {
+        let lexicon = Lexicon::new(vec!["攻攻", "攻"], &dictionary());
+        let probs = frames(&[(6, 0.9), (6, 0.9), (0, 0.9)]);
+        assert_eq!(
+            prefix_beam_search(&probs, BLANK, &lexicon).as_deref(),
+            Some("攻")
+        );
+        let probs = frames(&[(6, 0.9), (0, 0.9), (6, 0.9)]);
+        assert_eq!(
+            prefix_beam_search(&probs, BLANK, &lexicon).as_deref(),
+            Some("攻攻")
+        );
+    }
+
+    #[test]
+    fn gives_up_on_text_that_is_no_word() {
+        // "伤害" alone, no word is close
+        let probs = frames(&[(4, 0.95), (0, 0.95), (5, 0.95), (0, 0.95)]);
+        assert_eq!(prefix_beam_search(&probs, BLANK, &lexicon()), None);
+        // nothing but blanks
+        let probs = frames(&[(0, 0.99); 6]);
+        assert_eq!(prefix_beam_search(&probs, BLANK, &lexicon()), None);
+    }
+
+    #[test]
+    fn lexicon_skips_words_the_model_cannot_write() {
+        let lexicon = Lexicon::new(vec!["暴击率", "元素精通"], &dictionary());
+        assert!(lexicon.contains("暴击率"));
+        assert!(!lexicon.contains("元素精通"));
+    }
+}
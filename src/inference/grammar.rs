@@ -0,0 +1,325 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+struct State {
+    next: HashMap<usize, usize>,
+    accepting: bool,
+}
+
+// a deterministic automaton over dictionary indices, state 0 is the start
+pub struct Grammar {
+    states: Vec<State>,
+    token_of: HashMap<char, usize>,
+}
+
+fn token_map(index_2_word: &[String]) -> HashMap<char, usize> {
+    index_2_word
+        .iter()
+        .enumerate()
+        .filter_map(|(i, w)| {
+            let mut chars = w.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some((c, i)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+impl Grammar {
+    fn empty(index_2_word: &[String]) -> Grammar {
+        Grammar {
+            states: vec![State {
+                next: HashMap::new(),
+                accepting: false,
+            }],
+            token_of: token_map(index_2_word),
+        }
+    }
+
+    fn add_state(&mut self, accepting: bool) -> usize {
+        self.states.push(State {
+            next: HashMap::new(),
+            accepting,
+        });
+        self.states.len() - 1
+    }
+
+    fn link(&mut self, from: usize, c: char, to: usize) {
+        if let Some(&t) = self.token_of.get(&c) {
+            self.states[from].next.insert(t, to);
+        }
+    }
+
+    fn link_digits(&mut self, from: usize, to: usize) {
+        for c in "0123456789".chars() {
+            self.link(from, c, to);
+        }
+    }
+
+    // exactly one of `words`
+    pub fn words<I, S>(words: I, index_2_word: &[String]) -> Grammar
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut g = Grammar::empty(index_2_word);
+        for word in words {
+            // words the model cannot output are left out
+            let tokens: Option<Vec<usize>> = word
+                .as_ref()
+                .chars()
+                .map(|c| g.token_of.get(&c).cloned())
+                .collect();
+            let tokens = match tokens {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let mut state = 0;
+            for t in tokens {
+                state = match g.states[state].next.get(&t) {
+                    Some(&s) => s,
+                    None => {
+                        let s = g.add_state(false);
+                        g.states[state].next.insert(t, s);
+                        s
+                    }
+                };
+            }
+            if state != 0 {
+                g.states[state].accepting = true;
+            }
+        }
+        g
+    }
+
+    pub fn literal(s: &str, index_2_word: &[String]) -> Grammar {
+        Grammar::words([s], index_2_word)
+    }
+
+    // a value as shown on the panel: "23", "4,780", "3.9%", "46.6%"
+    pub fn number(index_2_word: &[String]) -> Grammar {
+        let mut g = Grammar::empty(index_2_word);
+        let d1 = g.add_state(true);
+        let d2 = g.add_state(true);
+        let d3 = g.add_state(true);
+        let sep = g.add_state(false);
+        let g1 = g.add_state(false);
+        let g2 = g.add_state(false);
+        let g3 = g.add_state(true);
+        let point = g.add_state(false);
+        let fraction = g.add_state(true);
+        let percent = g.add_state(true);
+
+        g.link_digits(0, d1);
+        g.link_digits(d1, d2);
+        g.link_digits(d2, d3);
+        // thousands are always separated
+        g.link_digits(sep, g1);
+        g.link_digits(g1, g2);
+        g.link_digits(g2, g3);
+        for &s in [d1, d2, d3, g3].iter() {
+            g.link(s, ',', sep);
+            g.link(s, '.', point);
+            g.link(s, '%', percent);
+        }
+        g.link_digits(point, fraction);
+        g.link_digits(fraction, fraction);
+        g.link(fraction, '%', percent);
+        g
+    }
+
+    // `self` followed by `other`
+    pub fn then(mut self, other: Grammar) -> Grammar {
+        let offset = self.states.len();
+        let start_next: HashMap<usize, usize> = other.states[0]
+            .next
+            .iter()
+            .map(|(&t, &s)| (t, s + offset))
+            .collect();
+        let start_accepting = other.states[0].accepting;
+
+        for state in self.states.iter_mut().filter(|s| s.accepting) {
+            for (&t, &s) in start_next.iter() {
+                state.next.entry(t).or_insert(s);
+            }
+            state.accepting = start_accepting;
+        }
+        for state in other.states.into_iter() {
+            self.states.push(State {
+                next: state.next.iter().map(|(&t, &s)| (t, s + offset)).collect(),
+                accepting: state.accepting,
+            });
+        }
+        self
+    }
+
+    fn step(&self, state: usize, token: usize) -> Option<usize> {
+        self.states[state].next.get(&token).cloned()
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let mut state = 0;
+        for c in text.chars() {
+            state = match self.token_of.get(&c).and_then(|&t| self.step(state, t)) {
+                Some(s) => s,
+                None => return false,
+            };
+        }
+        self.states[state].accepting
+    }
+}
+
+const BEAM_WIDTH: usize = 16;
+
+// prefix beam search that only extends a prefix with tokens the grammar allows.
+// Returns the most probable accepted token sequence.
+pub fn beam_search(probs: &[Vec<f32>], blank: usize, grammar: &Grammar) -> Option<Vec<usize>> {
+    // prefix -> (ends in blank, ends in its last token, grammar state)
+    let mut beams: HashMap<Vec<usize>, (f64, f64, usize)> = HashMap::new();
+    beams.insert(Vec::new(), (1.0, 0.0, 0));
+
+    for row in probs.iter() {
+        let mut next: HashMap<Vec<usize>, (f64, f64, usize)> = HashMap::new();
+        for (prefix, &(pb, pnb, state)) in beams.iter() {
+            let total = pb + pnb;
+            next.entry(prefix.clone()).or_insert((0.0, 0.0, state)).0 += total * row[blank] as f64;
+
+            let last = prefix.last().cloned();
+            if let Some(t) = last {
+                next.entry(prefix.clone()).or_insert((0.0, 0.0, state)).1 += pnb * row[t] as f64;
+            }
+
+            for (&t, &s) in grammar.states[state].next.iter() {
+                let p = row[t] as f64;
+                // the same character twice needs a blank in between
+                let from = if last == Some(t) { pb } else { total };
+                let mut extended = prefix.clone();
+                extended.push(t);
+                next.entry(extended).or_insert((0.0, 0.0, s)).1 += from * p;
+            }
+        }
+
+        let mut ranked: Vec<(Vec<usize>, (f64, f64, usize))> = next.into_iter().collect();
+        ranked.sort_by(|a, b| {
+            let pa = (a.1).0 + (a.1).1;
+            let pb = (b.1).0 + (b.1).1;
+            pb.partial_cmp(&pa).unwrap_or(Ordering::Equal)
+        });
+        ranked.truncate(BEAM_WIDTH);
+        beams = ranked.into_iter().collect();
+    }
+
+    beams
+        .into_iter()
+        .filter(|(_, (pb, pnb, state))| grammar.states[*state].accepting && pb + pnb > 0.0)
+        .max_by(|a, b| {
+            let pa = (a.1).0 + (a.1).1;
+            let pb = (b.1).0 + (b.1).1;
+            pa.partial_cmp(&pb).unwrap_or(Ordering::Equal)
+        })
+        .map(|(prefix, _)| prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLANK: usize = 0;
+
+    fn dictionary() -> Vec<String> {
+        let mut words: Vec<String> = vec![String::from("-")];
+        words.extend("0123456789,.%+暴击率攻力".chars().map(|c| c.to_string()));
+        words
+    }
+
+    fn token(c: char) -> usize {
+        dictionary()
+            .iter()
+            .position(|w| *w == c.to_string())
+            .unwrap()
+    }
+
+    fn text(tokens: &[usize]) -> String {
+        let dictionary = dictionary();
+        tokens.iter().map(|&t| dictionary[t].as_str()).collect()
+    }
+
+    // one frame per entry, `p` on the character (`-` is blank) and the rest spread out
+    fn frames(chars: &[(char, f32)]) -> Vec<Vec<f32>> {
+        let n = dictionary().len();
+        chars
+            .iter()
+            .map(|&(c, p)| {
+                let mut row = vec![(1.0 - p) / (n - 1) as f32; n];
+                row[token(c)] = p;
+                row
+            })
+            .collect()
+    }
+
+    fn stat_grammar() -> Grammar {
+        let dict = dictionary();
+        Grammar::words(vec!["暴击率", "攻击力"], &dict)
+            .then(Grammar::literal("+", &dict))
+            .then(Grammar::number(&dict))
+    }
+
+    #[test]
+    fn number_accepts_panel_values() {
+        let g = Grammar::number(&dictionary());
+        for s in ["23", "4,780", "3.9%", "46.6%", "311", "1,234,567"].iter() {
+            assert!(g.matches(s), "{}", s);
+        }
+        for s in ["", "4,78", "1234", "3.%", ".5", "46.6%%", "+23", "3,9%"].iter() {
+            assert!(!g.matches(s), "{}", s);
+        }
+    }
+
+    #[test]
+    fn then_joins_stat_name_plus_and_value() {
+        let g = stat_grammar();
+        assert!(g.matches("暴击率+3.9%"));
+        assert!(g.matches("攻击力+311"));
+        assert!(!g.matches("暴击率3.9%"));
+        assert!(!g.matches("暴击率+"));
+        assert!(!g.matches("攻击+311"));
+        // characters the model cannot output never match
+        assert!(!g.matches("生命值+4,780"));
+    }
+
+    #[test]
+    fn beam_search_keeps_the_best_valid_string() {
+        // the greedy path reads "1,23", which has a short thousands group
+        let probs = frames(&[('1', 0.9), (',', 0.5), ('2', 0.9), ('-', 0.9), ('3', 0.9)]);
+        let g = Grammar::number(&dictionary());
+        let tokens = beam_search(&probs, BLANK, &g).unwrap();
+        assert_eq!(text(&tokens), "123");
+    }
+
+    #[test]
+    fn beam_search_follows_the_frames_when_they_are_valid() {
+        let probs = frames(&[
+            ('暴', 0.9),
+            ('击', 0.9),
+            ('率', 0.9),
+            ('+', 0.9),
+            ('1', 0.9),
+            ('-', 0.9),
+            ('1', 0.9),
+            ('.', 0.9),
+            ('7', 0.9),
+            ('%', 0.9),
+        ]);
+        let tokens = beam_search(&probs, BLANK, &stat_grammar()).unwrap();
+        assert_eq!(text(&tokens), "暴击率+11.7%");
+    }
+
+    #[test]
+    fn beam_search_finds_nothing_when_the_grammar_cannot_end() {
+        // too few frames for any stat
+        let probs = frames(&[('暴', 0.9), ('击', 0.9)]);
+        assert_eq!(beam_search(&probs, BLANK, &stat_grammar()), None);
+    }
+}
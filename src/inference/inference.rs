@@ -1,94 +1,405 @@
-use std::collections::HashMap;
-use std::io::Read;
+use std::fs;
 
 use tract_onnx::prelude::*;
-use tract_onnx::Onnx;
-use serde_json::{Result, Value};
+use serde_json::Value;
 
+use crate::common::error::YasError;
+use crate::inference::ctc::{prefix_beam_search, Constraint, Lexicon};
+use crate::inference::grammar::beam_search;
 use crate::common::RawImage;
-use crate::common::utils;
-use image::EncodableLayout;
 
 
 type ModelType = RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
 
 pub struct CRNNModel {
-    model: ModelType,
+    // a plan for single images (the count, bench samples) and one for whole panels,
+    // so that a single image is not padded to a full batch
+    single: ModelType,
+    batched: ModelType,
     index_2_word: Vec<String>,
 
     pub avg_inference_time: f64,
 }
 
 impl CRNNModel {
-    pub fn new(name: String, dict_name: String) -> CRNNModel {
-        // let model = tract_onnx::onnx()
-        //     .model_for_path(String::from("models/") + name.as_str()).unwrap()
-        //     .with_input_fact(0, InferenceFact::dt_shape(f32::datum_type(), tvec!(1, 1, 32, 384))).unwrap()
-        //     .into_optimized().unwrap()
-        //     .into_runnable().unwrap();
-        let pretrained_model = include_bytes!("../../models/model_acc100-epoch45.onnx");
-        // let mut bytes = include_bytes!("../../models/model_training.onnx");
-
-        let model = tract_onnx::onnx()
-            .model_for_read(&mut pretrained_model.as_bytes()).unwrap()
-            .with_input_fact(0, InferenceFact::dt_shape(f32::datum_type(), tvec!(1, 1, 32, 384))).unwrap()
-            .into_optimized().unwrap()
-            .into_runnable().unwrap();
-
-        // let content = utils::read_file_to_string(String::from("models/index_2_word.json"));
-        let content = String::from(include_str!("../../models/index_2_word.json"));
-        let json: Value = serde_json::from_str(content.as_str()).unwrap();
-
-        let mut index_2_word: Vec<String> = Vec::new();
-        let mut i = 0;
-        loop {
-            let word = match json.get(i.to_string()) {
-                Some(x) => x,
-                None => break,
-            };
-            index_2_word.push(word.as_str().unwrap().to_string());
-            i += 1;
-        }
+    // `model_path` and `dict_path` default to the model embedded at compile time,
+    // the dictionary must match the model's output whichever is used
+    pub fn new(model_path: Option<&str>, dict_path: Option<&str>) -> Result<CRNNModel, YasError> {
+        let model_bytes: Vec<u8> = match model_path {
+            Some(path) => fs::read(path)
+                .map_err(|e| YasError::ModelLoad(format!("cannot read {}: {}", path, e)))?,
+            None => include_bytes!("../../models/model_acc100-epoch45.onnx").to_vec(),
+        };
+        let dict_content = match dict_path {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|e| YasError::ModelLoad(format!("cannot read {}: {}", path, e)))?,
+            None => String::from(include_str!("../../models/index_2_word.json")),
+        };
+
+        let single = plan(&model_bytes, 1)?;
+        let batched = plan(&model_bytes, BATCH_SIZE)?;
 
-        CRNNModel {
-            model,
+        let index_2_word = parse_dict(&dict_content)?;
+        let model = CRNNModel {
+            single,
+            batched,
             index_2_word,
 
             avg_inference_time: 0.0,
+        };
+        model.validate()?;
+        Ok(model)
+    }
+
+    // one run of each plan on blank images, the output has to be
+    // (frames, batch, dictionary size)
+    fn validate(&self) -> Result<(), YasError> {
+        for &batch in [1, BATCH_SIZE].iter() {
+            let tensor: Tensor =
+                tract_ndarray::Array4::<f32>::zeros((batch, 1, INPUT_HEIGHT, INPUT_WIDTH)).into();
+            let result = self
+                .plan_for(batch)
+                .run(tvec!(tensor))
+                .map_err(|e| YasError::ModelLoad(e.to_string()))?;
+            let arr = result[0]
+                .to_array_view::<f32>()
+                .map_err(|e| YasError::ModelLoad(e.to_string()))?;
+
+            let shape = arr.shape();
+            if shape.len() != 3 || shape[1] != batch {
+                return Err(YasError::ModelLoad(format!(
+                    "unexpected output shape {:?}",
+                    shape
+                )));
+            }
+            if shape[2] != self.index_2_word.len() {
+                return Err(YasError::ModelLoad(format!(
+                    "dictionary has {} entries but the model outputs {}",
+                    self.index_2_word.len(),
+                    shape[2]
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // the plan for `n` images, one image runs alone and more are padded to BATCH_SIZE
+    fn plan_for(&self, n: usize) -> &ModelType {
+        if n == 1 {
+            &self.single
+        } else {
+            &self.batched
         }
     }
 
-    pub fn inference_string(&self, img: &RawImage) -> String {
-        let tensor: Tensor = tract_ndarray::Array4::from_shape_fn((1, 1, 32, 384), |(_, _, y, x)| {
-            let index = img.w * y as u32 + x as u32;
-            img.data[index as usize]
-        }).into();
+    pub fn inference_string(&self, img: &RawImage) -> Result<String, YasError> {
+        Ok(self.inference_with_confidence(img)?.text)
+    }
+
+    pub fn inference_with_confidence(&self, img: &RawImage) -> Result<Recognition, YasError> {
+        self.inference_constrained(img, Constraint::Free)
+    }
+
+    pub fn lexicon<I, S>(&self, words: I) -> Lexicon
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Lexicon::new(words, &self.index_2_word)
+    }
 
-        let result = self.model.run(tvec!(tensor)).unwrap();
-        let arr = result[0].to_array_view::<f32>().unwrap();
+    pub fn dictionary(&self) -> &[String] {
+        &self.index_2_word
+    }
+
+    pub fn inference_constrained(
+        &self,
+        img: &RawImage,
+        constraint: Constraint,
+    ) -> Result<Recognition, YasError> {
+        Ok(self.inference_batch(&[(img, constraint)])?.remove(0))
+    }
+
+    // runs the network once per BATCH_SIZE images, results are in input order
+    pub fn inference_batch(
+        &self,
+        inputs: &[(&RawImage, Constraint)],
+    ) -> Result<Vec<Recognition>, YasError> {
+        let mut results: Vec<Recognition> = Vec::with_capacity(inputs.len());
+        for chunk in inputs.chunks(BATCH_SIZE) {
+            let images: Vec<&RawImage> = chunk.iter().map(|(img, _)| *img).collect();
+            let probs = self.probabilities(&images)?;
+            for (p, (_, constraint)) in probs.iter().zip(chunk.iter()) {
+                results.push(self.decode(p, *constraint));
+            }
+        }
+        Ok(results)
+    }
+
+    // falls back to the plain best path when no valid word fits the frames
+    fn decode(&self, probs: &[Vec<f32>], constraint: Constraint) -> Recognition {
+        let greedy = self.decode_greedy(probs);
+
+        match constraint {
+            Constraint::Free => greedy,
+            Constraint::Word(lexicon) => {
+                if lexicon.contains(&greedy.text) {
+                    return greedy;
+                }
+                match prefix_beam_search(probs, BLANK, lexicon) {
+                    Some(word) => self.recognition_of(probs, word),
+                    None => greedy,
+                }
+            }
+            Constraint::Grammar(grammar) => {
+                if grammar.matches(&greedy.text) {
+                    return greedy;
+                }
+                match beam_search(probs, BLANK, grammar) {
+                    Some(tokens) => {
+                        let text = tokens
+                            .iter()
+                            .map(|&t| self.index_2_word[t].as_str())
+                            .collect();
+                        self.recognition_of(probs, text)
+                    }
+                    None => greedy,
+                }
+            }
+            Constraint::StatName(lexicon) => {
+                let name = greedy.text.split('+').next().unwrap_or("");
+                if lexicon.contains(name) {
+                    return greedy;
+                }
+                // the name is read from the frames before the `+`
+                let plus = match self.index_2_word.iter().position(|w| w == "+") {
+                    Some(v) => v,
+                    None => return greedy,
+                };
+                let split = match probs.iter().position(|row| argmax(row) == plus) {
+                    Some(v) => v,
+                    None => return greedy,
+                };
+                let word = match prefix_beam_search(&probs[..split], BLANK, lexicon) {
+                    Some(v) => v,
+                    None => return greedy,
+                };
+
+                // the frame before `split` is not a `+`, so the best path from there
+                // emits the same tokens as the rest of `greedy`, one confidence each
+                let rest = self.decode_greedy(&probs[split..]);
+                let mut r = self.recognition_of(&probs[..split], word);
+                r.text.push_str(&rest.text);
+                r.char_confidences.extend(rest.char_confidences);
+                r.confidence = r.char_confidences.iter().cloned().fold(1.0_f32, f32::min);
+                r
+            }
+        }
+    }
+
+    // for each image one probability distribution over the dictionary per frame,
+    // at most BATCH_SIZE images, more than one are zero padded to a full batch
+    fn probabilities(&self, images: &[&RawImage]) -> Result<Vec<Vec<Vec<f32>>>, YasError> {
+        for img in images.iter() {
+            // the pre-processing always resizes to the input size, anything else
+            // would be read with the wrong stride
+            assert!(
+                img.w as usize == INPUT_WIDTH && img.h as usize == INPUT_HEIGHT,
+                "model input must be {}x{}, got {}x{}",
+                INPUT_WIDTH,
+                INPUT_HEIGHT,
+                img.w,
+                img.h
+            );
+        }
+        let batch = if images.len() == 1 { 1 } else { BATCH_SIZE };
+        let tensor: Tensor = tract_ndarray::Array4::from_shape_fn(
+            (batch, 1, INPUT_HEIGHT, INPUT_WIDTH),
+            |(b, _, y, x)| match images.get(b) {
+                Some(img) => img.data[(img.w * y as u32 + x as u32) as usize],
+                None => 0.0,
+            },
+        )
+        .into();
+
+        let result = self
+            .plan_for(images.len())
+            .run(tvec!(tensor))
+            .map_err(|e| YasError::Recognition(e.to_string()))?;
+        let arr = result[0]
+            .to_array_view::<f32>()
+            .map_err(|e| YasError::Recognition(e.to_string()))?;
 
         let shape = arr.shape();
 
-        let mut ans = String::new();
-        let mut last_word = String::new();
-        for i in 0..shape[0] {
-            let mut max_index = 0;
-            let mut max_value = -1.0;
-            for j in 0..self.index_2_word.len() {
-                let value = arr[[i, 0, j]];
-                if value > max_value {
-                    max_value = value;
-                    max_index = j;
+        let mut all: Vec<Vec<Vec<f32>>> = Vec::with_capacity(images.len());
+        for b in 0..images.len() {
+            let mut probs: Vec<Vec<f32>> = Vec::with_capacity(shape[0]);
+            for i in 0..shape[0] {
+                let mut row: Vec<f32> = vec![0.0; self.index_2_word.len()];
+                for j in 0..self.index_2_word.len() {
+                    row[j] = arr[[i, b, j]];
                 }
+                to_probabilities(&mut row);
+                probs.push(row);
             }
+            all.push(probs);
+        }
+        Ok(all)
+    }
+
+    fn decode_greedy(&self, probs: &[Vec<f32>]) -> Recognition {
+        let mut ans = String::new();
+        let mut char_confidences: Vec<f32> = Vec::new();
+        let mut last_word = String::new();
+        for row in probs.iter() {
+            let max_index = argmax(row);
+            let max_value = row[max_index];
             let word = &self.index_2_word[max_index];
             if *word != last_word && word != "-" {
                 ans = ans + word;
+                char_confidences.push(max_value);
+            } else if *word == last_word && word != "-" {
+                // a character spans several frames, keep its best one
+                if let Some(c) = char_confidences.last_mut() {
+                    *c = c.max(max_value);
+                }
             }
 
             last_word = word.clone();
         }
 
-        ans
+        // the weakest character decides, nothing emitted counts as certain
+        let confidence = char_confidences.iter().cloned().fold(1.0_f32, f32::min);
+        Recognition {
+            text: ans,
+            char_confidences,
+            confidence,
+        }
+    }
+
+    // a word chosen by the lexicon has no single path, each character gets the
+    // best probability it reaches in any frame
+    fn recognition_of(&self, probs: &[Vec<f32>], word: String) -> Recognition {
+        let char_confidences: Vec<f32> = word
+            .chars()
+            .map(|c| {
+                let c = c.to_string();
+                match self.index_2_word.iter().position(|w| *w == c) {
+                    Some(t) => probs.iter().map(|row| row[t]).fold(0.0_f32, f32::max),
+                    None => 0.0,
+                }
+            })
+            .collect();
+        let confidence = char_confidences.iter().cloned().fold(1.0_f32, f32::min);
+        Recognition {
+            text: word,
+            char_confidences,
+            confidence,
+        }
+    }
+}
+
+// every text field of an artifact panel goes through the network in one run
+pub const BATCH_SIZE: usize = 9;
+const INPUT_HEIGHT: usize = 32;
+const INPUT_WIDTH: usize = 384;
+
+// the network optimized for a fixed batch size
+fn plan(mut model_bytes: &[u8], batch: usize) -> Result<ModelType, YasError> {
+    tract_onnx::onnx()
+        .model_for_read(&mut model_bytes)
+        .map_err(|e| YasError::ModelLoad(e.to_string()))?
+        .with_input_fact(
+            0,
+            InferenceFact::dt_shape(
+                f32::datum_type(),
+                tvec!(batch, 1, INPUT_HEIGHT, INPUT_WIDTH),
+            ),
+        )
+        .map_err(|e| {
+            YasError::ModelLoad(format!(
+                "input must be Nx1x{}x{}: {}",
+                INPUT_HEIGHT, INPUT_WIDTH, e
+            ))
+        })?
+        .into_optimized()
+        .and_then(|m| m.into_runnable())
+        .map_err(|e| YasError::ModelLoad(e.to_string()))
+}
+
+// {"0": "-", "1": " ", ...}
+fn parse_dict(content: &str) -> Result<Vec<String>, YasError> {
+    let json: Value =
+        serde_json::from_str(content).map_err(|e| YasError::ModelLoad(e.to_string()))?;
+
+    let mut index_2_word: Vec<String> = Vec::new();
+    let mut i = 0;
+    while let Some(word) = json.get(i.to_string()) {
+        let word = match word.as_str() {
+            Some(w) => w,
+            None => return Err(YasError::ModelLoad(format!("invalid dict entry {}", i))),
+        };
+        index_2_word.push(word.to_string());
+        i += 1;
     }
-}
\ No newline at end of file
+
+    if index_2_word.first().map(|w| w.as_str()) != Some("-") {
+        return Err(YasError::ModelLoad(String::from(
+            "dict entry 0 must be the blank \"-\"",
+        )));
+    }
+    Ok(index_2_word)
+}
+
+// index of "-" in index_2_word.json
+const BLANK: usize = 0;
+
+fn argmax(row: &[f32]) -> usize {
+    let mut max_index = 0;
+    let mut max_value = -1.0;
+    for (j, &value) in row.iter().enumerate() {
+        if value > max_value {
+            max_value = value;
+            max_index = j;
+        }
+    }
+    max_index
+}
+
+// a decoded string, with the probability of each character and of the whole string
+#[derive(Clone, Debug)]
+pub struct Recognition {
+    pub text: String,
+    pub char_confidences: Vec<f32>,
+    pub confidence: f32,
+}
+
+impl Recognition {
+    // a field with nothing to read, e.g. a missing 4th substat
+    pub fn empty() -> Recognition {
+        Recognition {
+            text: String::new(),
+            char_confidences: Vec::new(),
+            confidence: 1.0,
+        }
+    }
+}
+
+// the model may end in a softmax or output raw logits, only the latter are normalized
+fn to_probabilities(row: &mut [f32]) {
+    let sum: f32 = row.iter().sum();
+    if row.iter().all(|v| (0.0..=1.0).contains(v)) && (sum - 1.0).abs() < 1e-3 {
+        return;
+    }
+
+    let max = row.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let mut sum = 0.0;
+    for v in row.iter_mut() {
+        *v = (*v - max).exp();
+        sum += *v;
+    }
+    for v in row.iter_mut() {
+        *v /= sum;
+    }
+}
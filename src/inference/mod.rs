@@ -1,2 +1,6 @@
 pub mod pre_process;
-pub mod inference;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod inference;
+pub mod ctc;
+pub mod grammar;
+pub mod bench;
@@ -1,39 +1,92 @@
-use image::imageops::colorops::grayscale;
-use image::{RgbImage, GrayImage, ImageBuffer};
-use image::imageops::resize;
-use log::info;
+use image::{GrayImage, ImageBuffer};
+use serde::{Deserialize, Serialize};
 
 use crate::common::RawImage;
 
+// how the resized field is turned into the model input, the model was trained on
+// `Fixed(0.53)`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Binarization {
+    Fixed(f32),
+    Otsu,
+    // local threshold, for backgrounds that change across the line
+    Sauvola,
+    // the model sees greyscale
+    None,
+}
+
+impl Default for Binarization {
+    fn default() -> Binarization {
+        Binarization::Fixed(0.53)
+    }
+}
+
+impl Binarization {
+    // "fixed", "fixed:0.6", "otsu", "sauvola" or "none"
+    pub fn from_name(name: &str) -> Option<Binarization> {
+        let mut parts = name.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("fixed"), None) => Some(Binarization::default()),
+            (Some("fixed"), Some(v)) => v.parse::<f32>().ok().map(Binarization::Fixed),
+            (Some("otsu"), None) => Some(Binarization::Otsu),
+            (Some("sauvola"), None) => Some(Binarization::Sauvola),
+            (Some("none"), None) => Some(Binarization::None),
+            _ => None,
+        }
+    }
+}
+
 #[inline]
 fn get_index(width: u32, x: u32, y: u32) -> usize {
     (y * width + x) as usize
 }
 
+// the model input, text is scaled to the height and padded on the right
+const OUT_WIDTH: u32 = 384;
+const OUT_HEIGHT: u32 = 32;
+const CROP_THRESHOLD: f32 = 0.7;
+
 pub fn to_gray(raw: Vec<u8>, width: u32, height: u32) -> RawImage {
-    let mut ans: Vec<f32> = vec![0.0; (width * height) as usize];
-    for i in 0..width {
-        for j in 0..height {
-            let x = i;
-            let y = j;
-            let b = raw[((y * width + x) * 4 + 0) as usize];
-            let g = raw[((y * width + x) * 4 + 1) as usize];
-            let r = raw[((y * width + x) * 4 + 2) as usize];
+    let len = (width * height) as usize;
+    let data = raw[..len * 4]
+        .chunks_exact(4)
+        .map(|p| {
+            let b = p[0] as f32 / 255.0;
+            let g = p[1] as f32 / 255.0;
+            let r = p[2] as f32 / 255.0;
+            r * 0.2989 + g * 0.5870 + b * 0.1140
+        })
+        .collect();
 
-            let r = r as f32 / 255.0;
-            let g = g as f32 / 255.0;
-            let b = b as f32 / 255.0;
+    RawImage {
+        data,
+        h: height,
+        w: width,
+    }
+}
 
-            let gray = r as f32 * 0.2989 + g as f32 * 0.5870 + b as f32 * 0.1140;
-            let index = get_index(width, i, j);
-            ans[index] = gray;
+fn min_max(data: &[f32]) -> (f32, f32) {
+    let mut max: f32 = 0.0;
+    let mut min: f32 = 256.0;
+    for &p in data.iter() {
+        if p > max {
+            max = p;
+        }
+        if p < min {
+            min = p;
         }
     }
+    (min, max)
+}
 
-    RawImage {
-        data: ans,
-        h: height,
-        w: width,
+#[inline]
+fn normalized(p: f32, min: f32, max: f32, inverse: bool) -> f32 {
+    let v = (p - min) / (max - min);
+    if inverse {
+        1.0 - v
+    } else {
+        v
     }
 }
 
@@ -45,110 +98,115 @@ pub fn normalize(im: &mut RawImage, auto_inverse: bool) -> bool {
         return false;
     }
 
-    let data = &mut im.data;
-    // info!("in normalize: width = {}, height = {}", width, height);
-
-    let mut max: f32 = 0.0;
-    let mut min: f32 = 256.0;
-
-    for i in 0..width {
-        for j in 0..height {
-            let index = get_index(width, i, j);
-            // info!("i = {}, j = {}, width = {}, index = {}", i, j, width, index);
-            let p = data[index];
-            if p > max {
-                max = p;
-            }
-            if p < min {
-                min = p;
-            }
-        }
-    }
-
+    let (min, max) = min_max(&im.data);
     if max == min {
         return false;
     }
 
-    let flag_pixel = data[get_index(width, width - 1, height - 1)];
-    let flag_pixel = (flag_pixel - min) / (max - min);
-
-    for i in 0..width {
-        for j in 0..height {
-            let index = get_index(width, i, j);
-            let p = data[index];
-            data[index] = (p - min) / (max - min);
-            if auto_inverse && flag_pixel > 0.5 {
-                // println!("123");
-                data[index] = 1.0 - data[index];
-            }
-            // if data[index] < 0.6 {
-            //     data[index] = 0.0;
-            // }
-        }
+    let inverse = auto_inverse && flag_is_bright(&im.data, width, height, min, max);
+    for p in im.data.iter_mut() {
+        *p = normalized(*p, min, max, inverse);
     }
 
     true
 }
 
-pub fn crop(im: &RawImage) -> RawImage {
-    let width = im.w;
-    let height = im.h;
+// the background decides the inversion, text should come out bright. The fixed
+// threshold and greyscale keep the original rule of looking at the bottom-right pixel
+fn flag_is_bright(data: &[f32], width: u32, height: u32, min: f32, max: f32) -> bool {
+    let flag = data[get_index(width, width - 1, height - 1)];
+    (flag - min) / (max - min) > 0.5
+}
 
-    let mut min_col = width - 1;
-    let mut max_col = 0;
-    let mut min_row = height - 1;
-    let mut max_row = 0_u32;
-
-    for i in 0..width {
-        for j in 0..height {
-            let index = get_index(width, i, j);
-            let p = im.data[index];
-            if p > 0.7 {
-                if i < min_col {
-                    min_col = i;
-                }
-                if i > max_col {
-                    max_col = i;
-                }
-                break;
-            }
+// for the adaptive thresholds: the border is mostly background, so the most common of
+// its normalised values is taken
+fn border_is_bright(data: &[f32], width: u32, height: u32, min: f32, max: f32) -> bool {
+    const BINS: usize = 16;
+    let mut histogram = [0_u32; BINS];
+    let mut add = |x: u32, y: u32| {
+        let p = (data[get_index(width, x, y)] - min) / (max - min);
+        let bin = ((p * BINS as f32) as usize).min(BINS - 1);
+        histogram[bin] += 1;
+    };
+    for x in 0..width {
+        add(x, 0);
+        if height > 1 {
+            add(x, height - 1);
         }
     }
-
-    for j in 0..height {
-        for i in 0..width {
-            let index = get_index(width, i, j);
-            let p = im.data[index];
-            if p > 0.7 {
-                if j < min_row {
-                    min_row = j;
-                }
-                if j > max_row {
-                    max_row = j;
-                }
-                break;
-            }
+    for y in 1..height.saturating_sub(1) {
+        add(0, y);
+        if width > 1 {
+            add(width - 1, y);
         }
     }
 
-    let new_height = max_row - min_row + 1;
-    let new_width = max_col - min_col + 1;
+    let mut mode = 0;
+    for bin in 1..BINS {
+        if histogram[bin] > histogram[mode] {
+            mode = bin;
+        }
+    }
+    mode >= BINS / 2
+}
 
-    let mut ans: Vec<f32> = vec![0.0; (new_width * new_height) as usize];
+// (left, top, right, bottom) of the pixels `bright` accepts, inclusive
+fn text_bounds<F>(data: &[f32], width: u32, bright: F) -> Option<(u32, u32, u32, u32)>
+where
+    F: Fn(f32) -> bool,
+{
+    if width == 0 {
+        return None;
+    }
 
-    for i in min_col..=max_col {
-        for j in min_row..=max_row {
-            let index = get_index(width, i, j);
-            let new_index = get_index(new_width, i - min_col, j - min_row);
-            ans[new_index] = im.data[index];
-        }
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (y, row) in data.chunks_exact(width as usize).enumerate() {
+        let left = match row.iter().position(|&p| bright(p)) {
+            Some(v) => v as u32,
+            None => continue,
+        };
+        let right = row.iter().rposition(|&p| bright(p)).unwrap() as u32;
+        let y = y as u32;
+        bounds = Some(match bounds {
+            Some((l, t, r, _)) => (l.min(left), t, r.max(right), y),
+            None => (left, y, right, y),
+        });
     }
+    bounds
+}
 
-    RawImage {
-        data: ans,
-        w: new_width,
-        h: new_height,
+// copies the inclusive `bounds` through `f` into `out`, returns its width and height
+fn copy_region<F>(
+    data: &[f32],
+    width: u32,
+    bounds: (u32, u32, u32, u32),
+    out: &mut Vec<f32>,
+    f: F,
+) -> (u32, u32)
+where
+    F: Fn(f32) -> f32,
+{
+    let (left, top, right, bottom) = bounds;
+    out.clear();
+    for row in data
+        .chunks_exact(width as usize)
+        .skip(top as usize)
+        .take((bottom - top + 1) as usize)
+    {
+        out.extend(row[left as usize..=right as usize].iter().map(|&p| f(p)));
     }
+    (right - left + 1, bottom - top + 1)
+}
+
+// an image without bright pixels gives an empty one
+pub fn crop(im: &RawImage) -> RawImage {
+    let mut data: Vec<f32> = Vec::new();
+    let (w, h) = match text_bounds(&im.data, im.w, |p| p > CROP_THRESHOLD) {
+        Some(bounds) => copy_region(&im.data, im.w, bounds, &mut data, |p| p),
+        None => (0, 0),
+    };
+
+    RawImage { data, w, h }
 }
 
 pub fn raw_to_img(im: &RawImage) -> GrayImage {
@@ -156,7 +214,7 @@ pub fn raw_to_img(im: &RawImage) -> GrayImage {
     let height = im.h;
     let data = &im.data;
 
-    let img = ImageBuffer::from_fn(width, height, |x, y| {
+    ImageBuffer::from_fn(width, height, |x, y| {
         let index = get_index(width, x, y);
         let p = data[index];
         let pixel = (p * 255.0) as u32;
@@ -166,9 +224,7 @@ pub fn raw_to_img(im: &RawImage) -> GrayImage {
             pixel as u8
         };
         image::Luma([pixel])
-    });
-
-    img
+    })
 }
 
 pub fn uint8_raw_to_img(im: &RawImage) -> GrayImage {
@@ -176,7 +232,7 @@ pub fn uint8_raw_to_img(im: &RawImage) -> GrayImage {
     let height = im.h;
     let data = &im.data;
 
-    let img = ImageBuffer::from_fn(width, height, |x, y| {
+    ImageBuffer::from_fn(width, height, |x, y| {
         let index = get_index(width, x, y);
         let pixel =  data[index] as u32;
         let pixel: u8 = if pixel > 255 {
@@ -185,57 +241,347 @@ pub fn uint8_raw_to_img(im: &RawImage) -> GrayImage {
             pixel as u8
         };
         image::Luma([pixel])
-    });
+    })
+}
 
-    img
+struct Span {
+    // first input pixel
+    left: usize,
+    // range in the weights
+    start: usize,
+    end: usize,
+    sum: f32,
 }
 
-pub fn resize_and_pad(im: &RawImage) -> RawImage {
-    let w = im.w;
-    let h = im.h;
+#[inline]
+fn triangle(x: f32) -> f32 {
+    if x.abs() < 1.0 {
+        1.0 - x.abs()
+    } else {
+        0.0
+    }
+}
 
-    let new_width = (32.0 / h as f64 * w as f64) as u32;
+// the first `count` output pixels of a `len` to `new_len` triangle filter along one axis,
+// computed like `image::imageops::resize` does
+fn triangle_spans(
+    len: u32,
+    new_len: u32,
+    count: u32,
+    weights: &mut Vec<f32>,
+    spans: &mut Vec<Span>,
+) {
+    weights.clear();
+    spans.clear();
+
+    let ratio = len as f32 / new_len as f32;
+    let sratio = if ratio < 1.0 { 1.0 } else { ratio };
+    for out in 0..count {
+        let input = (out as f32 + 0.5) * ratio;
+        let left = ((input - sratio).floor() as i64).max(0).min(len as i64 - 1);
+        let right = ((input + sratio).ceil() as i64)
+            .max(left + 1)
+            .min(len as i64);
+
+        let input = input - 0.5;
+        let start = weights.len();
+        let mut sum = 0.0;
+        for i in left..right {
+            let w = triangle((i as f32 - input) / sratio);
+            weights.push(w);
+            sum += w;
+        }
+        spans.push(Span {
+            left: left as usize,
+            start,
+            end: weights.len(),
+            sum,
+        });
+    }
+}
 
-    let img = raw_to_img(&im);
-    let img = resize(&img, new_width, 32, image::imageops::FilterType::Triangle);
+#[inline]
+fn to_u8(t: f32) -> u8 {
+    t.clamp(0.0, 255.0).round() as u8
+}
 
-    let mut data: Vec<f32> = vec![0.0; 32 * 384];
-    for i in 0..new_width.min(384) {
-        for j in 0..32_u32 {
-            let pixel = (img.get_pixel(i, j).0[0] as f32) / 255.0;
-            data[(j * 384 + i) as usize] = pixel;
+// `image::imageops::resize` with `FilterType::Triangle` on the `raw_to_img` of the input,
+// vertical pass first and rounded to u8 after each pass, so the output is bit-identical
+#[derive(Default)]
+struct Resampler {
+    pixels: Vec<u8>,
+    rows: Vec<u8>,
+    acc: Vec<f32>,
+    weights: Vec<f32>,
+    spans: Vec<Span>,
+}
+
+impl Resampler {
+    // `out` is OUT_WIDTH x OUT_HEIGHT
+    fn resize_and_pad(&mut self, data: &[f32], w: u32, h: u32, out: &mut [f32]) {
+        for p in out.iter_mut() {
+            *p = 0.0;
+        }
+        if w == 0 || h == 0 {
+            return;
+        }
+        let new_width = (OUT_HEIGHT as f64 / h as f64 * w as f64) as u32;
+        // columns past the padding are never computed
+        let columns = new_width.min(OUT_WIDTH);
+        if columns == 0 {
+            return;
+        }
+        let w = w as usize;
+
+        self.pixels.clear();
+        self.pixels
+            .extend(data.iter().map(|&p| ((p * 255.0) as u32).min(255) as u8));
+
+        triangle_spans(
+            h,
+            OUT_HEIGHT,
+            OUT_HEIGHT,
+            &mut self.weights,
+            &mut self.spans,
+        );
+        self.rows.clear();
+        for span in self.spans.iter() {
+            self.acc.clear();
+            self.acc.resize(w, 0.0);
+            for (k, &weight) in self.weights[span.start..span.end].iter().enumerate() {
+                let row = &self.pixels[(span.left + k) * w..(span.left + k + 1) * w];
+                for (t, &p) in self.acc.iter_mut().zip(row.iter()) {
+                    *t += p as f32 * weight;
+                }
+            }
+            let sum = span.sum;
+            self.rows.extend(self.acc.iter().map(|&t| to_u8(t / sum)));
+        }
+
+        triangle_spans(
+            w as u32,
+            new_width,
+            columns,
+            &mut self.weights,
+            &mut self.spans,
+        );
+        for (row, out_row) in self
+            .rows
+            .chunks_exact(w)
+            .zip(out.chunks_exact_mut(OUT_WIDTH as usize))
+        {
+            for (span, o) in self.spans.iter().zip(out_row.iter_mut()) {
+                let mut t = 0.0;
+                for (k, &weight) in self.weights[span.start..span.end].iter().enumerate() {
+                    t += row[span.left + k] as f32 * weight;
+                }
+                *o = to_u8(t / span.sum) as f32 / 255.0;
+            }
         }
     }
+}
+
+pub fn resize_and_pad(im: &RawImage) -> RawImage {
+    let mut data: Vec<f32> = vec![0.0; (OUT_WIDTH * OUT_HEIGHT) as usize];
+    Resampler::default().resize_and_pad(&im.data, im.w, im.h, &mut data);
 
     RawImage {
         data,
-        w: 384,
-        h: 32,
+        w: OUT_WIDTH,
+        h: OUT_HEIGHT,
     }
 }
 
-pub fn pre_process(im: RawImage) -> Option<RawImage> {
-    let mut im = im;
-    if !normalize(&mut im, true) {
-        return None;
+// buffers for preprocessing one field after another without allocating, e.g. one per
+// recognizer thread. The output is the one of `pre_process_with`
+#[derive(Default)]
+pub struct PreProcessor {
+    cropped: Vec<f32>,
+    resampler: Resampler,
+    integral: Vec<f64>,
+    integral_sq: Vec<f64>,
+}
+
+impl PreProcessor {
+    pub fn new() -> PreProcessor {
+        PreProcessor::default()
     }
-    let mut im = crop(&im);
-    normalize(&mut im, false);
-
-    let mut im = resize_and_pad(&im);
-    for i in 0..im.w {
-        for j in 0..im.h {
-            let index = get_index(im.w, i, j);
-            let p = im.data[index];
-            if p < 0.53 {
-                im.data[index] = 0.0;
-            } else {
-                im.data[index] = 1.0;
+
+    // `out` becomes the model input, false when there is nothing to read
+    pub fn process_into(
+        &mut self,
+        im: &RawImage,
+        binarization: Binarization,
+        out: &mut RawImage,
+    ) -> bool {
+        let width = im.w;
+        let height = im.h;
+        if width == 0 || height == 0 {
+            return false;
+        }
+
+        let (min, max) = min_max(&im.data);
+        if max == min {
+            return false;
+        }
+        let inverse = match binarization {
+            Binarization::Otsu | Binarization::Sauvola => {
+                border_is_bright(&im.data, width, height, min, max)
+            }
+            _ => flag_is_bright(&im.data, width, height, min, max),
+        };
+
+        // normalised values are only computed for the bounds and the text itself
+        let bright = |p: f32| normalized(p, min, max, inverse) > CROP_THRESHOLD;
+        let bounds = match text_bounds(&im.data, width, bright) {
+            Some(v) => v,
+            None => return false,
+        };
+        let (w, h) = copy_region(&im.data, width, bounds, &mut self.cropped, |p| {
+            normalized(p, min, max, inverse)
+        });
+
+        let (min, max) = min_max(&self.cropped);
+        if max != min {
+            for p in self.cropped.iter_mut() {
+                *p = normalized(*p, min, max, false);
+            }
+        }
+
+        out.w = OUT_WIDTH;
+        out.h = OUT_HEIGHT;
+        out.data.resize((OUT_WIDTH * OUT_HEIGHT) as usize, 0.0);
+        self.resampler
+            .resize_and_pad(&self.cropped, w, h, &mut out.data);
+
+        match binarization {
+            Binarization::Fixed(threshold) => threshold_at(&mut out.data, threshold),
+            Binarization::Otsu => {
+                let threshold = otsu_threshold(&out.data);
+                threshold_at(&mut out.data, threshold);
             }
+            Binarization::Sauvola => sauvola(
+                &mut out.data,
+                OUT_WIDTH as usize,
+                OUT_HEIGHT as usize,
+                &mut self.integral,
+                &mut self.integral_sq,
+            ),
+            Binarization::None => (),
+        }
+
+        true
+    }
+
+    pub fn process(&mut self, im: &RawImage, binarization: Binarization) -> Option<RawImage> {
+        let mut out = RawImage::default();
+        if self.process_into(im, binarization, &mut out) {
+            Some(out)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn pre_process(im: RawImage) -> Option<RawImage> {
+    pre_process_with(im, Binarization::default())
+}
+
+pub fn pre_process_with(im: RawImage, binarization: Binarization) -> Option<RawImage> {
+    PreProcessor::new().process(&im, binarization)
+}
+
+fn threshold_at(data: &mut [f32], threshold: f32) {
+    for p in data.iter_mut() {
+        *p = if *p < threshold { 0.0 } else { 1.0 };
+    }
+}
+
+// the threshold that maximises the variance between the two classes
+pub fn otsu_threshold(data: &[f32]) -> f32 {
+    const BINS: usize = 256;
+    let mut histogram = [0_u32; BINS];
+    for &p in data.iter() {
+        let bin = ((p.max(0.0) * (BINS - 1) as f32).round() as usize).min(BINS - 1);
+        histogram[bin] += 1;
+    }
+
+    let total = data.len() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| i as f64 * c as f64)
+        .sum();
+    let mut sum_below = 0.0;
+    let mut count_below = 0.0;
+    let mut best = 0;
+    let mut best_variance = -1.0;
+    for (i, &c) in histogram.iter().enumerate() {
+        count_below += c as f64;
+        sum_below += i as f64 * c as f64;
+        let count_above = total - count_below;
+        if count_below == 0.0 || count_above == 0.0 {
+            continue;
+        }
+        let mean_below = sum_below / count_below;
+        let mean_above = (sum - sum_below) / count_above;
+        let variance = count_below * count_above * (mean_below - mean_above).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = i;
         }
     }
 
-    Some(im)
+    // pixels above bin `best` are foreground
+    (best as f32 + 0.5) / (BINS - 1) as f32
+}
+
+const SAUVOLA_RADIUS: i64 = 7;
+const SAUVOLA_K: f64 = 0.2;
+const SAUVOLA_R: f64 = 0.5;
+
+// Sauvola's threshold over a (2r+1)^2 window, computed on the inverted image
+// because it is meant for dark text and ours is bright. `sum` and `sum_sq` are
+// scratch space for the integral images
+fn sauvola(data: &mut [f32], w: usize, h: usize, sum: &mut Vec<f64>, sum_sq: &mut Vec<f64>) {
+    // integral images of the inverted values and their squares, one row and column of padding
+    sum.clear();
+    sum.resize((w + 1) * (h + 1), 0.0);
+    sum_sq.clear();
+    sum_sq.resize((w + 1) * (h + 1), 0.0);
+    for y in 0..h {
+        let mut row = 0.0;
+        let mut row_sq = 0.0;
+        for x in 0..w {
+            let v = 1.0 - data[y * w + x] as f64;
+            row += v;
+            row_sq += v * v;
+            sum[(y + 1) * (w + 1) + x + 1] = sum[y * (w + 1) + x + 1] + row;
+            sum_sq[(y + 1) * (w + 1) + x + 1] = sum_sq[y * (w + 1) + x + 1] + row_sq;
+        }
+    }
+    let area = |table: &[f64], x0: usize, y0: usize, x1: usize, y1: usize| {
+        table[y1 * (w + 1) + x1] - table[y0 * (w + 1) + x1] - table[y1 * (w + 1) + x0]
+            + table[y0 * (w + 1) + x0]
+    };
+
+    // a pixel only depends on itself and the integral images, so it is overwritten in place
+    for y in 0..h {
+        for x in 0..w {
+            let x0 = (x as i64 - SAUVOLA_RADIUS).max(0) as usize;
+            let y0 = (y as i64 - SAUVOLA_RADIUS).max(0) as usize;
+            let x1 = (x as i64 + SAUVOLA_RADIUS + 1).min(w as i64) as usize;
+            let y1 = (y as i64 + SAUVOLA_RADIUS + 1).min(h as i64) as usize;
+            let n = ((x1 - x0) * (y1 - y0)) as f64;
+
+            let mean = area(sum, x0, y0, x1, y1) / n;
+            let variance = (area(sum_sq, x0, y0, x1, y1) / n - mean * mean).max(0.0);
+            let threshold = mean * (1.0 + SAUVOLA_K * (variance.sqrt() / SAUVOLA_R - 1.0));
+
+            let v = 1.0 - data[y * w + x] as f64;
+            data[y * w + x] = if v <= threshold { 1.0 } else { 0.0 };
+        }
+    }
 }
 
 pub fn image_to_raw(im: GrayImage) -> RawImage {
@@ -256,4 +602,413 @@ pub fn image_to_raw(im: GrayImage) -> RawImage {
         w,
         h,
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::imageops::{resize, FilterType};
+
+    // deterministic noise in [0, 1)
+    fn noise(n: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..n)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 8) as f32 / (1 << 24) as f32
+            })
+            .collect()
+    }
+
+    // the column-major implementations this module replaced, the output must not change
+    mod old {
+        use super::*;
+
+        pub fn to_gray(raw: &[u8], width: u32, height: u32) -> Vec<f32> {
+            let mut ans: Vec<f32> = vec![0.0; (width * height) as usize];
+            for i in 0..width {
+                for j in 0..height {
+                    let b = raw[((j * width + i) * 4) as usize];
+                    let g = raw[((j * width + i) * 4 + 1) as usize];
+                    let r = raw[((j * width + i) * 4 + 2) as usize];
+                    let r = r as f32 / 255.0;
+                    let g = g as f32 / 255.0;
+                    let b = b as f32 / 255.0;
+                    ans[get_index(width, i, j)] = r * 0.2989 + g * 0.5870 + b * 0.1140;
+                }
+            }
+            ans
+        }
+
+        pub fn normalize(im: &mut RawImage, auto_inverse: bool) -> bool {
+            let (width, height) = (im.w, im.h);
+            let data = &mut im.data;
+            let mut max: f32 = 0.0;
+            let mut min: f32 = 256.0;
+            for i in 0..width {
+                for j in 0..height {
+                    let p = data[get_index(width, i, j)];
+                    if p > max {
+                        max = p;
+                    }
+                    if p < min {
+                        min = p;
+                    }
+                }
+            }
+            if max == min {
+                return false;
+            }
+
+            let flag_pixel = data[get_index(width, width - 1, height - 1)];
+            let flag_pixel = (flag_pixel - min) / (max - min);
+            for i in 0..width {
+                for j in 0..height {
+                    let index = get_index(width, i, j);
+                    data[index] = (data[index] - min) / (max - min);
+                    if auto_inverse && flag_pixel > 0.5 {
+                        data[index] = 1.0 - data[index];
+                    }
+                }
+            }
+            true
+        }
+
+        pub fn crop(im: &RawImage) -> RawImage {
+            let (width, height) = (im.w, im.h);
+            let mut min_col = width - 1;
+            let mut max_col = 0;
+            let mut min_row = height - 1;
+            let mut max_row = 0_u32;
+            for i in 0..width {
+                for j in 0..height {
+                    if im.data[get_index(width, i, j)] > 0.7 {
+                        min_col = min_col.min(i);
+                        max_col = max_col.max(i);
+                        break;
+                    }
+                }
+            }
+            for j in 0..height {
+                for i in 0..width {
+                    if im.data[get_index(width, i, j)] > 0.7 {
+                        min_row = min_row.min(j);
+                        max_row = max_row.max(j);
+                        break;
+                    }
+                }
+            }
+
+            let new_height = max_row - min_row + 1;
+            let new_width = max_col - min_col + 1;
+            let mut ans: Vec<f32> = vec![0.0; (new_width * new_height) as usize];
+            for i in min_col..=max_col {
+                for j in min_row..=max_row {
+                    ans[get_index(new_width, i - min_col, j - min_row)] =
+                        im.data[get_index(width, i, j)];
+                }
+            }
+            RawImage {
+                data: ans,
+                w: new_width,
+                h: new_height,
+            }
+        }
+
+        pub fn resize_and_pad(im: &RawImage) -> RawImage {
+            let new_width = (32.0 / im.h as f64 * im.w as f64) as u32;
+            let img = resize(&raw_to_img(im), new_width, 32, FilterType::Triangle);
+
+            let mut data: Vec<f32> = vec![0.0; 32 * 384];
+            for i in 0..new_width.min(384) {
+                for j in 0..32_u32 {
+                    data[(j * 384 + i) as usize] = img.get_pixel(i, j).0[0] as f32 / 255.0;
+                }
+            }
+            RawImage {
+                data,
+                w: 384,
+                h: 32,
+            }
+        }
+
+        pub fn pre_process(im: &RawImage) -> Option<RawImage> {
+            let mut im = RawImage {
+                data: im.data.clone(),
+                w: im.w,
+                h: im.h,
+            };
+            if !normalize(&mut im, true) {
+                return None;
+            }
+            let mut im = crop(&im);
+            normalize(&mut im, false);
+            let mut im = resize_and_pad(&im);
+            for p in im.data.iter_mut() {
+                *p = if *p < 0.53 { 0.0 } else { 1.0 };
+            }
+            Some(im)
+        }
+    }
+
+    #[test]
+    fn resize_is_bit_identical_to_image_resize() {
+        // upscaled, downscaled, odd sizes, the output height and wider than the padding
+        let sizes = [
+            (20, 10),
+            (201, 50),
+            (37, 17),
+            (333, 47),
+            (45, 32),
+            (900, 20),
+            (3, 1),
+        ];
+        for (k, &(w, h)) in sizes.iter().enumerate() {
+            let im = RawImage {
+                data: noise((w * h) as usize, k as u32 + 1),
+                w,
+                h,
+            };
+            assert_eq!(
+                resize_and_pad(&im).data,
+                old::resize_and_pad(&im).data,
+                "{}x{}",
+                w,
+                h
+            );
+        }
+    }
+
+    #[test]
+    fn to_gray_and_normalize_are_unchanged() {
+        let (w, h) = (13, 7);
+        let raw: Vec<u8> = noise((w * h * 4) as usize, 7)
+            .iter()
+            .map(|&p| (p * 256.0) as u8)
+            .collect();
+        let gray = to_gray(raw.clone(), w, h);
+        assert_eq!(gray.data, old::to_gray(&raw, w, h));
+
+        // noise puts anything at the bottom-right pixel, so some of these are inverted
+        let mut inverted = 0;
+        for seed in 0..20 {
+            let data = noise((w * h) as usize, seed);
+            let flag = data[(w * h - 1) as usize];
+            let (min, max) = min_max(&data);
+            if (flag - min) / (max - min) > 0.5 {
+                inverted += 1;
+            }
+            for &auto_inverse in [false, true].iter() {
+                let mut im = RawImage {
+                    data: data.clone(),
+                    w,
+                    h,
+                };
+                let mut expected = RawImage {
+                    data: data.clone(),
+                    w,
+                    h,
+                };
+                assert_eq!(
+                    normalize(&mut im, auto_inverse),
+                    old::normalize(&mut expected, auto_inverse)
+                );
+                assert_eq!(im.data, expected.data, "seed {} {}", seed, auto_inverse);
+            }
+        }
+        assert!(inverted > 0 && inverted < 20);
+    }
+
+    // bright text on a dark background, or the other way round. With `corner_text` a
+    // stroke runs into the bottom-right pixel, which the fixed threshold inverts by
+    fn text_field(w: u32, h: u32, dark_text: bool, corner_text: bool, seed: u32) -> RawImage {
+        let noise = noise((w * h) as usize, seed);
+        let data = (0..(w * h) as usize)
+            .map(|i| {
+                let (x, y) = (i as u32 % w, i as u32 / w);
+                let is_text =
+                    (y > h / 4 && y < h * 3 / 4 && x > w / 8 && x < w * 7 / 8 && x % 6 < 3)
+                        || (corner_text && x + 2 >= w && y > h / 2);
+                let v = if is_text {
+                    0.75 + 0.25 * noise[i]
+                } else {
+                    0.2 * noise[i]
+                };
+                if dark_text {
+                    1.0 - v
+                } else {
+                    v
+                }
+            })
+            .collect();
+        RawImage { data, w, h }
+    }
+
+    #[test]
+    fn pre_process_is_bit_identical_to_the_old_pipeline() {
+        let mut pre_processor = PreProcessor::new();
+        for (k, &(w, h, dark_text, corner_text)) in [
+            (120, 20, false, false),
+            (77, 33, false, false),
+            (300, 41, true, false),
+            (31, 9, true, false),
+            (120, 20, false, true),
+            (95, 27, true, true),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let field = text_field(w, h, dark_text, corner_text, k as u32 + 11);
+            if corner_text {
+                // the border histogram would invert the other way
+                let (min, max) = min_max(&field.data);
+                assert_ne!(
+                    flag_is_bright(&field.data, w, h, min, max),
+                    border_is_bright(&field.data, w, h, min, max)
+                );
+            }
+            let expected = old::pre_process(&field).unwrap();
+            let out = pre_processor
+                .process(&field, Binarization::default())
+                .unwrap();
+            assert_eq!(out.data, expected.data, "{}x{}", w, h);
+        }
+    }
+
+    // bright vertical strokes on a background getting brighter from left to right,
+    // the strokes are `contrast` above the background
+    fn strokes_on_gradient(w: usize, h: usize, contrast: f32) -> (Vec<f32>, Vec<bool>) {
+        let mut data = vec![0.0; w * h];
+        let mut text = vec![false; w * h];
+        for y in 0..h {
+            for x in 0..w {
+                let background = 0.1 + 0.5 * x as f32 / w as f32;
+                let is_text = (4..h - 4).contains(&y) && x % 12 >= 5 && x % 12 < 8;
+                data[y * w + x] = if is_text {
+                    background + contrast
+                } else {
+                    background
+                };
+                text[y * w + x] = is_text;
+            }
+        }
+        (data, text)
+    }
+
+    #[test]
+    fn otsu_splits_two_classes() {
+        let mut data: Vec<f32> = Vec::new();
+        for i in 0..300 {
+            data.push(0.15 + (i % 7) as f32 * 0.01);
+        }
+        for i in 0..40 {
+            data.push(0.8 + (i % 5) as f32 * 0.02);
+        }
+        let threshold = otsu_threshold(&data);
+        assert!(threshold > 0.21 && threshold < 0.8, "{}", threshold);
+
+        threshold_at(&mut data, threshold);
+        assert!(data[..300].iter().all(|&p| p == 0.0));
+        assert!(data[300..].iter().all(|&p| p == 1.0));
+    }
+
+    #[test]
+    fn otsu_of_a_flat_image_keeps_it_in_one_class() {
+        let mut data = vec![0.4; 100];
+        let threshold = otsu_threshold(&data);
+        threshold_at(&mut data, threshold);
+        assert!(data.iter().all(|&p| p == data[0]));
+    }
+
+    #[test]
+    fn sauvola_follows_a_changing_background() {
+        let (w, h) = (96, 24);
+        let (mut data, text) = strokes_on_gradient(w, h, 0.3);
+
+        // a fixed threshold takes the bright end of the background for text
+        let mut fixed = data.clone();
+        threshold_at(&mut fixed, 0.53);
+        assert!(fixed.iter().zip(text.iter()).any(|(&p, &t)| p == 1.0 && !t));
+
+        sauvola(&mut data, w, h, &mut Vec::new(), &mut Vec::new());
+        for (i, (&p, &t)) in data.iter().zip(text.iter()).enumerate() {
+            assert_eq!(p == 1.0, t, "pixel ({}, {})", i % w, i / w);
+        }
+    }
+
+    #[test]
+    fn binarized_fields_are_black_and_white() {
+        let (w, h) = (96, 24);
+        let (data, _) = strokes_on_gradient(w, h, 0.3);
+        let field = RawImage {
+            data,
+            w: w as u32,
+            h: h as u32,
+        };
+
+        let mut pre_processor = PreProcessor::new();
+        for &b in [
+            Binarization::default(),
+            Binarization::Otsu,
+            Binarization::Sauvola,
+        ]
+        .iter()
+        {
+            let out = pre_processor.process(&field, b).unwrap();
+            assert_eq!((out.w, out.h), (OUT_WIDTH, OUT_HEIGHT));
+            assert!(out.data.iter().all(|&p| p == 0.0 || p == 1.0), "{:?}", b);
+            assert!(out.data.contains(&1.0), "{:?}", b);
+        }
+        let grey = pre_processor.process(&field, Binarization::None).unwrap();
+        assert!(grey.data.iter().any(|&p| p > 0.0 && p < 1.0));
+    }
+
+    #[test]
+    fn only_adaptive_thresholds_look_at_the_border() {
+        // bright text on a dark background, with a stroke reaching the bottom-right pixel
+        let (w, h) = (40, 12);
+        let data: Vec<f32> = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let text = (y > 3 && y < 9 && x % 5 < 2) || (x == w - 1 && y > 8);
+                if text {
+                    0.9
+                } else {
+                    0.1
+                }
+            })
+            .collect();
+        assert!(flag_is_bright(&data, w, h, 0.1, 0.9));
+        assert!(!border_is_bright(&data, w, h, 0.1, 0.9));
+
+        let field = RawImage { data, w, h };
+        let mut pre_processor = PreProcessor::new();
+        let mut white = |b: Binarization| {
+            let out = pre_processor.process(&field, b).unwrap();
+            out.data.iter().filter(|&&p| p == 1.0).count()
+        };
+        // the fixed threshold inverts as before and the background comes out white
+        let fixed = white(Binarization::default());
+        let otsu = white(Binarization::Otsu);
+        assert!(fixed > 2 * otsu, "{} {}", fixed, otsu);
+    }
+
+    #[test]
+    fn binarization_names() {
+        assert_eq!(
+            Binarization::from_name("fixed"),
+            Some(Binarization::Fixed(0.53))
+        );
+        assert_eq!(
+            Binarization::from_name("fixed:0.6"),
+            Some(Binarization::Fixed(0.6))
+        );
+        assert_eq!(Binarization::from_name("otsu"), Some(Binarization::Otsu));
+        assert_eq!(
+            Binarization::from_name("sauvola"),
+            Some(Binarization::Sauvola)
+        );
+        assert_eq!(Binarization::from_name("none"), Some(Binarization::None));
+        assert_eq!(Binarization::from_name("fixed:x"), None);
+        assert_eq!(Binarization::from_name("otsu:1"), None);
+    }
+}
@@ -0,0 +1,522 @@
+use image::{Rgb, RgbImage};
+
+use crate::common::color::Color;
+use crate::info::layout::Layouts;
+use crate::info::window_info::{Rect, WindowInfo};
+
+// squared colour distance from the background above which a pixel belongs to a card
+const CARD_CONTRAST: u32 = 40 * 40;
+// squared distance to the rarity palette of the panel header
+const HEADER_DISTANCE: u32 = 40 * 40;
+const TEXT_CONTRAST: u32 = 100 * 100;
+// the flag is sampled at most this far above the first row, in card heights
+const FLAG_ABOVE: f64 = 0.085;
+
+// same palette as `star_from_color`
+const STAR_COLORS: [Color; 5] = [
+    Color(113, 119, 139),
+    Color(42, 143, 114),
+    Color(81, 127, 203),
+    Color(161, 86, 224),
+    Color(188, 105, 50),
+];
+
+// screenshot pixels, right and bottom exclusive
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl Bounds {
+    pub fn width(&self) -> u32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> u32 {
+        self.bottom - self.top
+    }
+
+    fn add(&mut self, x: u32, y: u32) {
+        self.left = self.left.min(x);
+        self.top = self.top.min(y);
+        self.right = self.right.max(x + 1);
+        self.bottom = self.bottom.max(y + 1);
+    }
+
+    fn point(x: u32, y: u32) -> Bounds {
+        Bounds {
+            left: x,
+            top: y,
+            right: x + 1,
+            bottom: y + 1,
+        }
+    }
+}
+
+struct Component {
+    bounds: Bounds,
+    pixels: u32,
+}
+
+fn color(p: &Rgb<u8>) -> Color {
+    Color(p.0[0], p.0[1], p.0[2])
+}
+
+// the mean colour of the most common 4 bit per channel bin
+fn background(img: &RgbImage) -> Color {
+    let mut histogram = vec![0_u32; 4096];
+    let bin =
+        |p: &[u8]| ((p[0] as usize >> 4) << 8) | ((p[1] as usize >> 4) << 4) | (p[2] as usize >> 4);
+    for p in img.pixels() {
+        histogram[bin(&p.0)] += 1;
+    }
+    let mode = (0..histogram.len()).max_by_key(|&i| histogram[i]).unwrap();
+
+    let mut sum = [0_u64; 3];
+    for p in img.pixels().filter(|p| bin(&p.0) == mode) {
+        for (s, &c) in sum.iter_mut().zip(p.0.iter()) {
+            *s += c as u64;
+        }
+    }
+    let n = histogram[mode].max(1) as u64;
+    Color((sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8)
+}
+
+// 4-connected regions of `mask`
+fn components(mask: &[bool], width: u32, height: u32) -> Vec<Component> {
+    let mut visited = vec![false; mask.len()];
+    let mut stack: Vec<(u32, u32)> = Vec::new();
+    let mut result: Vec<Component> = Vec::new();
+
+    for start in 0..mask.len() {
+        if !mask[start] || visited[start] {
+            continue;
+        }
+        let (x, y) = (start as u32 % width, start as u32 / width);
+        let mut component = Component {
+            bounds: Bounds::point(x, y),
+            pixels: 0,
+        };
+        visited[start] = true;
+        stack.push((x, y));
+        while let Some((x, y)) = stack.pop() {
+            component.bounds.add(x, y);
+            component.pixels += 1;
+
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for &(nx, ny) in neighbours.iter() {
+                if nx >= width || ny >= height {
+                    continue;
+                }
+                let i = (ny * width + nx) as usize;
+                if mask[i] && !visited[i] {
+                    visited[i] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+        result.push(component);
+    }
+    result
+}
+
+fn median(values: &mut [u32]) -> u32 {
+    values.sort_unstable();
+    values[values.len() / 2]
+}
+
+// groups sorted positions closer than `within`, one position per group
+fn cluster(mut values: Vec<u32>, within: u32) -> Vec<u32> {
+    values.sort_unstable();
+    let mut groups: Vec<Vec<u32>> = Vec::new();
+    for v in values {
+        match groups.last_mut() {
+            Some(g) if v - g[g.len() - 1] < within => g.push(v),
+            _ => groups.push(vec![v]),
+        }
+    }
+    groups.iter_mut().map(|g| median(g)).collect()
+}
+
+#[derive(Debug)]
+pub struct Grid {
+    pub left: u32,
+    pub top: u32,
+    pub art_width: u32,
+    pub art_height: u32,
+    pub gap_x: u32,
+    pub gap_y: u32,
+    pub rows: u32,
+    pub cols: u32,
+}
+
+impl Grid {
+    pub fn right(&self) -> u32 {
+        self.left + self.cols * (self.art_width + self.gap_x) - self.gap_x
+    }
+}
+
+// pitch of evenly spaced positions, some of which may be missing
+fn pitch_and_count(positions: &[u32]) -> (u32, u32) {
+    let mut diffs: Vec<u32> = positions.windows(2).map(|w| w[1] - w[0]).collect();
+    let pitch = median(&mut diffs);
+    let span = positions[positions.len() - 1] - positions[0];
+    (pitch, (span as f64 / pitch as f64).round() as u32 + 1)
+}
+
+// cards are the most common solid box of card proportions. The backpack should be
+// full enough that at least two rows and two columns are visible
+pub fn find_grid(img: &RgbImage) -> Result<Grid, String> {
+    let (width, height) = img.dimensions();
+    let bg = background(img);
+    let mask: Vec<bool> = img
+        .pixels()
+        .map(|p| bg.dis_2(&color(p)) > CARD_CONTRAST)
+        .collect();
+
+    let candidates: Vec<Bounds> = components(&mask, width, height)
+        .into_iter()
+        .filter(|c| {
+            let b = &c.bounds;
+            let ratio = b.height() as f64 / b.width() as f64;
+            b.width() >= width / 40
+                && b.width() <= width / 6
+                && b.height() >= height / 12
+                && b.height() <= height / 3
+                && ratio > 0.9
+                && ratio < 1.8
+                && c.pixels as f64 > 0.6 * (b.width() * b.height()) as f64
+        })
+        .map(|c| c.bounds)
+        .collect();
+
+    let same_size = |a: &Bounds, b: &Bounds| {
+        (a.width() as i64 - b.width() as i64).abs() <= 3
+            && (a.height() as i64 - b.height() as i64).abs() <= 3
+    };
+    let card = match candidates
+        .iter()
+        .max_by_key(|a| candidates.iter().filter(|b| same_size(a, b)).count())
+    {
+        Some(v) => *v,
+        None => return Err(String::from("截图中没有找到圣遗物")),
+    };
+    let cards: Vec<Bounds> = candidates
+        .into_iter()
+        .filter(|b| same_size(&card, b))
+        .collect();
+
+    let art_width = median(&mut cards.iter().map(|b| b.width()).collect::<Vec<u32>>());
+    let art_height = median(&mut cards.iter().map(|b| b.height()).collect::<Vec<u32>>());
+    let lefts = cluster(cards.iter().map(|b| b.left).collect(), art_width / 2);
+    let tops = cluster(cards.iter().map(|b| b.top).collect(), art_height / 2);
+    if lefts.len() < 2 || tops.len() < 2 {
+        return Err(String::from("背包中至少需要能看到两行两列圣遗物"));
+    }
+
+    let (pitch_x, cols) = pitch_and_count(&lefts);
+    let (pitch_y, rows) = pitch_and_count(&tops);
+    if pitch_x <= art_width || pitch_y <= art_height {
+        return Err(String::from("圣遗物之间没有间隔"));
+    }
+
+    Ok(Grid {
+        left: lefts[0],
+        top: tops[0],
+        art_width,
+        art_height,
+        gap_x: pitch_x - art_width,
+        gap_y: pitch_y - art_height,
+        rows,
+        cols,
+    })
+}
+
+// the rarity coloured header of the detail panel, the largest region in the palette
+// right of the grid. It needs an artifact to be selected
+pub fn find_panel_header(img: &RgbImage, grid: &Grid) -> Result<Bounds, String> {
+    let (width, height) = img.dimensions();
+    let from = grid.right() + grid.gap_x;
+    let mask: Vec<bool> = img
+        .enumerate_pixels()
+        .map(|(x, _, p)| {
+            x >= from
+                && STAR_COLORS
+                    .iter()
+                    .any(|s| s.dis_2(&color(p)) < HEADER_DISTANCE)
+        })
+        .collect();
+
+    match components(&mask, width, height)
+        .into_iter()
+        .max_by_key(|c| c.pixels)
+    {
+        Some(c) if c.bounds.width() > 2 * grid.art_width => Ok(c.bounds),
+        _ => Err(String::from("没有找到圣遗物详情面板，请先选中一个圣遗物")),
+    }
+}
+
+// text in `near`, grown by its height on every side
+fn find_text(img: &RgbImage, near: &Rect) -> Option<Bounds> {
+    let (width, height) = img.dimensions();
+    let margin = near.2 - near.0;
+    let clamp = |v: f64, max: u32| v.max(0.0).min(max as f64) as u32;
+    let left = clamp(near.3 - margin, width);
+    let right = clamp(near.1 + margin, width);
+    let top = clamp(near.0 - margin, height);
+    let bottom = clamp(near.2 + margin, height);
+    if left >= right || top >= bottom {
+        return None;
+    }
+
+    // the background of the search area is its most common colour
+    let area = image::imageops::crop_imm(img, left, top, right - left, bottom - top).to_image();
+    let bg = background(&area);
+    let mut bounds: Option<Bounds> = None;
+    for (x, y, p) in area.enumerate_pixels() {
+        if bg.dis_2(&color(p)) > TEXT_CONTRAST {
+            match bounds {
+                Some(ref mut b) => b.add(left + x, top + y),
+                None => bounds = Some(Bounds::point(left + x, top + y)),
+            }
+        }
+    }
+    bounds.filter(|b| b.width() > b.height())
+}
+
+// the pixel nearest to `expected` inside the header that has a rarity colour, which is
+// where the scanner reads the star count
+fn find_star(img: &RgbImage, header: &Bounds, expected: (f64, f64)) -> Option<(u32, u32)> {
+    let mut best: Option<(f64, (u32, u32))> = None;
+    for y in header.top..header.bottom {
+        for x in header.left..header.right {
+            let p = color(img.get_pixel(x, y));
+            if !STAR_COLORS.iter().any(|s| s.dis_2(&p) < HEADER_DISTANCE) {
+                continue;
+            }
+            let d = (x as f64 - expected.0).powi(2) + (y as f64 - expected.1).powi(2);
+            match best {
+                Some((bd, _)) if bd <= d => {}
+                _ => best = Some((d, (x, y))),
+            }
+        }
+    }
+    best.map(|(_, p)| p)
+}
+
+// the scanner waits for the flag to change colour when a row scrolls in, so it has to
+// be on the background just above the first row. `None` when a card or other UI touches
+// the first row in column `x`
+fn find_flag_y(img: &RgbImage, grid: &Grid, x: u32) -> Option<u32> {
+    let bg = background(img);
+    let free = (0..grid.top)
+        .rev()
+        .take_while(|&y| bg.dis_2(&color(img.get_pixel(x, y))) <= CARD_CONTRAST)
+        .count() as u32;
+    if free < 2 {
+        return None;
+    }
+    let above = (grid.art_height as f64 * FLAG_ABOVE).round() as u32;
+    Some(grid.top - above.min(free / 2).max(1))
+}
+
+fn round1(v: f64) -> f64 {
+    (v * 10.0).round() / 10.0
+}
+
+// a layout for the resolution of `img`, a screenshot of the game window's client area
+// with the backpack open and an artifact selected. The grid, the panel header, the count
+// text, the star pixel and the flag are found in the screenshot. The text fields inside
+// the panel are not: they come from the built-in layout of the closest ratio, scaled to
+// the panel found, and should be checked with `layout-preview`
+pub fn calibrate(img: &RgbImage) -> Result<WindowInfo, String> {
+    let (width, height) = img.dimensions();
+    let grid = find_grid(img)?;
+    let header = find_panel_header(img, &grid)?;
+
+    let ratio = width as f64 / height as f64;
+    let layouts = Layouts::builtin();
+    let reference = &layouts
+        .iter()
+        .min_by(|a, b| {
+            let da = (a.window.width / a.window.height - ratio).abs();
+            let db = (b.window.width / b.window.height - ratio).abs();
+            da.partial_cmp(&db).unwrap()
+        })
+        .unwrap()
+        .window;
+
+    // the UI scales uniformly, so the panel width gives the scale of everything in it
+    let panel = &reference.panel_pos;
+    let scale = header.width() as f64 / (panel.1 - panel.3);
+    let x = |v: f64| {
+        round1(
+            ((v - panel.3) * scale + header.left as f64)
+                .max(0.0)
+                .min(width as f64),
+        )
+    };
+    let y = |v: f64| {
+        round1(
+            ((v - panel.0) * scale + header.top as f64)
+                .max(0.0)
+                .min(height as f64),
+        )
+    };
+    let project = |r: &Rect| Rect(y(r.0), x(r.1), y(r.2), x(r.3));
+
+    let expected_count = project(&reference.art_count_pos);
+    let art_count_pos = match find_text(img, &expected_count) {
+        // a few pixels of room around the text
+        Some(b) => {
+            let pad = (b.height() / 4).max(2) as f64;
+            Rect(
+                (b.top as f64 - pad).max(0.0),
+                (b.right as f64 + pad).min(width as f64),
+                (b.bottom as f64 + pad).min(height as f64),
+                (b.left as f64 - pad).max(0.0),
+            )
+        }
+        None => expected_count,
+    };
+
+    let star = find_star(img, &header, (x(reference.star_x), y(reference.star_y)))
+        .ok_or_else(|| String::from("没有在详情面板中找到星级颜色"))?;
+
+    // the second column, so that the first column scrolling in doesn't hide the flag
+    let flag_x = grid.left + grid.art_width + grid.gap_x + grid.art_width / 2;
+    let flag_y = find_flag_y(img, &grid, flag_x)
+        .ok_or_else(|| String::from("第一行圣遗物上方没有空白，请截取完整的游戏窗口"))?;
+
+    Ok(WindowInfo {
+        width: width as f64,
+        height: height as f64,
+
+        title_pos: project(&reference.title_pos),
+        main_stat_name_pos: project(&reference.main_stat_name_pos),
+        main_stat_value_pos: project(&reference.main_stat_value_pos),
+        level_pos: project(&reference.level_pos),
+        panel_pos: Rect(
+            header.top as f64,
+            header.right as f64,
+            y(panel.2),
+            header.left as f64,
+        ),
+
+        sub_stat1_pos: project(&reference.sub_stat1_pos),
+        sub_stat2_pos: project(&reference.sub_stat2_pos),
+        sub_stat3_pos: project(&reference.sub_stat3_pos),
+        sub_stat4_pos: project(&reference.sub_stat4_pos),
+
+        equip_pos: project(&reference.equip_pos),
+        art_count_pos,
+
+        art_width: grid.art_width as f64,
+        art_height: grid.art_height as f64,
+        art_gap_x: grid.gap_x as f64,
+        art_gap_y: grid.gap_y as f64,
+
+        art_row: grid.rows as usize,
+        art_col: grid.cols as usize,
+
+        left_margin: grid.left as f64,
+        top_margin: grid.top as f64,
+
+        flag_x: flag_x as f64,
+        flag_y: flag_y as f64,
+
+        star_x: star.0 as f64,
+        star_y: star.1 as f64,
+
+        pool_pos: project(&reference.pool_pos),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a synthetic 1280x720 backpack drawn with the built-in 16:9 layout scaled by 0.8,
+    // with the last row only partly filled and an artifact selected
+    fn fixture() -> RgbImage {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/info/testdata/backpack_1280x720.png"
+        );
+        image::open(path).unwrap().to_rgb8()
+    }
+
+    fn assert_near(actual: f64, expected: f64, what: &str) {
+        assert!(
+            (actual - expected).abs() <= 2.0,
+            "{}: {} instead of {}",
+            what,
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn finds_the_grid() {
+        let grid = find_grid(&fixture()).unwrap();
+        assert_eq!((grid.rows, grid.cols), (5, 8));
+        assert_near(grid.left as f64, 79.2, "left");
+        assert_near(grid.top as f64, 80.8, "top");
+        assert_near(grid.art_width as f64, 81.6, "art width");
+        assert_near(grid.art_height as f64, 100.8, "art height");
+        assert_near(grid.gap_x as f64, 16.0, "gap x");
+        assert_near(grid.gap_y as f64, 16.0, "gap y");
+    }
+
+    #[test]
+    fn calibrates_the_scaled_layout() {
+        let img = fixture();
+        let window = calibrate(&img).unwrap();
+        assert_eq!((window.width, window.height), (1280.0, 720.0));
+        assert_eq!((window.art_row, window.art_col), (5, 8));
+
+        let near_rect = |actual: &Rect, expected: &Rect, what: &str| {
+            assert_near(actual.0, expected.0 * 0.8, what);
+            assert_near(actual.1, expected.1 * 0.8, what);
+            assert_near(actual.2, expected.2 * 0.8, what);
+            assert_near(actual.3, expected.3 * 0.8, what);
+        };
+        let builtin = &crate::info::window_info::WINDOW_16_9;
+        near_rect(&window.title_pos, &builtin.title_pos, "title");
+        near_rect(&window.sub_stat4_pos, &builtin.sub_stat4_pos, "sub stat 4");
+        near_rect(&window.equip_pos, &builtin.equip_pos, "equip");
+        assert_near(window.panel_pos.0, 80.0, "panel top");
+        assert_near(window.panel_pos.3, 872.0, "panel left");
+
+        // the count text is found, it sits inside the box
+        let count = &window.art_count_pos;
+        assert!(count.3 <= 1052.8 && count.1 >= 1195.0, "{:?}", count);
+        assert!(count.0 <= 23.2 && count.2 >= 40.8, "{:?}", count);
+
+        // the star pixel has the header's colour and the flag is on the background
+        let star = img.get_pixel(window.star_x as u32, window.star_y as u32);
+        assert_eq!(star.0, [188, 105, 50]);
+        assert_near(window.star_x, builtin.star_x * 0.8, "star x");
+        assert_near(window.star_y, builtin.star_y * 0.8, "star y");
+        let flag = img.get_pixel(window.flag_x as u32, window.flag_y as u32);
+        assert_eq!(flag.0, [41, 47, 62]);
+        assert_near(window.flag_x, builtin.flag_x * 0.8, "flag x");
+        assert_near(window.flag_y, builtin.flag_y * 0.8, "flag y");
+    }
+
+    #[test]
+    fn needs_two_rows_and_two_columns() {
+        let img = fixture();
+        // only the first row
+        let cropped = image::imageops::crop_imm(&img, 0, 0, 1280, 190).to_image();
+        assert!(find_grid(&cropped).is_err());
+    }
+}
@@ -1,7 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+use crate::common::error::YasError;
 use crate::common::{PixelRect, PixelRectBound};
+use crate::inference::pre_process::Binarization;
+use crate::info::layout::Layouts;
 use crate::info::window_info::{WINDOW_43_18, WINDOW_7_3, WINDOW_16_9, WINDOW_4_3, WINDOW_8_5};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ScanInfo {
     // pub panel_height: u32,
     // pub panel_width: u32,
@@ -44,6 +49,71 @@ pub struct ScanInfo {
     pub star_y: u32,
 
     pub pool_position: PixelRectBound,
+
+    // missing in replay indices saved before it existed
+    #[serde(default)]
+    pub binarization: FieldBinarization,
+}
+
+// preprocessing per panel field, the 4 substat lines share one
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FieldBinarization {
+    pub title: Binarization,
+    pub main_stat_name: Binarization,
+    pub main_stat_value: Binarization,
+    pub sub_stat: Binarization,
+    pub level: Binarization,
+    pub equip: Binarization,
+}
+
+impl FieldBinarization {
+    // by the field names used in dumps
+    pub fn get(&self, field: &str) -> Binarization {
+        match field {
+            "title" => self.title,
+            "main_stat_name" => self.main_stat_name,
+            "main_stat_value" => self.main_stat_value,
+            "level" => self.level,
+            "equip" => self.equip,
+            f if f.starts_with("sub_stat") => self.sub_stat,
+            _ => Binarization::default(),
+        }
+    }
+
+    // "otsu" for every field, or "equip=sauvola,sub_stat=otsu"
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for item in spec.split(',') {
+            let (field, name) = match item.find('=') {
+                Some(i) => (&item[..i], &item[i + 1..]),
+                None => ("", item),
+            };
+            let b = match Binarization::from_name(name.trim()) {
+                Some(v) => v,
+                None => return Err(format!("unknown binarization `{}`", name)),
+            };
+            match field.trim() {
+                "" => {
+                    *self = FieldBinarization {
+                        title: b,
+                        main_stat_name: b,
+                        main_stat_value: b,
+                        sub_stat: b,
+                        level: b,
+                        equip: b,
+                    }
+                }
+                "title" => self.title = b,
+                "main_stat_name" => self.main_stat_name = b,
+                "main_stat_value" => self.main_stat_value = b,
+                "sub_stat" => self.sub_stat = b,
+                "level" => self.level = b,
+                "equip" => self.equip = b,
+                f => return Err(format!("unknown field `{}`", f)),
+            }
+        }
+        Ok(())
+    }
 }
 
 impl ScanInfo {
@@ -168,20 +238,15 @@ impl ScanInfo {
 }
 
 impl ScanInfo {
-    pub fn from_rect(rect: &PixelRect) -> Result<ScanInfo, String> {
-        let mut info: ScanInfo;
-        if rect.height * 16 == rect.width * 9 {
-            info = ScanInfo::from_16_9(rect.width as u32, rect.height as u32, rect.left, rect.top);
-        } else if rect.height * 8 == rect.width * 5 {
-            info = ScanInfo::from_8_5(rect.width as u32, rect.height as u32, rect.left, rect.top);
-        } else if rect.height * 4 == rect.width * 3 {
-            info = ScanInfo::from_4_3(rect.width as u32, rect.height as u32, rect.left, rect.top);
-        } else if rect.height * 7 == rect.width * 3 {
-            info = ScanInfo::from_7_3(rect.width as u32, rect.height as u32, rect.left, rect.top);
-        } else {
-            return Err(String::from("不支持的分辨率"));
-        }
+    // the point `move_to` clicks for a grid cell, relative to the window
+    pub fn art_position(&self, row: u32, col: u32) -> (i32, i32) {
+        let x = self.left_margin + (self.art_width + self.art_gap_x) * col + self.art_width / 2;
+        let y = self.top_margin + (self.art_height + self.art_gap_y) * row + self.art_height / 4;
+        (x as i32, y as i32)
+    }
 
-        Ok(info)
+    // with the built-in layouts only, see `Layouts::scan_info`
+    pub fn from_rect(rect: &PixelRect) -> Result<ScanInfo, YasError> {
+        Layouts::builtin().scan_info(rect)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,540 @@
+use std::fs;
+use std::path::Path;
+
+use log::info;
+
+use crate::common::error::YasError;
+use crate::common::PixelRect;
+use crate::info::info::ScanInfo;
+use crate::info::window_info::{
+    Rect, WindowInfo, WINDOW_16_9, WINDOW_43_18, WINDOW_4_3, WINDOW_7_3, WINDOW_8_5,
+};
+
+// window borders and title bars easily leave a window a pixel or two off a layout's
+// ratio, anything within this fraction of it still uses the layout
+const RATIO_TOLERANCE: f64 = 0.01;
+// no layout is guessed for windows narrower or wider than this
+const MIN_RATIO: f64 = 1.2;
+const MAX_RATIO: f64 = 3.6;
+
+// the game fits its UI into a 16:9 box, a wider window gets extra room on the sides
+// and a narrower one below
+const UI_WIDTH: f64 = 1600.0;
+const UI_HEIGHT: f64 = 900.0;
+
+pub struct Layout {
+    // the file it was read from, or the ratio of a built-in one
+    pub name: String,
+    pub window: WindowInfo,
+}
+
+impl Layout {
+    // a window fits when it has the aspect ratio of the reference resolution
+    pub fn fits(&self, width: i32, height: i32) -> bool {
+        height as f64 * self.window.width == width as f64 * self.window.height
+    }
+
+    pub fn ratio(&self) -> f64 {
+        self.window.width / self.window.height
+    }
+}
+
+// the layouts a window is matched against, user ones first so they can replace a
+// built-in one
+pub struct Layouts {
+    layouts: Vec<Layout>,
+}
+
+impl Layouts {
+    pub fn builtin() -> Layouts {
+        let layouts = vec![
+            ("43:18", WINDOW_43_18),
+            ("16:9", WINDOW_16_9),
+            ("8:5", WINDOW_8_5),
+            ("4:3", WINDOW_4_3),
+            ("7:3", WINDOW_7_3),
+        ];
+
+        Layouts {
+            layouts: layouts
+                .into_iter()
+                .map(|(name, window)| Layout {
+                    name: String::from(name),
+                    window,
+                })
+                .collect(),
+        }
+    }
+
+    // the built-in layouts and every `.toml` or `.json` in `dir`, which may not exist
+    pub fn load(dir: &str) -> Result<Layouts, YasError> {
+        let mut user: Vec<Layout> = Vec::new();
+        if Path::new(dir).is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let window = match path.extension().and_then(|e| e.to_str()) {
+                    Some("toml") | Some("json") => load_window_info(&path)?,
+                    _ => continue,
+                };
+                user.push(Layout {
+                    name: path.display().to_string(),
+                    window,
+                });
+            }
+        }
+        // read_dir has no order
+        user.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut layouts = Layouts::builtin();
+        user.append(&mut layouts.layouts);
+        layouts.layouts = user;
+        Ok(layouts)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Layout> {
+        self.layouts.iter()
+    }
+
+    // the first layout with exactly the window's ratio, else the closest one within
+    // `RATIO_TOLERANCE`
+    pub fn find(&self, width: i32, height: i32) -> Option<&Layout> {
+        if let Some(layout) = self.layouts.iter().find(|l| l.fits(width, height)) {
+            return Some(layout);
+        }
+
+        let ratio = width as f64 / height as f64;
+        let distance = |l: &Layout| (l.ratio() / ratio - 1.0).abs();
+        self.layouts
+            .iter()
+            .filter(|l| distance(l) <= RATIO_TOLERANCE)
+            .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+    }
+
+    // a layout at the window's own resolution for a ratio no layout has, from the
+    // closest narrower and wider layouts, or the closest one past either end
+    pub fn interpolate(&self, width: i32, height: i32) -> Option<Layout> {
+        let ratio = width as f64 / height as f64;
+        if !(MIN_RATIO..=MAX_RATIO).contains(&ratio) {
+            return None;
+        }
+
+        let narrower = self
+            .layouts
+            .iter()
+            .filter(|l| l.ratio() <= ratio)
+            .min_by(|a, b| b.ratio().partial_cmp(&a.ratio()).unwrap());
+        let wider = self
+            .layouts
+            .iter()
+            .filter(|l| l.ratio() >= ratio)
+            .min_by(|a, b| a.ratio().partial_cmp(&b.ratio()).unwrap());
+        let (a, b) = match (narrower, wider) {
+            (Some(a), Some(b)) => (a, b),
+            (Some(a), None) | (None, Some(a)) => (a, a),
+            (None, None) => return None,
+        };
+
+        let name = if a.name == b.name {
+            format!("{}x{}（由{}推算）", width, height, a.name)
+        } else {
+            format!("{}x{}（由{}和{}推算）", width, height, a.name, b.name)
+        };
+        Some(Layout {
+            name,
+            window: interpolate(&a.window, &b.window, width as f64, height as f64),
+        })
+    }
+
+    pub fn scan_info(&self, rect: &PixelRect) -> Result<ScanInfo, YasError> {
+        let interpolated;
+        let layout = match self.find(rect.width, rect.height) {
+            Some(v) => v,
+            None => match self.interpolate(rect.width, rect.height) {
+                Some(v) => {
+                    interpolated = v;
+                    &interpolated
+                }
+                None => {
+                    return Err(YasError::UnsupportedResolution {
+                        width: rect.width,
+                        height: rect.height,
+                    })
+                }
+            },
+        };
+        info!("使用布局：{}", layout.name);
+
+        Ok(layout
+            .window
+            .to_scan_info(rect.height as f64, rect.width as f64, rect.left, rect.top))
+    }
+}
+
+pub fn load_window_info(path: &Path) -> Result<WindowInfo, YasError> {
+    let s = fs::read_to_string(path)?;
+    let window: WindowInfo = if path.extension().and_then(|e| e.to_str()) == Some("json") {
+        serde_json::from_str(&s)
+            .map_err(|e| YasError::Layout(format!("{}: {}", path.display(), e)))?
+    } else {
+        toml::from_str(&s).map_err(|e| YasError::Layout(format!("{}: {}", path.display(), e)))?
+    };
+
+    if window.width <= 0.0 || window.height <= 0.0 {
+        return Err(YasError::Layout(format!(
+            "{}: width and height must be positive",
+            path.display()
+        )));
+    }
+    Ok(window)
+}
+
+#[derive(Clone, Copy)]
+enum Anchor {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+// the UI scale of a window and its size in UI units, one of which is the 16:9 box's
+struct Ui {
+    scale: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Ui {
+    fn new(width: f64, height: f64) -> Ui {
+        let scale = (width / UI_WIDTH).min(height / UI_HEIGHT);
+        Ui {
+            scale,
+            width: width / scale,
+            height: height / scale,
+        }
+    }
+
+    // how much wider than 16:9 the window is, negative when it is taller instead
+    fn extent(&self) -> f64 {
+        self.width - self.height * UI_WIDTH / UI_HEIGHT
+    }
+
+    // a pixel coordinate as the distance from the edge it is anchored to in UI units
+    fn offset(&self, v: f64, anchor: Anchor) -> f64 {
+        match anchor {
+            Anchor::Left | Anchor::Top => v / self.scale,
+            Anchor::Right => self.width - v / self.scale,
+            Anchor::Bottom => self.height - v / self.scale,
+        }
+    }
+
+    fn position(&self, v: f64, anchor: Anchor) -> f64 {
+        match anchor {
+            Anchor::Left | Anchor::Top => v * self.scale,
+            Anchor::Right => (self.width - v) * self.scale,
+            Anchor::Bottom => (self.height - v) * self.scale,
+        }
+    }
+}
+
+// every rect is in the item panel or the count above it, which the game keeps at the
+// right edge; nothing on the bag screen is centred, so there is no centre anchor and
+// both sides of a rect follow the right edge
+fn map_rect(
+    rect: &Rect,
+    top: Anchor,
+    bottom: Anchor,
+    f: &mut impl FnMut(f64, Anchor) -> f64,
+) -> Rect {
+    Rect(
+        f(rect.0, top),
+        f(rect.1, Anchor::Right),
+        f(rect.2, bottom),
+        f(rect.3, Anchor::Right),
+    )
+}
+
+// applies `f` to every coordinate with the edge the game anchors it to: the panel
+// and the count above it to the right, the grid to the left, the equip line and the
+// bottom of the panel to the bottom and everything else to the top
+fn map_window(w: &WindowInfo, mut f: impl FnMut(f64, Anchor) -> f64) -> WindowInfo {
+    WindowInfo {
+        width: w.width,
+        height: w.height,
+
+        title_pos: map_rect(&w.title_pos, Anchor::Top, Anchor::Top, &mut f),
+        main_stat_name_pos: map_rect(&w.main_stat_name_pos, Anchor::Top, Anchor::Top, &mut f),
+        main_stat_value_pos: map_rect(&w.main_stat_value_pos, Anchor::Top, Anchor::Top, &mut f),
+        level_pos: map_rect(&w.level_pos, Anchor::Top, Anchor::Top, &mut f),
+        panel_pos: map_rect(&w.panel_pos, Anchor::Top, Anchor::Bottom, &mut f),
+
+        sub_stat1_pos: map_rect(&w.sub_stat1_pos, Anchor::Top, Anchor::Top, &mut f),
+        sub_stat2_pos: map_rect(&w.sub_stat2_pos, Anchor::Top, Anchor::Top, &mut f),
+        sub_stat3_pos: map_rect(&w.sub_stat3_pos, Anchor::Top, Anchor::Top, &mut f),
+        sub_stat4_pos: map_rect(&w.sub_stat4_pos, Anchor::Top, Anchor::Top, &mut f),
+
+        equip_pos: map_rect(&w.equip_pos, Anchor::Bottom, Anchor::Bottom, &mut f),
+        art_count_pos: map_rect(&w.art_count_pos, Anchor::Top, Anchor::Top, &mut f),
+
+        // sizes scale like a coordinate from the left or top
+        art_width: f(w.art_width, Anchor::Left),
+        art_height: f(w.art_height, Anchor::Top),
+        art_gap_x: f(w.art_gap_x, Anchor::Left),
+        art_gap_y: f(w.art_gap_y, Anchor::Top),
+
+        art_row: w.art_row,
+        art_col: w.art_col,
+
+        left_margin: f(w.left_margin, Anchor::Left),
+        top_margin: f(w.top_margin, Anchor::Top),
+
+        flag_x: f(w.flag_x, Anchor::Left),
+        flag_y: f(w.flag_y, Anchor::Top),
+
+        star_x: f(w.star_x, Anchor::Right),
+        star_y: f(w.star_y, Anchor::Top),
+
+        pool_pos: map_rect(&w.pool_pos, Anchor::Top, Anchor::Top, &mut f),
+    }
+}
+
+// the distance of each coordinate from its edge in UI units changes linearly with
+// the extra room between the two layouts, and stays put past the last one
+fn interpolate(a: &WindowInfo, b: &WindowInfo, width: f64, height: f64) -> WindowInfo {
+    let ui_a = Ui::new(a.width, a.height);
+    let ui_b = Ui::new(b.width, b.height);
+    let ui = Ui::new(width, height);
+    let t = if ui_a.extent() == ui_b.extent() {
+        0.0
+    } else {
+        (ui.extent() - ui_a.extent()) / (ui_b.extent() - ui_a.extent())
+    };
+
+    // `map_window` visits the coordinates of both in the same order
+    let mut from_b = Vec::new();
+    map_window(b, |v, anchor| {
+        from_b.push(ui_b.offset(v, anchor));
+        v
+    });
+    let mut from_b = from_b.into_iter();
+    let mut window = map_window(a, |v, anchor| {
+        let v = ui_a.offset(v, anchor) * (1.0 - t) + from_b.next().unwrap() * t;
+        ui.position(v, anchor)
+    });
+    window.width = width;
+    window.height = height;
+
+    // as many whole cells as fit left of the panel and above the bottom of the window
+    let col = (window.panel_pos.3 - window.left_margin + window.art_gap_x)
+        / (window.art_width + window.art_gap_x);
+    let row =
+        (height - window.top_margin + window.art_gap_y) / (window.art_height + window.art_gap_y);
+    window.art_col = col.floor().max(1.0) as usize;
+    window.art_row = row.floor().max(1.0) as usize;
+
+    window
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::temp_path::TempPath;
+
+    fn builtin(name: &str) -> WindowInfo {
+        let layouts = Layouts::builtin();
+        let layout = layouts.iter().find(|l| l.name == name).unwrap();
+        layout.window.clone()
+    }
+
+    fn coordinates(w: &WindowInfo) -> Vec<f64> {
+        let mut v = Vec::new();
+        map_window(w, |x, _| {
+            v.push(x);
+            x
+        });
+        v
+    }
+
+    fn layout_dir(name: &str) -> TempPath {
+        let dir = TempPath::new(name);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn resized(width: f64, height: f64) -> WindowInfo {
+        let mut window = WINDOW_16_9;
+        window.width = width;
+        window.height = height;
+        window
+    }
+
+    fn assert_close(a: &[f64], b: &[f64]) {
+        assert_eq!(a.len(), b.len());
+        for (i, (x, y)) in a.iter().zip(b).enumerate() {
+            assert!((x - y).abs() < 1e-6, "coordinate {}: {} != {}", i, x, y);
+        }
+    }
+
+    #[test]
+    fn a_window_a_pixel_off_uses_the_layout() {
+        let layouts = Layouts::builtin();
+        let name = |w, h| layouts.find(w, h).map(|l| l.name.as_str());
+
+        assert_eq!(name(1920, 1080), Some("16:9"));
+        assert_eq!(name(1920, 1081), Some("16:9"));
+        assert_eq!(name(1921, 1080), Some("16:9"));
+        assert_eq!(name(3440, 1441), Some("43:18"));
+        assert_eq!(name(1919, 1200), Some("8:5"));
+        assert_eq!(name(1920, 1000), None);
+    }
+
+    #[test]
+    fn the_nearest_layout_within_the_tolerance_wins() {
+        let mut layouts = Layouts::builtin();
+        let mut window = builtin("16:9");
+        window.width = 1790.0;
+        window.height = 1000.0;
+        layouts.layouts.insert(
+            0,
+            Layout {
+                name: String::from("custom"),
+                window,
+            },
+        );
+        let name = |w, h| layouts.find(w, h).map(|l| l.name.as_str());
+
+        // both are within 1% of 1920x1081
+        assert_eq!(name(1920, 1081), Some("16:9"));
+        assert_eq!(name(1790, 1001), Some("custom"));
+        assert_eq!(name(1790, 1000), Some("custom"));
+    }
+
+    #[test]
+    fn interpolating_at_an_endpoint_reproduces_it() {
+        let (a, b) = (builtin("16:9"), builtin("43:18"));
+
+        let at_a = interpolate(&a, &b, a.width, a.height);
+        assert_close(&coordinates(&at_a), &coordinates(&a));
+        assert_eq!((at_a.art_col, at_a.art_row), (a.art_col, a.art_row));
+
+        let at_b = interpolate(&a, &b, b.width, b.height);
+        assert_close(&coordinates(&at_b), &coordinates(&b));
+        assert_eq!((at_b.art_col, at_b.art_row), (b.art_col, b.art_row));
+    }
+
+    #[test]
+    fn a_known_ratio_at_another_resolution_is_scaled() {
+        let layouts = Layouts::builtin();
+        let layout = layouts.interpolate(1920, 1080).unwrap();
+        assert_eq!(layout.name, "1920x1080（由16:9推算）");
+
+        let scaled: Vec<f64> = coordinates(&WINDOW_16_9).iter().map(|v| v * 1.2).collect();
+        assert_close(&coordinates(&layout.window), &scaled);
+
+        assert!(layouts.interpolate(1000, 1000).is_none());
+        assert!(layouts.interpolate(4000, 1000).is_none());
+    }
+
+    #[test]
+    fn interpolated_edges_stay_between_the_two_layouts() {
+        let layouts = Layouts::builtin();
+        let layout = layouts.interpolate(2560, 1200).unwrap();
+        assert_eq!(layout.name, "2560x1200（由16:9和7:3推算）");
+
+        // in UI units, measured from the edge each is anchored to
+        let (a, b, w) = (builtin("16:9"), builtin("7:3"), &layout.window);
+        let (ui_a, ui_b, ui) = (
+            Ui::new(a.width, a.height),
+            Ui::new(b.width, b.height),
+            Ui::new(w.width, w.height),
+        );
+        let between = |x: f64, a: f64, b: f64| a.min(b) <= x && x <= a.max(b);
+
+        let panel = |ui: &Ui, w: &WindowInfo| ui.offset(w.panel_pos.1, Anchor::Right);
+        assert!(between(panel(&ui, w), panel(&ui_a, &a), panel(&ui_b, &b)));
+        let grid = |ui: &Ui, w: &WindowInfo| ui.offset(w.left_margin, Anchor::Left);
+        assert!(between(grid(&ui, w), grid(&ui_a, &a), grid(&ui_b, &b)));
+    }
+
+    #[test]
+    fn rows_and_columns_fill_the_space_left_for_the_grid() {
+        // the counts of every built-in layout come out of its own geometry
+        for l in Layouts::builtin().iter() {
+            let w = interpolate(&l.window, &l.window, l.window.width, l.window.height);
+            assert_eq!((w.art_col, w.art_row), (l.window.art_col, l.window.art_row));
+        }
+
+        let layouts = Layouts::builtin();
+        for &(width, height) in &[(2560, 1200), (1500, 1000), (3000, 1000)] {
+            let w = layouts.interpolate(width, height).unwrap().window;
+            let right =
+                |col: usize| w.left_margin + col as f64 * (w.art_width + w.art_gap_x) - w.art_gap_x;
+            let bottom =
+                |row: usize| w.top_margin + row as f64 * (w.art_height + w.art_gap_y) - w.art_gap_y;
+            assert!(right(w.art_col) <= w.panel_pos.3);
+            assert!(right(w.art_col + 1) > w.panel_pos.3);
+            assert!(bottom(w.art_row) <= height as f64);
+            assert!(bottom(w.art_row + 1) > height as f64);
+        }
+    }
+
+    #[test]
+    fn toml_and_json_layouts_are_loaded() {
+        let dir = layout_dir("layouts");
+        let toml = toml::to_string(&resized(2000.0, 1000.0)).unwrap();
+        fs::write(dir.join("wide.toml"), toml).unwrap();
+        let json = serde_json::to_string(&resized(2100.0, 1000.0)).unwrap();
+        fs::write(dir.join("wider.json"), json).unwrap();
+        fs::write(dir.join("notes.txt"), "not a layout").unwrap();
+
+        let layouts = Layouts::load(dir.to_str().unwrap()).unwrap();
+        let name = |w, h| layouts.find(w, h).map(|l| l.name.clone());
+        let path = |file: &str| Some(dir.join(file).display().to_string());
+        assert_eq!(name(2000, 1000), path("wide.toml"));
+        assert_eq!(name(2100, 1000), path("wider.json"));
+        assert_eq!(name(1920, 1080), Some(String::from("16:9")));
+        assert_eq!(layouts.iter().count(), 7);
+    }
+
+    #[test]
+    fn user_layouts_replace_built_in_ones() {
+        let dir = layout_dir("layouts-override");
+        let toml = toml::to_string(&resized(1600.0, 900.0)).unwrap();
+        fs::write(dir.join("mine.toml"), toml).unwrap();
+
+        let layouts = Layouts::load(dir.to_str().unwrap()).unwrap();
+        let expected = dir.join("mine.toml").display().to_string();
+        assert_eq!(layouts.find(1920, 1080).unwrap().name, expected);
+        assert_eq!(layouts.find(1920, 1081).unwrap().name, expected);
+    }
+
+    #[test]
+    fn a_missing_directory_has_only_the_built_in_layouts() {
+        let dir = TempPath::new("no-layouts");
+        let layouts = Layouts::load(dir.to_str().unwrap()).unwrap();
+        assert_eq!(layouts.iter().count(), 5);
+    }
+
+    #[test]
+    fn bad_layout_files_are_rejected() {
+        let window = toml::to_string(&resized(0.0, 900.0)).unwrap();
+        let json = serde_json::to_string(&resized(1600.0, 900.0)).unwrap();
+        let cases = [
+            ("broken.toml", String::from("width = ")),
+            (
+                "missing.toml",
+                String::from("width = 1600.0\nheight = 900.0\n"),
+            ),
+            ("flat.toml", window),
+            ("truncated.json", json[..json.len() / 2].to_string()),
+        ];
+        for (file, content) in cases.iter() {
+            let dir = layout_dir("layouts-bad");
+            fs::write(dir.join(file), content).unwrap();
+            match Layouts::load(dir.to_str().unwrap()) {
+                Err(YasError::Layout(s)) => assert!(s.contains(file), "{}", s),
+                Err(e) => panic!("{}: {}", file, e),
+                Ok(_) => panic!("{} was loaded", file),
+            }
+        }
+    }
+}
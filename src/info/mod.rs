@@ -1,2 +1,6 @@
+pub mod calibration;
+#[allow(clippy::module_inception)]
 pub mod info;
+pub mod layout;
+pub mod preview;
 pub mod window_info;
@@ -0,0 +1,189 @@
+use image::{Rgb, RgbImage};
+
+use crate::common::PixelRectBound;
+use crate::info::info::ScanInfo;
+
+const LABEL_BACKGROUND: Rgb<u8> = Rgb([0, 0, 0]);
+const CELL: Rgb<u8> = Rgb([255, 0, 255]);
+const POINT: Rgb<u8> = Rgb([255, 255, 0]);
+
+// 5x7 glyphs, one row per byte, bit 4 is the leftmost column
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        'A' => [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1e],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f],
+        _ => [0; 7],
+    }
+}
+
+// drawing outside the image is clipped
+fn fill(img: &mut RgbImage, left: i32, top: i32, width: i32, height: i32, color: Rgb<u8>) {
+    let x0 = left.max(0);
+    let y0 = top.max(0);
+    let x1 = (left + width).min(img.width() as i32);
+    let y1 = (top + height).min(img.height() as i32);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            img.put_pixel(x as u32, y as u32, color);
+        }
+    }
+}
+
+fn outline(img: &mut RgbImage, rect: &PixelRectBound, thickness: i32, color: Rgb<u8>) {
+    let width = rect.right - rect.left;
+    let height = rect.bottom - rect.top;
+    fill(img, rect.left, rect.top, width, thickness, color);
+    fill(
+        img,
+        rect.left,
+        rect.bottom - thickness,
+        width,
+        thickness,
+        color,
+    );
+    fill(img, rect.left, rect.top, thickness, height, color);
+    fill(
+        img,
+        rect.right - thickness,
+        rect.top,
+        thickness,
+        height,
+        color,
+    );
+}
+
+// on a dark box so it stays readable over the game
+fn label(img: &mut RgbImage, left: i32, top: i32, text: &str, scale: i32, color: Rgb<u8>) {
+    let width = text.chars().count() as i32 * 6 * scale + scale;
+    fill(img, left, top, width, 9 * scale, LABEL_BACKGROUND);
+    for (i, c) in text.chars().enumerate() {
+        let x = left + scale + i as i32 * 6 * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..5 {
+                if bits & (0x10 >> col) != 0 {
+                    let y = top + scale + row as i32 * scale;
+                    fill(img, x + col * scale, y, scale, scale, color);
+                }
+            }
+        }
+    }
+}
+
+fn cross(img: &mut RgbImage, x: i32, y: i32, size: i32, thickness: i32, color: Rgb<u8>) {
+    fill(
+        img,
+        x - size,
+        y - thickness / 2,
+        2 * size + 1,
+        thickness,
+        color,
+    );
+    fill(
+        img,
+        x - thickness / 2,
+        y - size,
+        thickness,
+        2 * size + 1,
+        color,
+    );
+}
+
+// outlines every region of `info` on a screenshot of the window, marks the grid cells
+// `move_to` clicks and the colour sample points
+pub fn draw_layout(img: &mut RgbImage, info: &ScanInfo) {
+    let scale = (img.height() as i32 / 540).max(1);
+
+    let regions: [(&str, &PixelRectBound, Rgb<u8>); 12] = [
+        ("panel", &info.panel_position, Rgb([255, 255, 255])),
+        ("title", &info.title_position, Rgb([255, 64, 64])),
+        (
+            "main_stat_name",
+            &info.main_stat_name_position,
+            Rgb([255, 160, 0]),
+        ),
+        (
+            "main_stat_value",
+            &info.main_stat_value_position,
+            Rgb([255, 220, 0]),
+        ),
+        ("sub_stat_1", &info.sub_stat1_position, Rgb([64, 220, 64])),
+        ("sub_stat_2", &info.sub_stat2_position, Rgb([0, 200, 160])),
+        ("sub_stat_3", &info.sub_stat3_position, Rgb([64, 220, 64])),
+        ("sub_stat_4", &info.sub_stat4_position, Rgb([0, 200, 160])),
+        ("level", &info.level_position, Rgb([0, 160, 255])),
+        ("equip", &info.equip_position, Rgb([128, 128, 255])),
+        ("count", &info.art_count_position, Rgb([255, 96, 200])),
+        ("pool", &info.pool_position, Rgb([160, 255, 255])),
+    ];
+    for (_, rect, color) in regions.iter() {
+        outline(img, rect, scale, *color);
+    }
+    // labels last so no outline covers them, right of the region when there is no room above
+    for (name, rect, color) in regions.iter() {
+        let above = rect.top - 9 * scale;
+        if above >= 0 {
+            label(img, rect.left, above, name, scale, *color);
+        } else {
+            label(img, rect.right + scale, rect.top, name, scale, *color);
+        }
+    }
+
+    for row in 0..info.art_row {
+        for col in 0..info.art_col {
+            let (x, y) = info.art_position(row, col);
+            cross(img, x, y, 4 * scale, scale, CELL);
+        }
+    }
+
+    let points = [
+        ("flag", info.flag_x as i32, info.flag_y as i32),
+        ("star", info.star_x as i32, info.star_y as i32),
+    ];
+    for (name, x, y) in points.iter() {
+        cross(img, *x, *y, 6 * scale, scale, POINT);
+        fill(
+            img,
+            x - scale,
+            y - scale,
+            2 * scale + 1,
+            2 * scale + 1,
+            POINT,
+        );
+        label(img, x + 7 * scale, y - 4 * scale, name, scale, POINT);
+    }
+}
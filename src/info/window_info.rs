@@ -1,8 +1,14 @@
+use serde::{Deserialize, Serialize};
+
 use crate::common::PixelRectBound;
-use crate::info::info::ScanInfo;
+use crate::info::info::{FieldBinarization, ScanInfo};
 
-pub struct Rect(f64, f64, f64, f64); // top, right, bottom, left
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rect(pub f64, pub f64, pub f64, pub f64); // top, right, bottom, left
 
+// positions at the reference resolution `width` x `height`, which also gives the
+// aspect ratio the layout is for
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WindowInfo {
     pub width: f64,
     pub height: f64,
@@ -93,7 +99,8 @@ impl WindowInfo {
             flag_y: convert_y(self.flag_y) as u32,
             star_x: convert_x(self.star_x) as u32,
             star_y: convert_y(self.star_y) as u32,
-            pool_position: convert_rect(&self.pool_pos)
+            pool_position: convert_rect(&self.pool_pos),
+            binarization: FieldBinarization::default(),
         }
     }
 }
@@ -113,7 +120,7 @@ pub const WINDOW_43_18: WindowInfo = WindowInfo {
     sub_stat3_pos: Rect(742.0, 3080.0, 782.0, 2590.0),
     sub_stat4_pos: Rect(795.0, 3080.0, 835.0, 2590.0),
 
-    equip_pos: Rect(1220.0, 5630.0, 1260.0, 3140.0),
+    equip_pos: Rect(1220.0, 3038.0, 1260.0, 2635.0),
     art_count_pos: Rect(50.0, 3185.0, 85.0, 2750.0),
 
     art_width: 2421.0 - 2257.0,
@@ -268,4 +275,78 @@ pub const WINDOW_4_3: WindowInfo = WindowInfo {
     star_x: 1175.4,
     star_y: 95.8,
     pool_pos: Rect(93.2, 912.7 + 15.0, 412.4, 912.7)
-};
\ No newline at end of file
+};
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rects(w: &WindowInfo) -> Vec<(&'static str, &Rect)> {
+        vec![
+            ("title", &w.title_pos),
+            ("main_stat_name", &w.main_stat_name_pos),
+            ("main_stat_value", &w.main_stat_value_pos),
+            ("level", &w.level_pos),
+            ("panel", &w.panel_pos),
+            ("sub_stat1", &w.sub_stat1_pos),
+            ("sub_stat2", &w.sub_stat2_pos),
+            ("sub_stat3", &w.sub_stat3_pos),
+            ("sub_stat4", &w.sub_stat4_pos),
+            ("equip", &w.equip_pos),
+            ("art_count", &w.art_count_pos),
+        ]
+    }
+
+    #[test]
+    fn builtin_regions_are_inside_the_window() {
+        for w in &[
+            WINDOW_43_18,
+            WINDOW_7_3,
+            WINDOW_16_9,
+            WINDOW_8_5,
+            WINDOW_4_3,
+        ] {
+            for (name, r) in rects(w) {
+                let Rect(top, right, bottom, left) = *r;
+                assert!(
+                    0.0 <= left && left < right && right <= w.width,
+                    "{}x{} {}: {:?}",
+                    w.width,
+                    w.height,
+                    name,
+                    r
+                );
+                assert!(
+                    0.0 <= top && top < bottom && bottom <= w.height,
+                    "{}x{} {}: {:?}",
+                    w.width,
+                    w.height,
+                    name,
+                    r
+                );
+            }
+        }
+    }
+
+    // the equip line is at the bottom of the panel, below everything else in it
+    #[test]
+    fn equip_is_inside_the_panel() {
+        for w in &[
+            WINDOW_43_18,
+            WINDOW_7_3,
+            WINDOW_16_9,
+            WINDOW_8_5,
+            WINDOW_4_3,
+        ] {
+            let (equip, panel) = (&w.equip_pos, &w.panel_pos);
+            assert!(
+                panel.3 <= equip.3 && equip.1 <= panel.1,
+                "{}x{}: {:?} {:?}",
+                w.width,
+                w.height,
+                equip,
+                panel
+            );
+            assert!(panel.0 <= equip.0 && equip.2 <= panel.2);
+        }
+    }
+}
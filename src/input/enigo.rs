@@ -0,0 +1,35 @@
+use enigo::{Enigo, MouseButton, MouseControllable};
+
+use crate::input::InputBackend;
+
+pub struct EnigoInput {
+    enigo: Enigo,
+}
+
+impl EnigoInput {
+    pub fn new() -> EnigoInput {
+        EnigoInput {
+            enigo: Enigo::new(),
+        }
+    }
+}
+
+impl Default for EnigoInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputBackend for EnigoInput {
+    fn mouse_move_to(&mut self, x: i32, y: i32) {
+        self.enigo.mouse_move_to(x, y);
+    }
+
+    fn mouse_click(&mut self) {
+        self.enigo.mouse_click(MouseButton::Left);
+    }
+
+    fn mouse_scroll(&mut self, ticks: i32) {
+        self.enigo.mouse_scroll_y(ticks);
+    }
+}
@@ -0,0 +1,35 @@
+pub mod enigo;
+pub mod recording;
+pub mod xdotool;
+
+pub use self::enigo::EnigoInput;
+pub use self::recording::{InputAction, RecordingInput};
+pub use self::xdotool::XdotoolInput;
+
+/// Mouse control used by the scanner. Scroll amounts are raw wheel ticks,
+/// the scanner maps "scroll the backpack down" onto them with `scroll_direction` and `scroll_step`.
+pub trait InputBackend: Send {
+    fn mouse_move_to(&mut self, x: i32, y: i32);
+
+    fn mouse_click(&mut self);
+
+    fn mouse_scroll(&mut self, ticks: i32);
+}
+
+// default sign of a wheel tick that scrolls the backpack down
+pub fn default_scroll_direction() -> i32 {
+    if cfg!(windows) {
+        -1
+    } else {
+        1
+    }
+}
+
+// default wheel ticks for one coarse scroll
+pub fn default_scroll_step() -> i32 {
+    if cfg!(windows) {
+        5
+    } else {
+        1
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::{Arc, Mutex};
+
+use crate::input::InputBackend;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputAction {
+    MoveTo(i32, i32),
+    Click,
+    Scroll(i32),
+}
+
+// records every action instead of touching the mouse,
+// clones share the same log so it can be inspected after the scanner took ownership
+#[derive(Clone, Default)]
+pub struct RecordingInput {
+    actions: Arc<Mutex<Vec<InputAction>>>,
+}
+
+impl RecordingInput {
+    pub fn new() -> RecordingInput {
+        RecordingInput::default()
+    }
+
+    pub fn actions(&self) -> Vec<InputAction> {
+        self.actions.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.actions.lock().unwrap().clear();
+    }
+
+    // sum of all scroll ticks so far
+    pub fn total_scroll(&self) -> i32 {
+        self.actions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|a| match a {
+                InputAction::Scroll(t) => *t,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn push(&self, action: InputAction) {
+        self.actions.lock().unwrap().push(action);
+    }
+}
+
+impl InputBackend for RecordingInput {
+    fn mouse_move_to(&mut self, x: i32, y: i32) {
+        self.push(InputAction::MoveTo(x, y));
+    }
+
+    fn mouse_click(&mut self) {
+        self.push(InputAction::Click);
+    }
+
+    fn mouse_scroll(&mut self, ticks: i32) {
+        self.push(InputAction::Scroll(ticks));
+    }
+}
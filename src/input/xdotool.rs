@@ -0,0 +1,42 @@
+use std::process::Command;
+
+use log::error;
+
+use crate::input::InputBackend;
+
+// drives the X server through the `xdotool` command (XTest),
+// positive ticks scroll down like button 5
+pub struct XdotoolInput;
+
+impl XdotoolInput {
+    fn run(&self, args: &[String]) {
+        match Command::new("xdotool").args(args).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => error!("xdotool {:?} exited with {}", args, status),
+            Err(e) => error!("cannot run xdotool: {}", e),
+        }
+    }
+}
+
+impl InputBackend for XdotoolInput {
+    fn mouse_move_to(&mut self, x: i32, y: i32) {
+        self.run(&[String::from("mousemove"), x.to_string(), y.to_string()]);
+    }
+
+    fn mouse_click(&mut self) {
+        self.run(&[String::from("click"), String::from("1")]);
+    }
+
+    fn mouse_scroll(&mut self, ticks: i32) {
+        if ticks == 0 {
+            return;
+        }
+        let button = if ticks > 0 { "5" } else { "4" };
+        self.run(&[
+            String::from("click"),
+            String::from("--repeat"),
+            ticks.abs().to_string(),
+            String::from(button),
+        ]);
+    }
+}
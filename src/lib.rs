@@ -1,5 +1,6 @@
 pub mod common;
 pub mod capture;
+pub mod input;
 pub mod inference;
 pub mod info;
 pub mod scanner;
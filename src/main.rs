@@ -1,131 +1,266 @@
+use std::fs;
 use std::io::stdin;
 use std::path::Path;
-use std::time::{Duration, Instant, SystemTime};
+use std::process;
+use std::time::SystemTime;
 
-use yas::capture::{capture_absolute, capture_absolute_image};
+use yas::artifact::internal_artifact::InternalArtifact;
+use yas::artifact::diff::diff;
+use yas::artifact::merge::merge;
+use yas::capture::capture_absolute_image;
+use yas::common::error::YasError;
 use yas::common::utils;
-use yas::common::{PixelRect, RawImage};
-use yas::expo::good::GOODFormat;
-use yas::expo::mingyu_lab::MingyuLabFormat;
-use yas::expo::mona_uranai::MonaFormat;
+use yas::common::PixelRect;
+use yas::expo::convert::lost_fields;
+use yas::expo::{load_artifacts, ExportFormat, ALL_FORMATS};
+use yas::inference::bench::{load_samples, run as run_bench};
 use yas::inference::inference::CRNNModel;
-use yas::inference::pre_process::{
-    crop, image_to_raw, normalize, pre_process, raw_to_img, to_gray,
-};
-use yas::info::info;
+use yas::input::XdotoolInput;
+use yas::info::info::FieldBinarization;
+use yas::info::calibration::calibrate;
+use yas::info::layout::Layouts;
+use yas::info::preview::draw_layout;
+use yas::scanner::replay::{replay, ReplayIndex};
 use yas::scanner::yas_scanner::{YasScanner, YasScannerConfig};
 
-use clap::{App, Arg};
-use env_logger::{Builder, Env, Target};
-use image::imageops::grayscale;
-use image::{ImageBuffer, Pixel};
-use log::{info, warn, LevelFilter};
-use os_info;
-
-#[cfg(windows)]
-fn detect_gi_window() -> (PixelRect, bool) {
-    let rect: PixelRect;
-    let is_cloud: bool;
-    use winapi::shared::windef::DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE;
-    use winapi::um::wingdi::{GetDeviceCaps, HORZRES};
-    use winapi::um::winuser::{
-        GetDpiForSystem, GetSystemMetrics, SetForegroundWindow, SetProcessDPIAware,
-        SetThreadDpiAwarenessContext, ShowWindow, SW_RESTORE, SW_SHOW,
-    };
-    // use winapi::um::shellscalingapi::{SetProcessDpiAwareness, PROCESS_PER_MONITOR_DPI_AWARE};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use env_logger::Builder;
+use log::{error, info, warn, LevelFilter};
 
-    crate::utils::set_dpi_awareness();
+fn scan(matches: &ArgMatches, config: YasScannerConfig) -> Result<Vec<InternalArtifact>, YasError> {
+    let (rect, is_cloud) = utils::find_gi_window()?;
 
-    let hwnd = match utils::find_window_local() {
-        Ok(h) => {
-            is_cloud = false;
-            h
-        }
-        Err(_) => match utils::find_window_cloud() {
-            Ok(h) => {
-                is_cloud = true;
-                h
-            }
-            Err(_) => utils::error_and_quit("未找到原神窗口，请确认原神已经开启"),
-        },
-    };
+    // rect.scale(1.25);
+    info!(
+        "left = {}, top = {}, width = {}, height = {}",
+        rect.left, rect.top, rect.width, rect.height
+    );
+
+    let dir = matches.value_of("layout-dir").unwrap();
+    let layouts = Layouts::load(dir)?;
+    let mut info = layouts.scan_info(&rect)?;
 
-    unsafe {
-        ShowWindow(hwnd, SW_RESTORE);
+    let offset_x = matches
+        .value_of("offset-x")
+        .unwrap_or("0")
+        .parse::<i32>()
+        .unwrap();
+    let offset_y = matches
+        .value_of("offset-y")
+        .unwrap_or("0")
+        .parse::<i32>()
+        .unwrap();
+    info.left += offset_x;
+    info.top += offset_y;
+
+    let mut scanner = YasScanner::new(info.clone(), config, is_cloud)?;
+    if matches.value_of("input-backend") == Some("xdotool") {
+        scanner = scanner.with_input(Box::new(XdotoolInput));
     }
-    // utils::sleep(1000);
-    unsafe {
-        SetForegroundWindow(hwnd);
+
+    scanner.start()
+}
+
+fn bench(matches: &ArgMatches) {
+    let dir = matches.value_of("dir").unwrap();
+    let samples = match load_samples(dir) {
+        Ok(v) => v,
+        Err(e) => utils::error_and_quit(&e),
+    };
+    if samples.is_empty() {
+        utils::error_and_quit(&format!("{}中没有带标签的截图", dir));
     }
-    utils::sleep(1000);
+    info!("从{}读取了{}个带标签的截图", dir, samples.len());
 
-    rect = utils::get_client_rect(hwnd).unwrap();
+    let model = match CRNNModel::new(matches.value_of("model"), matches.value_of("dict")) {
+        Ok(v) => v,
+        Err(e) => utils::error_and_quit(&e.to_string()),
+    };
+    let mut binarization = FieldBinarization::default();
+    if let Some(spec) = matches.value_of("binarize") {
+        if let Err(e) = binarization.apply(spec) {
+            utils::error_and_quit(&e);
+        }
+    }
+    let report = match run_bench(&model, samples, &binarization) {
+        Ok(v) => v,
+        Err(e) => utils::error_and_quit(&e.to_string()),
+    };
+    if matches.is_present("show-errors") {
+        for m in report.mistakes.iter() {
+            info!("{}: `{}` -> `{}`", m.name, m.label, m.predicted);
+        }
+    }
+    print!("{}", report);
 }
 
-#[cfg(all(target_os = "linux"))]
-fn detect_gi_window() -> (PixelRect, bool) {
-    let rect: PixelRect;
-    let is_cloud: bool;
-    let window_id = unsafe {
-        String::from_utf8_unchecked(
-            std::process::Command::new("sh")
-                .arg("-c")
-                .arg(r#" xwininfo|grep "Window id"|cut -d " " -f 4 "#)
-                .output()
-                .unwrap()
-                .stdout,
-        )
+fn calibrate_layout(matches: &ArgMatches) {
+    let path = matches.value_of("screenshot").unwrap();
+    let img = match image::open(path) {
+        Ok(v) => v.to_rgb8(),
+        Err(e) => utils::error_and_quit(&format!("cannot open {}: {}", path, e)),
+    };
+    let window = match calibrate(&img) {
+        Ok(v) => v,
+        Err(e) => utils::error_and_quit(&e),
     };
-    let window_id = window_id.trim_end_matches("\n");
-
-    let position_size = unsafe {
-        String::from_utf8_unchecked(
-                std::process::Command::new("sh")
-                    .arg("-c")
-                    .arg(&format!(r#" xwininfo -id {window_id}|cut -f 2 -d :|tr -cd "0-9\n"|grep -v "^$"|sed -n "1,2p;5,6p" "#))
-                    .output()
-                    .unwrap()
-                    .stdout,
-            )
+    info!(
+        "背包：{}行{}列，圣遗物{}x{}，间隔{}x{}，第一个圣遗物位于({}, {})",
+        window.art_row,
+        window.art_col,
+        window.art_width,
+        window.art_height,
+        window.art_gap_x,
+        window.art_gap_y,
+        window.left_margin,
+        window.top_margin
+    );
+    info!(
+        "详情面板：({}, {}) - ({}, {})",
+        window.panel_pos.3, window.panel_pos.0, window.panel_pos.1, window.panel_pos.2
+    );
+
+    let output = match matches.value_of("output") {
+        Some(v) => String::from(v),
+        None => {
+            let dir = matches.value_of("layout-dir").unwrap();
+            if let Err(e) = fs::create_dir_all(dir) {
+                utils::error_and_quit(&format!("cannot create {}: {}", dir, e));
+            }
+            format!("{}/{}x{}.toml", dir, img.width(), img.height())
+        }
     };
+    let s = toml::to_string(&window).unwrap();
+    if let Err(e) = fs::write(&output, s) {
+        utils::error_and_quit(&format!("cannot write {}: {}", output, e));
+    }
+    info!("已保存到{}，相同比例的窗口将使用该布局", output);
+}
 
-    let mut info = position_size.split("\n");
+// the `ScanInfo` a scan would use, drawn over a screenshot of the window
+fn layout_preview(matches: &ArgMatches) -> Result<(), YasError> {
+    let dir = matches.value_of("layout-dir").unwrap();
+    let layouts = Layouts::load(dir)?;
+    let recorded = match matches.value_of("replay") {
+        Some(dir) => Some(ReplayIndex::load(dir)?.info),
+        None => None,
+    };
 
-    let left = info.next().unwrap().parse().unwrap();
-    let top = info.next().unwrap().parse().unwrap();
-    let width = info.next().unwrap().parse().unwrap();
-    let height = info.next().unwrap().parse().unwrap();
+    let (mut img, info) = match matches.value_of("screenshot") {
+        Some(path) => {
+            let img = match image::open(path) {
+                Ok(v) => v.to_rgb8(),
+                Err(e) => utils::error_and_quit(&format!("cannot open {}: {}", path, e)),
+            };
+            let info = match recorded {
+                Some(v) => v,
+                None => layouts.scan_info(&PixelRect {
+                    left: 0,
+                    top: 0,
+                    width: img.width() as i32,
+                    height: img.height() as i32,
+                })?,
+            };
+            (img, info)
+        }
+        None => {
+            let (rect, _) = utils::find_gi_window()?;
+            let mut info = match recorded {
+                Some(v) => v,
+                None => layouts.scan_info(&rect)?,
+            };
+            info.left += matches
+                .value_of("offset-x")
+                .unwrap_or("0")
+                .parse::<i32>()
+                .unwrap();
+            info.top += matches
+                .value_of("offset-y")
+                .unwrap_or("0")
+                .parse::<i32>()
+                .unwrap();
 
-    rect = PixelRect {
-        left,
-        top,
-        width,
-        height,
+            // what the scanner would capture, offsets included
+            let img = capture_absolute_image(&PixelRect {
+                left: info.left,
+                top: info.top,
+                width: info.width as i32,
+                height: info.height as i32,
+            })
+            .map_err(YasError::Capture)?;
+            (img, info)
+        }
     };
-    is_cloud = false; // todo: detect cloud genshin by title
 
-    (rect, is_cloud)
+    draw_layout(&mut img, &info);
+    let output = matches.value_of("output").unwrap();
+    if let Err(e) = img.save(output) {
+        utils::error_and_quit(&format!("cannot write {}: {}", output, e));
+    }
+    info!("已保存到{}", output);
+    Ok(())
 }
 
-fn open_local(path: String) -> RawImage {
-    let img = image::open(path).unwrap();
-    let img = grayscale(&img);
-    let raw_img = image_to_raw(img);
+fn convert(matches: &ArgMatches) -> Result<(), String> {
+    let input = matches.value_of("input").unwrap();
+    let to = match ExportFormat::from_name(matches.value_of("to").unwrap()) {
+        Some(v) => v,
+        None => return Err(format!("未知的格式：{}", matches.value_of("to").unwrap())),
+    };
+
+    let (from, artifacts) = load_artifacts(input)?;
+    info!(
+        "从{}读取了{}个圣遗物（{}）",
+        input,
+        artifacts.len(),
+        from.name()
+    );
+
+    for msg in lost_fields(from, to, &artifacts) {
+        warn!("{}", msg);
+    }
 
-    raw_img
+    let output = match matches.value_of("output") {
+        Some(v) => String::from(v),
+        None => format!("{}.json", to.name()),
+    };
+    to.save(&artifacts, &output)?;
+    info!("已保存到{}", output);
+    Ok(())
 }
 
-fn main() {
-    Builder::new().filter_level(LevelFilter::Info).init();
+fn diff_inventories(matches: &ArgMatches) {
+    let load = |path: &str| match load_artifacts(path) {
+        Ok((_, v)) => v,
+        Err(e) => utils::error_and_quit(&e),
+    };
+    let old = load(matches.value_of("old").unwrap());
+    let new = load(matches.value_of("new").unwrap());
 
-    #[cfg(windows)]
-    if !utils::is_admin() {
-        utils::error_and_quit("请以管理员身份运行该程序")
+    let result = diff(&old, &new);
+    print!("{}", result);
+
+    if let Some(path) = matches.value_of("json") {
+        let s = serde_json::to_string(&result).unwrap();
+        if let Err(e) = fs::write(path, s) {
+            utils::error_and_quit(&format!("cannot write {}: {}", path, e));
+        }
     }
+}
 
-    if let Some(v) = utils::check_update() {
-        warn!("检测到新版本，请手动更新：{}", v);
+// the sort order cannot be read from the game, an incremental scan of a differently
+// sorted backpack would stop at the wrong place, so the user has to confirm it
+fn confirm_sorted_by_newest() -> bool {
+    info!("增量扫描需要背包按“入手顺序”排序，已排序请输入y并按Enter：");
+    let mut s = String::new();
+    match stdin().read_line(&mut s) {
+        Ok(_) => s.trim().eq_ignore_ascii_case("y"),
+        Err(_) => false,
     }
+}
+
+fn main() {
+    Builder::new().filter_level(LevelFilter::Info).init();
 
     let matches = App::new("YAS - 原神圣遗物导出器")
         .version(utils::VERSION)
@@ -221,72 +356,353 @@ fn main() {
                 .takes_value(true)
                 .help("指定云·原神切换圣遗物等待时间(ms)"),
         )
+        .arg(
+            Arg::with_name("scroll-step")
+                .long("scroll-step")
+                .takes_value(true)
+                .help("翻页时每次滚动的滚轮格数（Windows默认为5，其他系统默认为1）"),
+        )
+        .arg(
+            Arg::with_name("invert-scroll")
+                .long("invert-scroll")
+                .help("反转滚轮方向"),
+        )
+        .arg(
+            Arg::with_name("input-backend")
+                .long("input-backend")
+                .takes_value(true)
+                .possible_values(&["enigo", "xdotool"])
+                .default_value("enigo")
+                .help("鼠标控制方式"),
+        )
+        .arg(
+            Arg::with_name("save-captures")
+                .long("save-captures")
+                .takes_value(true)
+                .help("将扫描到的圣遗物面板截图保存到指定目录，供--replay使用"),
+        )
+        .arg(
+            Arg::with_name("journal")
+                .long("journal")
+                .takes_value(true)
+                .help("扫描进度记录文件，每识别一个圣遗物写入一行，扫描中断后可用--resume继续"),
+        )
+        .arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .requires("journal")
+                .conflicts_with("replay")
+                .help("从--journal记录的位置继续上次中断的扫描"),
+        )
+        .arg(
+            Arg::with_name("overwrite-journal")
+                .long("overwrite-journal")
+                .requires("journal")
+                .conflicts_with("resume")
+                .help("--journal已有记录时清空重新扫描"),
+        )
+        .arg(
+            Arg::with_name("incremental")
+                .long("incremental")
+                .takes_value(true)
+                .help("增量扫描：读取之前导出的文件，遇到已有的圣遗物时停止，输出合并后的结果（需在游戏中按入手顺序排序）"),
+        )
+        .arg(
+            Arg::with_name("incremental-stop")
+                .long("incremental-stop")
+                .takes_value(true)
+                .help("增量扫描时连续遇到多少个已有圣遗物后停止（默认为5）"),
+        )
+        .arg(
+            Arg::with_name("model")
+                .long("model")
+                .takes_value(true)
+                .help("使用指定的onnx模型，默认使用内置模型"),
+        )
+        .arg(
+            Arg::with_name("dict")
+                .long("dict")
+                .takes_value(true)
+                .help("与模型对应的index_2_word.json，默认使用内置字典"),
+        )
+        .arg(
+            Arg::with_name("workers")
+                .long("workers")
+                .takes_value(true)
+                .help("识别线程数（默认为2）"),
+        )
+        .arg(
+            Arg::with_name("queue-size")
+                .long("queue-size")
+                .takes_value(true)
+                .help("等待识别的截图数量上限，超过后扫描会暂停等待识别（默认为8）"),
+        )
+        .arg(
+            Arg::with_name("binarize")
+                .long("binarize")
+                .takes_value(true)
+                .validator(|v| FieldBinarization::default().apply(&v))
+                .help("预处理二值化方式：fixed、fixed:<阈值>、otsu、sauvola或none，可按字段指定，如equip=sauvola,sub_stat=otsu"),
+        )
+        .arg(
+            Arg::with_name("decoder")
+                .long("decoder")
+                .takes_value(true)
+                .help("解码方式，lexicon会将圣遗物名、词条名和角色名限制在已知名称内，grammar还会将数值限制为合法格式")
+                .possible_values(&["greedy", "lexicon", "grammar"])
+                .default_value("greedy"),
+        )
+        .arg(
+            Arg::with_name("min-confidence")
+                .long("min-confidence")
+                .takes_value(true)
+                .help("识别置信度低于该值的字段将被标记（默认为0.9）"),
+        )
+        .arg(
+            Arg::with_name("confidence-report")
+                .long("confidence-report")
+                .takes_value(true)
+                .help("将低置信度字段写入该文件"),
+        )
+        .arg(
+            Arg::with_name("layout-dir")
+                .long("layout-dir")
+                .takes_value(true)
+                .default_value("layouts")
+                .help("自定义界面布局目录，其中的.toml或.json文件优先于内置布局，用于内置布局不支持的分辨率"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .takes_value(true)
+                .conflicts_with("save-captures")
+                .help("不启动游戏，识别--save-captures保存的截图目录"),
+        )
+        .subcommand(
+            SubCommand::with_name("convert")
+                .about("转换导出格式，不需要启动游戏")
+                .arg(
+                    Arg::with_name("input")
+                        .required(true)
+                        .help("之前导出的文件，格式自动识别"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .takes_value(true)
+                        .required(true)
+                        .possible_values(&["mona", "mingyulab", "good"])
+                        .help("目标格式"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .help("输出文件（默认为<目标格式>.json）"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("比较两次导出的圣遗物，列出新增、移除、升级和更换装备的圣遗物")
+                .arg(Arg::with_name("old").required(true).help("旧的导出文件"))
+                .arg(Arg::with_name("new").required(true).help("新的导出文件"))
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .takes_value(true)
+                        .help("同时将差异以JSON格式写入指定文件"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("用--dump保存并校正过标签的截图评估识别准确率和速度")
+                .arg(
+                    Arg::with_name("dir")
+                        .default_value("dumps")
+                        .help("包含<字段>_<序号>.png和对应.txt标签的目录"),
+                )
+                .arg(
+                    Arg::with_name("model")
+                        .long("model")
+                        .takes_value(true)
+                        .help("使用指定的onnx模型，默认使用内置模型"),
+                )
+                .arg(
+                    Arg::with_name("dict")
+                        .long("dict")
+                        .takes_value(true)
+                        .help("与模型对应的index_2_word.json，默认使用内置字典"),
+                )
+                .arg(
+                    Arg::with_name("binarize")
+                        .long("binarize")
+                        .takes_value(true)
+                        .help("与扫描时相同的各字段二值化方法，例如equip=sauvola,sub_stat=otsu"),
+                )
+                .arg(
+                    Arg::with_name("show-errors")
+                        .long("show-errors")
+                        .help("列出所有识别错误的截图"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("calibrate")
+                .about("从背包截图生成界面布局，用于内置布局不支持的分辨率")
+                .arg(
+                    Arg::with_name("screenshot")
+                        .required(true)
+                        .help("打开背包并选中一个圣遗物后，游戏窗口（不含标题栏）的截图"),
+                )
+                .arg(
+                    Arg::with_name("layout-dir")
+                        .long("layout-dir")
+                        .takes_value(true)
+                        .default_value("layouts")
+                        .help("保存布局的目录"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .help("输出文件（默认为<布局目录>/<宽>x<高>.toml）"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("layout-preview")
+                .about("在截图上标出扫描使用的各个区域、圣遗物点击位置和取色点，用于排查识别错误")
+                .arg(
+                    Arg::with_name("screenshot")
+                        .help("游戏窗口（不含标题栏）的截图，不指定时截取当前的原神窗口"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .default_value("layout_preview.png")
+                        .help("输出图片"),
+                )
+                .arg(
+                    Arg::with_name("layout-dir")
+                        .long("layout-dir")
+                        .takes_value(true)
+                        .default_value("layouts")
+                        .help("自定义界面布局目录"),
+                )
+                .arg(
+                    Arg::with_name("replay")
+                        .long("replay")
+                        .takes_value(true)
+                        .help("使用--save-captures保存的目录中记录的布局，而不是按分辨率匹配"),
+                )
+                .arg(
+                    Arg::with_name("offset-x")
+                        .long("offset-x")
+                        .takes_value(true)
+                        .help("横坐标偏移，仅在截取当前窗口时有效"),
+                )
+                .arg(
+                    Arg::with_name("offset-y")
+                        .long("offset-y")
+                        .takes_value(true)
+                        .help("纵坐标偏移，仅在截取当前窗口时有效"),
+                ),
+        )
         .get_matches();
-    let config = YasScannerConfig::from_match(&matches);
 
-    let rect: PixelRect;
-    let is_cloud: bool;
-    (rect, is_cloud) = detect_gi_window();
+    if let Some(m) = matches.subcommand_matches("convert") {
+        // a command line tool, failures go to the exit code instead of waiting for Enter
+        if let Err(e) = convert(m) {
+            error!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    if let Some(m) = matches.subcommand_matches("diff") {
+        diff_inventories(m);
+        return;
+    }
+    if let Some(m) = matches.subcommand_matches("bench") {
+        bench(m);
+        return;
+    }
+    if let Some(m) = matches.subcommand_matches("calibrate") {
+        calibrate_layout(m);
+        return;
+    }
+    if let Some(m) = matches.subcommand_matches("layout-preview") {
+        if let Err(e) = layout_preview(m) {
+            utils::error_and_quit(&e.to_string());
+        }
+        return;
+    }
 
-    // rect.scale(1.25);
-    info!(
-        "left = {}, top = {}, width = {}, height = {}",
-        rect.left, rect.top, rect.width, rect.height
-    );
+    #[cfg(windows)]
+    if !utils::is_admin() {
+        utils::error_and_quit("请以管理员身份运行该程序")
+    }
 
-    let mut info: info::ScanInfo;
-    if rect.height * 43 == rect.width * 18 {
-        info =
-            info::ScanInfo::from_43_18(rect.width as u32, rect.height as u32, rect.left, rect.top);
-    } else if rect.height * 16 == rect.width * 9 {
-        info =
-            info::ScanInfo::from_16_9(rect.width as u32, rect.height as u32, rect.left, rect.top);
-    } else if rect.height * 8 == rect.width * 5 {
-        info = info::ScanInfo::from_8_5(rect.width as u32, rect.height as u32, rect.left, rect.top);
-    } else if rect.height * 4 == rect.width * 3 {
-        info = info::ScanInfo::from_4_3(rect.width as u32, rect.height as u32, rect.left, rect.top);
-    } else if rect.height * 7 == rect.width * 3 {
-        info = info::ScanInfo::from_7_3(rect.width as u32, rect.height as u32, rect.left, rect.top);
-    } else {
-        utils::error_and_quit("不支持的分辨率");
+    if let Some(v) = utils::check_update() {
+        warn!("检测到新版本，请手动更新：{}", v);
     }
 
-    let offset_x = matches
-        .value_of("offset-x")
-        .unwrap_or("0")
-        .parse::<i32>()
-        .unwrap();
-    let offset_y = matches
-        .value_of("offset-y")
-        .unwrap_or("0")
-        .parse::<i32>()
-        .unwrap();
-    info.left += offset_x;
-    info.top += offset_y;
+    let mut config = YasScannerConfig::from_match(&matches);
 
-    let mut scanner = YasScanner::new(info.clone(), config, is_cloud);
+    let previous = match matches.value_of("incremental") {
+        Some(path) => match load_artifacts(path) {
+            Ok((_, v)) => {
+                info!("增量扫描：已读取{}个圣遗物", v.len());
+                if !confirm_sorted_by_newest() {
+                    utils::error_and_quit("背包未确认按“入手顺序”排序，无法增量扫描");
+                }
+                Some(v)
+            }
+            Err(e) => utils::error_and_quit(&e),
+        },
+        None => None,
+    };
+    if let Some(ref v) = previous {
+        config.known_artifacts = v.clone();
+    }
 
     let now = SystemTime::now();
-    let results = scanner.start();
+    let results = match matches.value_of("replay") {
+        Some(dir) => replay(dir, &config),
+        None => scan(&matches, config),
+    };
+    let results = match results {
+        Ok(v) => v,
+        Err(e) => utils::error_and_quit(&e.to_string()),
+    };
+    let results = match previous {
+        Some(ref v) => {
+            let merged = merge(v, &results);
+            info!("合并后共{}个圣遗物", merged.len());
+            merged
+        }
+        None => results,
+    };
     let t = now.elapsed().unwrap().as_secs_f64();
     info!("time: {}s", t);
 
     let output_dir = Path::new(matches.value_of("output-dir").unwrap());
 
+    let mut save_failed = false;
     if let Some(output_format) = matches.value_of("output-format") {
-        let filename = output_dir.join(format!("{output_format}.json"));
-        let filename = filename.to_str().unwrap();
-
-        match output_format {
-            "mona" => MonaFormat::new(&results).save(filename),
-            "mingyulab" => MingyuLabFormat::new(&results).save(filename),
-            "good" => GOODFormat::new(&results).save(filename),
-            "all" => {
-                MonaFormat::new(&results).save(filename);
-                MingyuLabFormat::new(&results).save(filename);
-                GOODFormat::new(&results).save(filename);
+        let formats = match ExportFormat::from_name(output_format) {
+            Some(v) => vec![v],
+            None => ALL_FORMATS.to_vec(),
+        };
+
+        // each format goes to its own <format>.json
+        for format in formats {
+            let filename = output_dir.join(format!("{}.json", format.name()));
+            if let Err(e) = format.save(&results, filename.to_str().unwrap()) {
+                error!("{}", e);
+                save_failed = true;
             }
-            _ => unreachable!(""),
         }
     }
     // let info = info;
@@ -297,5 +713,8 @@ fn main() {
     // println!("{}", s);
     info!("识别结束，请按Enter退出");
     let mut s = String::new();
-    stdin().read_line(&mut s);
+    let _ = stdin().read_line(&mut s);
+    if save_failed {
+        process::exit(1);
+    }
 }
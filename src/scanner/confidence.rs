@@ -0,0 +1,63 @@
+use std::fs;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::error::YasError;
+use crate::inference::inference::Recognition;
+use crate::scanner::journal::ScanPosition;
+use crate::scanner::yas_scanner::YasScanResult;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FieldConfidence {
+    pub confidence: f32,
+    pub chars: Vec<f32>,
+}
+
+impl From<&Recognition> for FieldConfidence {
+    fn from(r: &Recognition) -> FieldConfidence {
+        FieldConfidence {
+            confidence: r.confidence,
+            chars: r.char_confidences.clone(),
+        }
+    }
+}
+
+// one line of the sidecar report, enough to find the artifact in game
+#[derive(Serialize)]
+pub struct LowConfidenceField {
+    pub index: u32,
+    pub row: u32,
+    pub col: u32,
+    pub field: String,
+    pub text: String,
+    pub confidence: f32,
+    pub chars: Vec<f32>,
+}
+
+pub fn low_confidence_fields(
+    position: ScanPosition,
+    result: &YasScanResult,
+    threshold: f32,
+) -> Vec<LowConfidenceField> {
+    result
+        .confidence
+        .iter()
+        .filter(|(_, c)| c.confidence < threshold)
+        .map(|(field, c)| LowConfidenceField {
+            index: position.index,
+            row: position.row,
+            col: position.col,
+            field: field.clone(),
+            text: String::from(result.field_text(field)),
+            confidence: c.confidence,
+            chars: c.chars.clone(),
+        })
+        .collect()
+}
+
+pub fn save_report(path: &str, fields: &[LowConfidenceField]) -> Result<(), YasError> {
+    let s = serde_json::to_string_pretty(fields).map_err(io::Error::from)?;
+    fs::write(path, s)?;
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::common::error::YasError;
+use crate::scanner::yas_scanner::YasScanResult;
+
+// where a panel sits in the backpack, `row` counts from the top of the whole list
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ScanPosition {
+    pub index: u32,
+    pub row: u32,
+    pub col: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub position: ScanPosition,
+    pub result: YasScanResult,
+}
+
+// one json object per line, flushed after every artifact so that a crash
+// loses at most the line being written
+pub struct ScanJournal {
+    file: File,
+}
+
+impl ScanJournal {
+    // a journal with entries is from an interrupted scan, it is kept unless `overwrite`
+    pub fn create(path: &str, overwrite: bool) -> Result<ScanJournal, YasError> {
+        let has_entries = fs::metadata(path).map(|m| m.len() > 0).unwrap_or(false);
+        if has_entries && !overwrite {
+            return Err(YasError::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{}已有扫描记录，继续上次的扫描请加--resume，重新扫描请加--overwrite-journal",
+                    path
+                ),
+            )));
+        }
+        let file = File::create(path)?;
+        Ok(ScanJournal { file })
+    }
+
+    pub fn append_to(path: &str) -> Result<ScanJournal, YasError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(ScanJournal { file })
+    }
+
+    pub fn write(&mut self, entry: &JournalEntry) -> Result<(), YasError> {
+        let line = serde_json::to_string(entry).map_err(io::Error::from)?;
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+pub fn load_journal(path: &str) -> Result<Vec<JournalEntry>, YasError> {
+    if !Path::new(path).exists() {
+        return Ok(Vec::new());
+    }
+
+    let reader = BufReader::new(fs::File::open(path)?);
+    let mut entries: Vec<JournalEntry> = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<JournalEntry>(&line) {
+            Ok(v) => entries.push(v),
+            // usually the last line, cut off when the scan was killed
+            Err(e) => warn!("journal line {} ignored: {}", i + 1, e),
+        }
+    }
+
+    Ok(entries)
+}
+
+// the panel after the last journaled one, where a resumed scan starts
+pub fn resume_position(entries: &[JournalEntry], col: u32) -> ScanPosition {
+    let index = match entries.iter().map(|e| e.position.index).max() {
+        Some(v) => v + 1,
+        None => 0,
+    };
+    ScanPosition {
+        index,
+        row: index / col,
+        col: index % col,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::temp_path::TempPath;
+
+    #[test]
+    fn create_keeps_a_journal_with_entries() {
+        let temp = TempPath::new("journal");
+        let path = temp.to_str().unwrap();
+        ScanJournal::create(path, false).unwrap();
+        // an empty journal is started over
+        ScanJournal::create(path, false).unwrap();
+
+        fs::write(path, "{}\n").unwrap();
+        assert!(ScanJournal::create(path, false).is_err());
+        assert_eq!(fs::read_to_string(path).unwrap(), "{}\n");
+
+        ScanJournal::create(path, true).unwrap();
+        assert_eq!(fs::metadata(path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn resume_after_the_last_written_entry() {
+        let temp = TempPath::new("journal-resume");
+        let path = temp.to_str().unwrap();
+        let mut journal = ScanJournal::create(path, false).unwrap();
+        for &index in [4, 12, 9].iter() {
+            let entry = JournalEntry {
+                position: ScanPosition {
+                    index,
+                    row: index / 8,
+                    col: index % 8,
+                },
+                result: serde_json::from_str(SCAN_RESULT).unwrap(),
+            };
+            journal.write(&entry).unwrap();
+        }
+        // a line cut off when the scan was killed
+        write!(journal.file, "{{\"position\":").unwrap();
+
+        let entries = load_journal(path).unwrap();
+        assert_eq!(entries.len(), 3);
+        let next = resume_position(&entries, 8);
+        assert_eq!((next.index, next.row, next.col), (13, 1, 5));
+        assert_eq!(resume_position(&[], 8).index, 0);
+    }
+
+    const SCAN_RESULT: &str = r#"{"name":"","main_stat_name":"","main_stat_value":"","sub_stat_1":"","sub_stat_2":"","sub_stat_3":"","sub_stat_4":"","level":"","equip":"","star":5}"#;
+}
@@ -1 +1,5 @@
-pub mod yas_scanner;
\ No newline at end of file
+pub mod yas_scanner;
+pub mod replay;
+pub mod journal;
+pub mod confidence;
+pub mod simulator;
@@ -0,0 +1,180 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::artifact::internal_artifact::InternalArtifact;
+use crate::common::color::Color;
+use crate::common::error::YasError;
+use crate::common::RawCaptureImage;
+use crate::inference::inference::CRNNModel;
+use crate::info::info::ScanInfo;
+use crate::scanner::journal::ScanPosition;
+use crate::scanner::yas_scanner::{
+    spawn_recognizer, star_from_color, PanelRecognizer, YasScannerConfig,
+};
+
+const INDEX_FILE: &str = "replay.json";
+
+#[derive(Serialize, Deserialize)]
+pub struct ReplayPanel {
+    pub file: String,
+    pub row: u32,
+    pub col: u32,
+    pub star_color: Color,
+}
+
+// a replay directory contains `replay.json` and one png per panel,
+// panel positions in `info` are used to crop the fields out of the panels
+#[derive(Serialize, Deserialize)]
+pub struct ReplayIndex {
+    pub info: ScanInfo,
+    pub panels: Vec<ReplayPanel>,
+}
+
+impl ReplayIndex {
+    pub fn load(dir: &str) -> Result<ReplayIndex, YasError> {
+        let path = Path::new(dir).join(INDEX_FILE);
+        let content = fs::read_to_string(&path)?;
+        let index = serde_json::from_str(&content).map_err(io::Error::from)?;
+        Ok(index)
+    }
+}
+
+pub struct CaptureRecorder {
+    dir: PathBuf,
+    index: ReplayIndex,
+}
+
+impl CaptureRecorder {
+    pub fn new(dir: &str, info: &ScanInfo) -> Result<CaptureRecorder, YasError> {
+        fs::create_dir_all(dir)?;
+
+        Ok(CaptureRecorder {
+            dir: PathBuf::from(dir),
+            index: ReplayIndex {
+                info: info.clone(),
+                panels: Vec::new(),
+            },
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        capture: &RawCaptureImage,
+        star_color: Color,
+        row: u32,
+        col: u32,
+    ) -> Result<(), YasError> {
+        let file = format!("{}.png", self.index.panels.len());
+        let path = self.dir.join(&file);
+        capture.save(&path).map_err(YasError::Capture)?;
+
+        self.index.panels.push(ReplayPanel {
+            file,
+            row,
+            col,
+            star_color,
+        });
+        Ok(())
+    }
+
+    pub fn finish(&self) -> Result<(), YasError> {
+        let path = self.dir.join(INDEX_FILE);
+        let s = serde_json::to_string(&self.index).map_err(io::Error::from)?;
+        fs::write(&path, s)?;
+        Ok(())
+    }
+}
+
+// feed the saved panels through the recognition thread, no game window is needed
+pub fn replay(dir: &str, config: &YasScannerConfig) -> Result<Vec<InternalArtifact>, YasError> {
+    let index = ReplayIndex::load(dir)?;
+    info!("replay {} panels from {}", index.panels.len(), dir);
+
+    let model = Arc::new(CRNNModel::new(
+        config.model.as_deref(),
+        config.dict.as_deref(),
+    )?);
+    let mut info = index.info.clone();
+    config.override_binarization(&mut info);
+    let reader = Arc::new(PanelRecognizer::new(model, info.clone(), config));
+    let (tx, handle) = spawn_recognizer(reader, info, config, None, Vec::new())?;
+    for (i, panel) in index.panels.iter().enumerate() {
+        let star = star_from_color(&panel.star_color);
+        if star < config.min_star {
+            break;
+        }
+        if panel.row >= config.max_row {
+            break;
+        }
+
+        let path = Path::new(dir).join(&panel.file);
+        let capture = RawCaptureImage::load(&path).map_err(YasError::Capture)?;
+        let position = ScanPosition {
+            index: i as u32,
+            row: panel.row,
+            col: panel.col,
+        };
+        if tx.send((capture, star, position)).is_err() {
+            break;
+        }
+    }
+    drop(tx);
+
+    let results = match handle.join() {
+        Ok(v) => v?,
+        Err(_) => return Err(YasError::Recognition(String::from("识别线程异常退出"))),
+    };
+    info!("count: {}", results.len());
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::temp_path::TempPath;
+
+    #[test]
+    fn recorded_panels_load_back() {
+        let dir = TempPath::new("replay");
+        let info = ScanInfo::from_16_9(1600, 900, 0, 0);
+        let mut recorder = CaptureRecorder::new(dir.to_str().unwrap(), &info).unwrap();
+        let capture = RawCaptureImage {
+            data: (0..4 * 3 * 2)
+                .map(|i| if i % 4 == 3 { 255 } else { i as u8 * 10 })
+                .collect(),
+            w: 3,
+            h: 2,
+        };
+        let color = Color::from(1, 2, 3);
+        recorder.record(&capture, color, 4, 5).unwrap();
+        recorder.finish().unwrap();
+
+        let index = ReplayIndex::load(dir.to_str().unwrap()).unwrap();
+        assert_eq!(index.panels.len(), 1);
+        assert_eq!((index.panels[0].row, index.panels[0].col), (4, 5));
+        let loaded = RawCaptureImage::load(&dir.join(&index.panels[0].file)).unwrap();
+        assert_eq!((loaded.w, loaded.h), (3, 2));
+        assert_eq!(loaded.data, capture.data);
+    }
+
+    #[test]
+    fn record_reports_save_errors() {
+        let dir = TempPath::new("replay-gone");
+        let info = ScanInfo::from_16_9(1600, 900, 0, 0);
+        let mut recorder = CaptureRecorder::new(dir.to_str().unwrap(), &info).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let capture = RawCaptureImage {
+            data: vec![0; 4],
+            w: 1,
+            h: 1,
+        };
+        assert!(recorder
+            .record(&capture, Color::from(0, 0, 0), 0, 0)
+            .is_err());
+    }
+}
@@ -0,0 +1,647 @@
+use std::sync::{Arc, Mutex};
+
+use image::{Rgb, RgbImage};
+
+use crate::artifact::internal_artifact::{
+    ArtifactSetName, ArtifactSlot, ArtifactStat, ArtifactStatName, InternalArtifact,
+    ARTIFACT_NAMES_CHS,
+};
+use crate::artifact::validation::display_value;
+use crate::capture::CaptureBackend;
+use crate::common::error::YasError;
+use crate::common::{PixelRect, PixelRectBound, RawCaptureImage, RawImage};
+use crate::info::info::ScanInfo;
+use crate::info::window_info::WindowInfo;
+use crate::input::{default_scroll_direction, InputBackend};
+use crate::scanner::yas_scanner::{PanelBuffers, PanelReader, YasScanResult};
+
+const BACKGROUND: Rgb<u8> = Rgb([233, 229, 220]);
+const CARD: Rgb<u8> = Rgb([164, 150, 126]);
+const PANEL: Rgb<u8> = Rgb([236, 229, 216]);
+const LEVEL: Rgb<u8> = Rgb([57, 67, 79]);
+const TEXT: Rgb<u8> = Rgb([74, 83, 102]);
+
+// same palette as `star_from_color`
+fn star_color(star: u32) -> Rgb<u8> {
+    match star {
+        5 => Rgb([188, 105, 50]),
+        4 => Rgb([161, 86, 224]),
+        3 => Rgb([81, 127, 203]),
+        2 => Rgb([42, 143, 114]),
+        _ => Rgb([113, 119, 139]),
+    }
+}
+
+// the synthetic panel carries its inventory index in the first substat line
+fn index_color(index: usize) -> Rgb<u8> {
+    Rgb([(index >> 16) as u8, (index >> 8) as u8, index as u8])
+}
+
+fn contains(rect: &PixelRectBound, x: i32, y: i32) -> bool {
+    x >= rect.left && x <= rect.right && y >= rect.top && y <= rect.bottom
+}
+
+struct SimState {
+    info: ScanInfo,
+    artifacts: Vec<InternalArtifact>,
+    panels: Option<Vec<RgbImage>>,
+
+    // wheel ticks per row, and the sign of "down". Like in game a row need not be a
+    // whole number of ticks, so scrolling tick by tick can overshoot a row boundary
+    ticks_per_row: f64,
+    scroll_direction: i32,
+    scroll_ticks: i32,
+
+    mouse: (i32, i32),
+    selected: Option<usize>,
+    captured: Vec<usize>,
+}
+
+impl SimState {
+    #[allow(clippy::manual_div_ceil)]
+    fn total_rows(&self) -> i32 {
+        let col = self.info.art_col as usize;
+        ((self.artifacts.len() + col - 1) / col) as i32
+    }
+
+    fn max_scroll_px(&self) -> i32 {
+        (self.total_rows() - self.info.art_row as i32).max(0) * self.row_pitch()
+    }
+
+    fn max_scroll_ticks(&self) -> i32 {
+        (self.max_scroll_px() as f64 * self.ticks_per_row / self.row_pitch() as f64).ceil() as i32
+    }
+
+    fn row_pitch(&self) -> i32 {
+        (self.info.art_height + self.info.art_gap_y) as i32
+    }
+
+    fn scroll_px(&self) -> i32 {
+        let px = self.scroll_ticks as f64 * self.row_pitch() as f64 / self.ticks_per_row;
+        (px.round() as i32).min(self.max_scroll_px())
+    }
+
+    fn in_grid(&self, x: i32) -> bool {
+        x < self.info.panel_position.left
+    }
+
+    // inventory index of the card under a window relative position
+    fn card_at(&self, x: i32, y: i32) -> Option<usize> {
+        let info = &self.info;
+        let pitch_x = (info.art_width + info.art_gap_x) as i32;
+        let vx = x - info.left_margin as i32;
+        let vy = y - info.top_margin as i32 + self.scroll_px();
+        if !self.in_grid(x) || vx < 0 || vx % pitch_x >= info.art_width as i32 {
+            return None;
+        }
+        if vy < 0 || vy % self.row_pitch() >= info.art_height as i32 {
+            return None;
+        }
+
+        let col = vx / pitch_x;
+        let row = vy / self.row_pitch();
+        if col >= info.art_col as i32 || row >= self.total_rows() {
+            return None;
+        }
+        let index = (row * info.art_col as i32 + col) as usize;
+        if index < self.artifacts.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    fn panel_pixel(&self, index: usize, x: i32, y: i32) -> Rgb<u8> {
+        let info = &self.info;
+        if let Some(ref panels) = self.panels {
+            let px = (x - info.panel_position.left) as u32;
+            let py = (y - info.panel_position.top) as u32;
+            let panel = &panels[index];
+            if px < panel.width() && py < panel.height() {
+                return *panel.get_pixel(px, py);
+            }
+            return PANEL;
+        }
+
+        if contains(&info.pool_position, x, y) {
+            // differs between neighbours so `wait_until_switched` sees the switch
+            let v = (40 + (index * 37) % 180) as u8;
+            return Rgb([v, v, v]);
+        }
+        if contains(&info.level_position, x, y) {
+            return LEVEL;
+        }
+        if contains(&info.sub_stat1_position, x, y) {
+            return index_color(index);
+        }
+        if y < info.main_stat_name_position.top {
+            return star_color(self.artifacts[index].star);
+        }
+        PANEL
+    }
+
+    // window relative
+    fn pixel(&self, x: i32, y: i32) -> Rgb<u8> {
+        let info = &self.info;
+        // panel_down samples the level badge before anything is selected
+        if x == info.level_position.left && y == info.level_position.bottom {
+            return LEVEL;
+        }
+        // something for the preprocessing of the artifact count to find, `SimReader`
+        // does not look at it
+        let count = &info.art_count_position;
+        if contains(count, x, y) && (x - count.left) % 8 < 4 && y > count.top + 4 {
+            return TEXT;
+        }
+        if contains(&info.panel_position, x, y) {
+            return match self.selected {
+                Some(index) => self.panel_pixel(index, x, y),
+                None => PANEL,
+            };
+        }
+        match self.card_at(x, y) {
+            Some(_) => CARD,
+            None => BACKGROUND,
+        }
+    }
+}
+
+/// A fake backpack driven by the scanner through `SimCapture` and `SimInput`.
+/// The window sits at the screen origin, clicks select the card under the mouse
+/// and wheel ticks over the grid scroll it by `ticks_per_row` per row.
+#[derive(Clone)]
+pub struct SimInventory {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl SimInventory {
+    pub fn new(
+        artifacts: Vec<InternalArtifact>,
+        window: &WindowInfo,
+        width: u32,
+        height: u32,
+    ) -> SimInventory {
+        let info = window.to_scan_info(height as f64, width as f64, 0, 0);
+        SimInventory {
+            state: Arc::new(Mutex::new(SimState {
+                info,
+                artifacts,
+                panels: None,
+                ticks_per_row: 10.0,
+                scroll_direction: default_scroll_direction(),
+                scroll_ticks: 0,
+                mouse: (0, 0),
+                selected: None,
+                captured: Vec::new(),
+            })),
+        }
+    }
+
+    // use real panel screenshots (e.g. from a replay directory), one per artifact,
+    // instead of the synthetic panel, so recognition can run end to end
+    pub fn with_panels(self, panels: Vec<RgbImage>) -> SimInventory {
+        {
+            let mut state = self.state.lock().unwrap();
+            assert_eq!(panels.len(), state.artifacts.len());
+            state.panels = Some(panels);
+        }
+        self
+    }
+
+    pub fn with_scroll(self, scroll_direction: i32, ticks_per_row: f64) -> SimInventory {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.scroll_direction = scroll_direction;
+            state.ticks_per_row = ticks_per_row;
+        }
+        self
+    }
+
+    pub fn scan_info(&self) -> ScanInfo {
+        self.state.lock().unwrap().info.clone()
+    }
+
+    pub fn capture(&self) -> SimCapture {
+        SimCapture {
+            state: self.state.clone(),
+        }
+    }
+
+    pub fn input(&self) -> SimInput {
+        SimInput {
+            state: self.state.clone(),
+        }
+    }
+
+    // inventory indices in the order their panels were captured by the scanner
+    pub fn captured_indices(&self) -> Vec<usize> {
+        self.state.lock().unwrap().captured.clone()
+    }
+
+    // reads synthetic panels back as the text the game would show
+    pub fn reader(&self) -> SimReader {
+        let state = self.state.lock().unwrap();
+        SimReader {
+            info: state.info.clone(),
+            artifacts: state.artifacts.clone(),
+        }
+    }
+
+    pub fn scrolled_rows(&self) -> f64 {
+        let state = self.state.lock().unwrap();
+        state.scroll_px() as f64 / state.row_pitch() as f64
+    }
+
+    pub fn render(&self) -> RgbImage {
+        let state = self.state.lock().unwrap();
+        RgbImage::from_fn(state.info.width, state.info.height, |x, y| {
+            state.pixel(x as i32, y as i32)
+        })
+    }
+}
+
+pub struct SimCapture {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl CaptureBackend for SimCapture {
+    fn capture(&self, rect: &PixelRect) -> Result<Vec<u8>, String> {
+        let mut state = self.state.lock().unwrap();
+        if rect.left < 0
+            || rect.top < 0
+            || (rect.left + rect.width) as u32 > state.info.width
+            || (rect.top + rect.height) as u32 > state.info.height
+        {
+            return Err(format!("capture {:?} out of bounds", rect));
+        }
+
+        let mut buffer: Vec<u8> = Vec::with_capacity((rect.width * rect.height * 4) as usize);
+        for y in rect.top..rect.top + rect.height {
+            for x in rect.left..rect.left + rect.width {
+                let p = state.pixel(x, y);
+                buffer.push(p.0[2]);
+                buffer.push(p.0[1]);
+                buffer.push(p.0[0]);
+                buffer.push(255);
+            }
+        }
+
+        let panel = &state.info.panel_position;
+        if rect.left == panel.left && rect.top == panel.top {
+            if let Some(index) = state.selected {
+                state.captured.push(index);
+            }
+        }
+
+        Ok(buffer)
+    }
+}
+
+pub struct SimInput {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl InputBackend for SimInput {
+    fn mouse_move_to(&mut self, x: i32, y: i32) {
+        self.state.lock().unwrap().mouse = (x, y);
+    }
+
+    fn mouse_click(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        let (x, y) = state.mouse;
+        if let Some(index) = state.card_at(x, y) {
+            state.selected = Some(index);
+        }
+    }
+
+    fn mouse_scroll(&mut self, ticks: i32) {
+        let mut state = self.state.lock().unwrap();
+        if !state.in_grid(state.mouse.0) {
+            return;
+        }
+        let ticks = state.scroll_ticks + ticks * state.scroll_direction;
+        state.scroll_ticks = ticks.max(0).min(state.max_scroll_ticks());
+    }
+}
+
+// stands in for OCR on synthetic panels, so a scan of the simulator can be compared
+// with its inventory
+pub struct SimReader {
+    info: ScanInfo,
+    artifacts: Vec<InternalArtifact>,
+}
+
+fn title(set_name: &ArtifactSetName, slot: &ArtifactSlot) -> String {
+    ARTIFACT_NAMES_CHS
+        .iter()
+        .find(|&&name| {
+            ArtifactSetName::from_zh_cn(name).as_ref() == Some(set_name)
+                && ArtifactSlot::from_zh_cn(name).as_ref() == Some(slot)
+        })
+        .map_or_else(String::new, |&name| String::from(name))
+}
+
+fn stat_name(name: &ArtifactStatName) -> &'static str {
+    use ArtifactStatName::*;
+
+    match name {
+        HealingBonus => "治疗加成",
+        CriticalDamage => "暴击伤害",
+        Critical => "暴击率",
+        Atk | AtkPercentage => "攻击力",
+        ElementalMastery => "元素精通",
+        Recharge => "元素充能效率",
+        Hp | HpPercentage => "生命值",
+        Def | DefPercentage => "防御力",
+        ElectroBonus => "雷元素伤害加成",
+        PyroBonus => "火元素伤害加成",
+        HydroBonus => "水元素伤害加成",
+        CryoBonus => "冰元素伤害加成",
+        AnemoBonus => "风元素伤害加成",
+        GeoBonus => "岩元素伤害加成",
+        DendroBonus => "草元素伤害加成",
+        PhysicalBonus => "物理伤害加成",
+    }
+}
+
+fn stat_value(stat: &ArtifactStat) -> String {
+    if stat.name.is_percentage() {
+        format!("{:.1}%", display_value(stat))
+    } else {
+        format!("{}", stat.value.round())
+    }
+}
+
+fn sub_stat(stat: &Option<ArtifactStat>) -> String {
+    match stat {
+        Some(stat) => format!("{}+{}", stat_name(&stat.name), stat_value(stat)),
+        None => String::new(),
+    }
+}
+
+impl PanelReader for SimReader {
+    fn read_count(&self, _image: &RawImage) -> Result<String, YasError> {
+        Ok(format!("圣遗物 {}/1500", self.artifacts.len()))
+    }
+
+    fn read_panel(
+        &self,
+        _buffers: &mut PanelBuffers,
+        capture: &RawCaptureImage,
+        star: u32,
+        _seq: u32,
+    ) -> Result<YasScanResult, YasError> {
+        let info = &self.info;
+        let x = (info.sub_stat1_position.right - info.panel_position.left) as u32;
+        let y = (info.sub_stat1_position.top - info.panel_position.top) as u32;
+        let offset = ((y * capture.w + x) * 4) as usize;
+        let (b, g, r) = (
+            capture.data[offset],
+            capture.data[offset + 1],
+            capture.data[offset + 2],
+        );
+        let index = ((r as usize) << 16) | ((g as usize) << 8) | b as usize;
+        let artifact = &self.artifacts[index];
+
+        Ok(YasScanResult {
+            name: title(&artifact.set_name, &artifact.slot),
+            main_stat_name: String::from(stat_name(&artifact.main_stat.name)),
+            main_stat_value: stat_value(&artifact.main_stat),
+            sub_stat_1: sub_stat(&artifact.sub_stat_1),
+            sub_stat_2: sub_stat(&artifact.sub_stat_2),
+            sub_stat_3: sub_stat(&artifact.sub_stat_3),
+            sub_stat_4: sub_stat(&artifact.sub_stat_4),
+            level: format!("+{}", artifact.level),
+            equip: match artifact.equip {
+                Some(ref name) => format!("{}已装备", name),
+                None => String::new(),
+            },
+            star,
+            confidence: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::artifact::validation::{main_stat_value, max_level, max_roll, validate};
+    use crate::info::window_info::WINDOW_16_9;
+    use crate::scanner::yas_scanner::{YasScanner, YasScannerConfig};
+
+    // rounded like the panel shows it, so the scanned stat is the same
+    fn shown(name: ArtifactStatName, value: f64) -> ArtifactStat {
+        let text = if name.is_percentage() {
+            format!("{:.1}", value)
+        } else {
+            format!("{}", value.round())
+        };
+        ArtifactStat::from_display(name, text.parse().unwrap())
+    }
+
+    // a valid flower or feather, different for every `i`: every substat rolled once at
+    // the top tier and the upgrades on one of them
+    #[allow(clippy::manual_is_multiple_of)]
+    fn artifact(i: usize, star: u32) -> InternalArtifact {
+        use ArtifactStatName::*;
+
+        let sets: Vec<ArtifactSetName> = ArtifactSetName::iter()
+            .filter(|s| {
+                !title(s, &ArtifactSlot::Flower).is_empty()
+                    && !title(s, &ArtifactSlot::Feather).is_empty()
+            })
+            .collect();
+        let (slot, main) = if i % 2 == 0 {
+            (ArtifactSlot::Flower, Hp)
+        } else {
+            (ArtifactSlot::Feather, Atk)
+        };
+        let level = 4 * (i / 2 / sets.len()) as u32 % (max_level(star) + 4);
+
+        let total = star - 1 + level / 4;
+        let n = total.min(4) as usize;
+        let mut rolls = vec![1; n];
+        rolls[i % n] += total as usize - n;
+        let mut subs = [CriticalDamage, Critical, AtkPercentage, Recharge]
+            .iter()
+            .zip(rolls.iter())
+            .map(|(name, &k)| {
+                Some(shown(
+                    name.clone(),
+                    max_roll(star, name).unwrap() * k as f64,
+                ))
+            })
+            .chain(std::iter::repeat(None));
+
+        let artifact = InternalArtifact {
+            set_name: sets[i / 2 % sets.len()].clone(),
+            slot,
+            star,
+            level,
+            // only 4 and 5 star main stats are checked
+            main_stat: shown(
+                main.clone(),
+                main_stat_value(star, level, &main).unwrap_or(100.0),
+            ),
+            sub_stat_1: subs.next().unwrap(),
+            sub_stat_2: subs.next().unwrap(),
+            sub_stat_3: subs.next().unwrap(),
+            sub_stat_4: subs.next().unwrap(),
+            equip: if i % 3 == 0 {
+                Some(String::from("迪卢克"))
+            } else {
+                None
+            },
+        };
+        assert!(validate(&artifact).is_valid(), "{:?}", artifact);
+        artifact
+    }
+
+    fn inventory(stars: &[(u32, usize)]) -> Vec<InternalArtifact> {
+        let mut i = 0;
+        let mut artifacts = Vec::new();
+        for &(star, count) in stars.iter() {
+            for _ in 0..count {
+                artifacts.push(artifact(i, star));
+                i += 1;
+            }
+        }
+        artifacts
+    }
+
+    fn scan(sim: &SimInventory, config: YasScannerConfig) -> Vec<InternalArtifact> {
+        let mut scanner = YasScanner::with_backends(
+            Arc::new(sim.reader()),
+            Box::new(sim.input()),
+            Box::new(sim.capture()),
+            sim.scan_info(),
+            config,
+            false,
+        );
+        scanner.start().unwrap()
+    }
+
+    fn config() -> YasScannerConfig {
+        YasScannerConfig {
+            scroll_stop: 0,
+            scroll_step: 1,
+            scroll_direction: default_scroll_direction(),
+            ..YasScannerConfig::default()
+        }
+    }
+
+    #[test]
+    fn scans_a_partial_last_row() {
+        // 8 columns, the third row has 3 artifacts and no scrolling is needed
+        let artifacts = inventory(&[(5, 19)]);
+        let sim = SimInventory::new(artifacts.clone(), &WINDOW_16_9, 1600, 900);
+
+        assert_eq!(scan(&sim, config()), artifacts);
+        assert_eq!(sim.captured_indices(), (0..19).collect::<Vec<_>>());
+        assert_eq!(sim.scrolled_rows(), 0.0);
+    }
+
+    #[test]
+    fn scans_pages_to_the_end_of_the_list() {
+        // 12 rows with 5 on screen: single rows are scrolled at first, then the average
+        // is used, and the last page only scrolls as far as the list goes
+        let artifacts = inventory(&[(5, 60), (4, 33)]);
+        let sim = SimInventory::new(artifacts.clone(), &WINDOW_16_9, 1600, 900);
+
+        assert_eq!(scan(&sim, config()), artifacts);
+        assert_eq!(sim.captured_indices(), (0..93).collect::<Vec<_>>());
+        assert_eq!(sim.scrolled_rows(), 7.0);
+    }
+
+    #[test]
+    fn scans_when_scrolling_overshoots_rows() {
+        // a row is 8.6 ticks, a tick scrolls 17 pixels and lands past most row starts
+        let artifacts = inventory(&[(5, 75)]);
+        let config = YasScannerConfig {
+            scroll_direction: -1,
+            ..config()
+        };
+        let sim =
+            SimInventory::new(artifacts.clone(), &WINDOW_16_9, 1600, 900).with_scroll(-1, 8.6);
+
+        assert_eq!(scan(&sim, config), artifacts);
+        assert_eq!(sim.captured_indices(), (0..75).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stops_at_the_first_artifact_below_min_star() {
+        let artifacts = inventory(&[(5, 10), (4, 7), (3, 6)]);
+        let sim = SimInventory::new(artifacts.clone(), &WINDOW_16_9, 1600, 900);
+
+        assert_eq!(scan(&sim, config()), artifacts[..17].to_vec());
+        // the 3 star panel is looked at, nothing after it
+        assert_eq!(sim.captured_indices(), (0..18).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn several_workers_keep_the_inventory_order() {
+        let artifacts = inventory(&[(5, 30), (4, 20)]);
+        let sim = SimInventory::new(artifacts.clone(), &WINDOW_16_9, 1600, 900);
+        let config = YasScannerConfig {
+            workers: 4,
+            queue_size: 2,
+            ..config()
+        };
+
+        assert_eq!(scan(&sim, config), artifacts);
+    }
+
+    // the network failing on one panel
+    struct FailingReader {
+        reader: SimReader,
+        seq: u32,
+    }
+
+    impl PanelReader for FailingReader {
+        fn read_count(&self, image: &RawImage) -> Result<String, YasError> {
+            self.reader.read_count(image)
+        }
+
+        fn read_panel(
+            &self,
+            buffers: &mut PanelBuffers,
+            capture: &RawCaptureImage,
+            star: u32,
+            seq: u32,
+        ) -> Result<YasScanResult, YasError> {
+            if seq == self.seq {
+                return Err(YasError::Recognition(String::from("inference failed")));
+            }
+            self.reader.read_panel(buffers, capture, star, seq)
+        }
+    }
+
+    #[test]
+    fn a_recognition_error_stops_the_scan() {
+        let sim = SimInventory::new(inventory(&[(5, 20)]), &WINDOW_16_9, 1600, 900);
+        let reader = FailingReader {
+            reader: sim.reader(),
+            seq: 10,
+        };
+        let mut scanner = YasScanner::with_backends(
+            Arc::new(reader),
+            Box::new(sim.input()),
+            Box::new(sim.capture()),
+            sim.scan_info(),
+            YasScannerConfig {
+                workers: 2,
+                ..config()
+            },
+            false,
+        );
+
+        match scanner.start() {
+            Err(YasError::Recognition(s)) => assert_eq!(s, "inference failed"),
+            other => panic!("{:?}", other.map(|v| v.len())),
+        }
+    }
+}
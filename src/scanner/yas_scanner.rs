@@ -1,26 +1,38 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::convert::From;
 use std::fs;
-use std::io::stdin;
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::SystemTime;
 
 use clap::ArgMatches;
-use enigo::*;
-use log::{debug, error, info, warn};
-use rand::Rng;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
 
 use crate::artifact::internal_artifact::{
-    ArtifactSetName, ArtifactSlot, ArtifactStat, InternalArtifact,
+    ArtifactSetName, ArtifactSlot, InternalArtifact, ARTIFACT_NAMES_CHS, STAT_NAMES_CHS,
 };
-use crate::capture;
+use crate::artifact::correction::{correct_stat, correct_title};
+use crate::artifact::merge::{key_set, match_key, KeyFields};
+use crate::artifact::validation::{is_main_stat_valid, is_sub_stat_valid, main_stat_value, validate};
+use crate::capture::{CaptureBackend, ScreenshotsCapture};
 use crate::common::character_name::CHARACTER_NAMES;
 use crate::common::color::Color;
+use crate::common::error::YasError;
 use crate::common::{utils, PixelRect, PixelRectBound, RawCaptureImage, RawImage};
-use crate::inference::inference::CRNNModel;
-use crate::inference::pre_process::pre_process;
+use crate::inference::ctc::{Constraint, Decoder, Lexicon};
+use crate::inference::grammar::Grammar;
+use crate::inference::inference::{CRNNModel, Recognition};
+use crate::inference::pre_process::{pre_process_with, PreProcessor};
 use crate::info::info::ScanInfo;
+use crate::input::{default_scroll_direction, default_scroll_step, EnigoInput, InputBackend};
+use crate::scanner::confidence::{
+    low_confidence_fields, save_report, FieldConfidence, LowConfidenceField,
+};
+use crate::scanner::journal::{load_journal, resume_position, JournalEntry, ScanJournal, ScanPosition};
+use crate::scanner::replay::CaptureRecorder;
 
 #[cfg(windows)]
 use crate::common::utils::{
@@ -29,20 +41,79 @@ use crate::common::utils::{
 };
 
 pub struct YasScannerConfig {
-    max_row: u32,
-    capture_only: bool,
-    min_star: u32,
-    min_level: u32,
-    max_wait_switch_artifact: u32,
-    scroll_stop: u32,
-    number: u32,
-    verbose: bool,
-    dump_mode: bool,
-    cloud_wait_switch_artifact: u32,
+    pub max_row: u32,
+    pub capture_only: bool,
+    pub min_star: u32,
+    pub min_level: u32,
+    pub max_wait_switch_artifact: u32,
+    pub scroll_stop: u32,
+    pub number: u32,
+    pub verbose: bool,
+    pub dump_mode: bool,
+    pub cloud_wait_switch_artifact: u32,
+    pub save_captures: Option<String>,
+    // every recognised panel is appended to the journal, `resume` continues after its last entry.
+    // A journal with entries is only started over with `overwrite_journal`
+    pub journal: Option<String>,
+    pub resume: bool,
+    pub overwrite_journal: bool,
+    // incremental scan: stop after this many consecutive artifacts already in `known_artifacts`,
+    // which only makes sense with the backpack sorted by 入手顺序
+    pub known_artifacts: Vec<InternalArtifact>,
+    pub stop_after_known: u32,
+    // recognised fields below this confidence are logged and go to `confidence_report`
+    pub min_confidence: f32,
+    pub confidence_report: Option<String>,
+    // greedy, or constrained to valid names (and with `Grammar` to well-formed values)
+    pub decoder: Decoder,
+    // onnx model and index_2_word.json to use instead of the embedded ones
+    pub model: Option<String>,
+    pub dict: Option<String>,
+    // recognition threads, and panels that may wait for them before the scan blocks
+    pub workers: u32,
+    pub queue_size: u32,
+    // e.g. "equip=sauvola,sub_stat=otsu", applied over the defaults of `ScanInfo`
+    pub binarize: Option<String>,
+    // sign of a wheel tick that scrolls the backpack down, and ticks per coarse scroll
+    pub scroll_direction: i32,
+    pub scroll_step: i32,
     // offset_x: i32,
     // offset_y: i32,
 }
 
+impl Default for YasScannerConfig {
+    fn default() -> YasScannerConfig {
+        YasScannerConfig {
+            max_row: 1000,
+            capture_only: false,
+            min_star: 4,
+            min_level: 0,
+            max_wait_switch_artifact: 800,
+            scroll_stop: 80,
+            number: 0,
+            verbose: false,
+            dump_mode: false,
+            cloud_wait_switch_artifact: 300,
+            save_captures: None,
+            journal: None,
+            resume: false,
+            overwrite_journal: false,
+            known_artifacts: Vec::new(),
+            stop_after_known: 5,
+            min_confidence: 0.9,
+            confidence_report: None,
+            decoder: Decoder::Greedy,
+            model: None,
+            dict: None,
+            workers: 2,
+            queue_size: 8,
+            binarize: None,
+            scroll_direction: default_scroll_direction(),
+            scroll_step: default_scroll_step(),
+        }
+    }
+}
+
 impl YasScannerConfig {
     pub fn from_match(matches: &ArgMatches) -> YasScannerConfig {
         YasScannerConfig {
@@ -84,15 +155,67 @@ impl YasScannerConfig {
                 .unwrap_or("300")
                 .parse::<u32>()
                 .unwrap(),
+            save_captures: matches.value_of("save-captures").map(String::from),
+            journal: matches.value_of("journal").map(String::from),
+            resume: matches.is_present("resume"),
+            overwrite_journal: matches.is_present("overwrite-journal"),
+            known_artifacts: Vec::new(),
+            stop_after_known: matches
+                .value_of("incremental-stop")
+                .unwrap_or("5")
+                .parse::<u32>()
+                .unwrap(),
+            min_confidence: matches
+                .value_of("min-confidence")
+                .unwrap_or("0.9")
+                .parse::<f32>()
+                .unwrap(),
+            confidence_report: matches.value_of("confidence-report").map(String::from),
+            decoder: matches
+                .value_of("decoder")
+                .and_then(Decoder::from_name)
+                .unwrap_or(Decoder::Greedy),
+            model: matches.value_of("model").map(String::from),
+            dict: matches.value_of("dict").map(String::from),
+            workers: matches
+                .value_of("workers")
+                .unwrap_or("2")
+                .parse::<u32>()
+                .unwrap(),
+            queue_size: matches
+                .value_of("queue-size")
+                .unwrap_or("8")
+                .parse::<u32>()
+                .unwrap(),
+            binarize: matches.value_of("binarize").map(String::from),
+            scroll_direction: if matches.is_present("invert-scroll") {
+                -default_scroll_direction()
+            } else {
+                default_scroll_direction()
+            },
+            scroll_step: match matches.value_of("scroll-step") {
+                Some(v) => v.parse::<i32>().unwrap(),
+                None => default_scroll_step(),
+            },
             // offset_x: matches.value_of("offset-x").unwrap_or("0").parse::<i32>().unwrap(),
             // offset_y: matches.value_of("offset-y").unwrap_or("0").parse::<i32>().unwrap(),
         }
     }
+
+    // `--binarize` over the per field defaults of the window layout
+    pub(crate) fn override_binarization(&self, info: &mut ScanInfo) {
+        if let Some(ref spec) = self.binarize {
+            if let Err(e) = info.binarization.apply(spec) {
+                warn!("--binarize ignored: {}", e);
+            }
+        }
+    }
 }
 
 pub struct YasScanner {
-    model: CRNNModel,
-    enigo: Enigo,
+    reader: Arc<dyn PanelReader>,
+    input: Box<dyn InputBackend>,
+    capture: Box<dyn CaptureBackend>,
 
     info: ScanInfo,
     config: YasScannerConfig,
@@ -114,7 +237,8 @@ pub struct YasScanner {
     is_cloud: bool,
 }
 
-enum ScrollResult {
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScrollResult {
     TLE,
     // time limit exceeded
     Interrupt,
@@ -122,24 +246,42 @@ enum ScrollResult {
     Skip,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct YasScanResult {
-    name: String,
-    main_stat_name: String,
-    main_stat_value: String,
-    sub_stat_1: String,
-    sub_stat_2: String,
-    sub_stat_3: String,
-    sub_stat_4: String,
-    level: String,
-    equip: String,
-    star: u32,
+    pub(crate) name: String,
+    pub(crate) main_stat_name: String,
+    pub(crate) main_stat_value: String,
+    pub(crate) sub_stat_1: String,
+    pub(crate) sub_stat_2: String,
+    pub(crate) sub_stat_3: String,
+    pub(crate) sub_stat_4: String,
+    pub(crate) level: String,
+    pub(crate) equip: String,
+    pub(crate) star: u32,
+    // per field, keyed by the names used in dumps (`title`, `sub_stat_1`, ...)
+    #[serde(default)]
+    pub confidence: BTreeMap<String, FieldConfidence>,
 }
 
 impl YasScanResult {
+    pub fn field_text(&self, field: &str) -> &str {
+        match field {
+            "title" => &self.name,
+            "main_stat_name" => &self.main_stat_name,
+            "main_stat_value" => &self.main_stat_value,
+            "sub_stat_1" => &self.sub_stat_1,
+            "sub_stat_2" => &self.sub_stat_2,
+            "sub_stat_3" => &self.sub_stat_3,
+            "sub_stat_4" => &self.sub_stat_4,
+            "level" => &self.level,
+            "equip" => &self.equip,
+            _ => "",
+        }
+    }
+
+    // numbers that do not fit the game's tables and titles that match no artifact
+    // are corrected where the tables leave one answer, see `artifact::correction`
     pub fn to_internal_artifact(&self) -> Option<InternalArtifact> {
-        let set_name = ArtifactSetName::from_zh_cn(&self.name)?;
-        let slot = ArtifactSlot::from_zh_cn(&self.name)?;
         let star = self.star;
         if !self.level.contains("+") {
             return None;
@@ -151,27 +293,35 @@ impl YasScanResult {
             .collect::<String>()
             .parse::<u32>()
             .ok()?;
-        let main_stat = ArtifactStat::from_zh_cn_raw(
+        let main_stat = correct_stat(
+            "main_stat",
             (self.main_stat_name.clone() + "+" + self.main_stat_value.as_str()).as_str(),
+            |s| {
+                main_stat_value(star, level, &s.name).is_none()
+                    || is_main_stat_valid(star, level, s)
+            },
         )?;
-        let sub1 = ArtifactStat::from_zh_cn_raw(&self.sub_stat_1);
-        let sub2 = ArtifactStat::from_zh_cn_raw(&self.sub_stat_2);
-        let sub3 = ArtifactStat::from_zh_cn_raw(&self.sub_stat_3);
-        let sub4 = ArtifactStat::from_zh_cn_raw(&self.sub_stat_4);
-
-        let equip = if self.equip.contains("已装备") {
-            let equip_name = self
-                .equip
-                .chars()
-                .take(self.equip.len() - 3)
-                .collect::<String>();
-            if CHARACTER_NAMES.contains(equip_name.as_str()) {
-                Some(equip_name)
-            } else {
-                None
+        let sub_stat = |field: &str, text: &str| {
+            correct_stat(field, text, |s| is_sub_stat_valid(star, level, s))
+        };
+        let sub1 = sub_stat("sub_stat_1", &self.sub_stat_1);
+        let sub2 = sub_stat("sub_stat_2", &self.sub_stat_2);
+        let sub3 = sub_stat("sub_stat_3", &self.sub_stat_3);
+        let sub4 = sub_stat("sub_stat_4", &self.sub_stat_4);
+
+        let (set_name, slot) = match (
+            ArtifactSetName::from_zh_cn(&self.name),
+            ArtifactSlot::from_zh_cn(&self.name),
+        ) {
+            (Some(set_name), Some(slot)) => (set_name, slot),
+            _ => correct_title(&self.name, &main_stat.name)?,
+        };
+
+        let equip = match self.equip.strip_suffix("已装备") {
+            Some(equip_name) if CHARACTER_NAMES.contains(equip_name) => {
+                Some(String::from(equip_name))
             }
-        } else {
-            None
+            _ => None,
         };
 
         let art = InternalArtifact {
@@ -190,7 +340,7 @@ impl YasScanResult {
     }
 }
 
-fn calc_pool(row: &Vec<u8>) -> f64 {
+fn calc_pool(row: &[u8]) -> f64 {
     let len = row.len() / 4;
     let mut pool: f64 = 0.0;
 
@@ -201,17 +351,556 @@ fn calc_pool(row: &Vec<u8>) -> f64 {
     pool
 }
 
+pub(crate) fn star_from_color(color: &Color) -> u32 {
+    let color_1 = Color::from(113, 119, 139);
+    let color_2 = Color::from(42, 143, 114);
+    let color_3 = Color::from(81, 127, 203);
+    let color_4 = Color::from(161, 86, 224);
+    let color_5 = Color::from(188, 105, 50);
+
+    let min_dis: u32 = color_1.dis_2(color);
+    let mut star = 1_u32;
+    if color_2.dis_2(color) < min_dis {
+        star = 2;
+    }
+    if color_3.dis_2(color) < min_dis {
+        star = 3;
+    }
+    if color_4.dis_2(color) < min_dis {
+        star = 4;
+    }
+    if color_5.dis_2(color) < min_dis {
+        star = 5;
+    }
+
+    star
+}
+
+pub(crate) type RecognizerSender = mpsc::SyncSender<(RawCaptureImage, u32, ScanPosition)>;
+pub(crate) type RecognizerHandle = thread::JoinHandle<Result<Vec<InternalArtifact>, YasError>>;
+
+struct FieldLexicons {
+    title: Lexicon,
+    stat_name: Lexicon,
+    equip: Lexicon,
+    // value grammars, only with `Decoder::Grammar`
+    stat: Option<Grammar>,
+    value: Option<Grammar>,
+}
+
+impl FieldLexicons {
+    fn new(model: &CRNNModel, decoder: Decoder) -> FieldLexicons {
+        let dict = model.dictionary();
+        let (stat, value) = if decoder == Decoder::Grammar {
+            let stat = Grammar::words(STAT_NAMES_CHS, dict)
+                .then(Grammar::literal("+", dict))
+                .then(Grammar::number(dict));
+            (Some(stat), Some(Grammar::number(dict)))
+        } else {
+            (None, None)
+        };
+
+        FieldLexicons {
+            title: model.lexicon(ARTIFACT_NAMES_CHS),
+            stat_name: model.lexicon(STAT_NAMES_CHS),
+            equip: model.lexicon(CHARACTER_NAMES.iter().map(|name| format!("{}已装备", name))),
+            stat,
+            value,
+        }
+    }
+
+    fn stat(&self) -> Constraint<'_> {
+        match self.stat {
+            Some(ref g) => Constraint::Grammar(g),
+            None => Constraint::StatName(&self.stat_name),
+        }
+    }
+
+    fn value(&self) -> Constraint<'_> {
+        match self.value {
+            Some(ref g) => Constraint::Grammar(g),
+            None => Constraint::Free,
+        }
+    }
+}
+
+// buffers of one worker, reused from panel to panel
+#[derive(Default)]
+pub(crate) struct PanelBuffers {
+    pre_processor: PreProcessor,
+    // the field being preprocessed
+    field: RawImage,
+    // model inputs, in the order of the fields
+    images: [RawImage; 9],
+}
+
+// turns captures into text. `PanelRecognizer` runs the OCR model, scanners built with
+// `YasScanner::with_backends` may read panels some other way, e.g. in tests
+pub(crate) trait PanelReader: Send + Sync {
+    // the artifact count above the grid, e.g. "圣遗物 1234/1500"
+    fn read_count(&self, image: &RawImage) -> Result<String, YasError>;
+
+    // `seq` only names the dump files
+    fn read_panel(
+        &self,
+        buffers: &mut PanelBuffers,
+        capture: &RawCaptureImage,
+        star: u32,
+        seq: u32,
+    ) -> Result<YasScanResult, YasError>;
+}
+
+// what every worker needs to read a panel
+pub(crate) struct PanelRecognizer {
+    model: Arc<CRNNModel>,
+    info: ScanInfo,
+    lexicons: Option<FieldLexicons>,
+    dump_mode: bool,
+}
+
+impl PanelRecognizer {
+    pub(crate) fn new(
+        model: Arc<CRNNModel>,
+        info: ScanInfo,
+        config: &YasScannerConfig,
+    ) -> PanelRecognizer {
+        let lexicons = if config.decoder == Decoder::Greedy {
+            None
+        } else {
+            Some(FieldLexicons::new(&model, config.decoder))
+        };
+        PanelRecognizer {
+            model,
+            info,
+            lexicons,
+            dump_mode: config.dump_mode,
+        }
+    }
+
+    fn convert_rect(&self, rect: &PixelRectBound) -> PixelRect {
+        PixelRect {
+            left: rect.left - self.info.panel_position.left,
+            top: rect.top - self.info.panel_position.top,
+            width: rect.right - rect.left,
+            height: rect.bottom - rect.top,
+        }
+    }
+
+    // `cnt` only names the dump files
+    fn recognize(
+        &self,
+        buffers: &mut PanelBuffers,
+        capture: &RawCaptureImage,
+        star: u32,
+        cnt: u32,
+    ) -> Result<YasScanResult, YasError> {
+        let word = |pick: fn(&FieldLexicons) -> &Lexicon| match self.lexicons {
+            Some(ref l) => Constraint::Word(pick(l)),
+            None => Constraint::Free,
+        };
+        let stat = || match self.lexicons {
+            Some(ref l) => l.stat(),
+            None => Constraint::Free,
+        };
+        let value = match self.lexicons {
+            Some(ref l) => l.value(),
+            None => Constraint::Free,
+        };
+
+        // names are the ones used in dumps, the order is the one of `YasScanResult`
+        let fields: [(&str, &PixelRectBound, Constraint); 9] = [
+            ("title", &self.info.title_position, word(|l| &l.title)),
+            (
+                "main_stat_name",
+                &self.info.main_stat_name_position,
+                word(|l| &l.stat_name),
+            ),
+            (
+                "main_stat_value",
+                &self.info.main_stat_value_position,
+                value,
+            ),
+            ("sub_stat_1", &self.info.sub_stat1_position, stat()),
+            ("sub_stat_2", &self.info.sub_stat2_position, stat()),
+            ("sub_stat_3", &self.info.sub_stat3_position, stat()),
+            ("sub_stat_4", &self.info.sub_stat4_position, stat()),
+            ("level", &self.info.level_position, Constraint::Free),
+            ("equip", &self.info.equip_position, word(|l| &l.equip)),
+        ];
+
+        // crop and preprocess every field, `present` is false when there is nothing to read
+        let mut present = [false; 9];
+        for (k, (name, pos, _)) in fields.iter().enumerate() {
+            capture.crop_to_raw_img_into(&self.convert_rect(pos), &mut buffers.field);
+            if self.dump_mode {
+                let path = format!("dumps/{}_{}.png", name, cnt);
+                if let Err(e) = buffers.field.grayscale_to_gray_image().save(&path) {
+                    warn!("cannot save {}: {}", path, e);
+                }
+            }
+
+            let binarization = self.info.binarization.get(name);
+            let image = &mut buffers.images[k];
+            present[k] = buffers
+                .pre_processor
+                .process_into(&buffers.field, binarization, image);
+            if present[k] && self.dump_mode {
+                let path = format!("dumps/p_{}_{}.png", name, cnt);
+                if let Err(e) = image.to_gray_image().save(&path) {
+                    warn!("cannot save {}: {}", path, e);
+                }
+            }
+        }
+
+        let batch: Vec<(&RawImage, Constraint)> = buffers
+            .images
+            .iter()
+            .zip(fields.iter())
+            .zip(present.iter())
+            .filter(|(_, &p)| p)
+            .map(|((img, field), _)| (img, field.2))
+            .collect();
+        let mut recognized = self.model.inference_batch(&batch)?.into_iter();
+        let recognitions: Vec<Recognition> = present
+            .iter()
+            .map(|&p| {
+                if p {
+                    recognized.next().unwrap()
+                } else {
+                    Recognition::empty()
+                }
+            })
+            .collect();
+
+        if self.dump_mode {
+            for ((name, _, _), r) in fields.iter().zip(recognitions.iter()) {
+                let path = format!("dumps/{}_{}.txt", name, cnt);
+                if let Err(e) = fs::write(&path, &r.text) {
+                    warn!("cannot save {}: {}", path, e);
+                }
+            }
+        }
+
+        let confidence = fields
+            .iter()
+            .zip(recognitions.iter())
+            .map(|((name, _, _), r)| (name.to_string(), FieldConfidence::from(r)))
+            .collect();
+
+        let mut text = recognitions.into_iter().map(|r| r.text);
+        let mut next_text = || text.next().unwrap();
+        Ok(YasScanResult {
+            name: next_text(),
+            main_stat_name: next_text(),
+            main_stat_value: next_text(),
+            sub_stat_1: next_text(),
+            sub_stat_2: next_text(),
+            sub_stat_3: next_text(),
+            sub_stat_4: next_text(),
+            level: next_text(),
+            equip: next_text(),
+            star,
+            confidence,
+        })
+    }
+}
+
+impl PanelReader for PanelRecognizer {
+    fn read_count(&self, image: &RawImage) -> Result<String, YasError> {
+        self.model.inference_string(image)
+    }
+
+    fn read_panel(
+        &self,
+        buffers: &mut PanelBuffers,
+        capture: &RawCaptureImage,
+        star: u32,
+        seq: u32,
+    ) -> Result<YasScanResult, YasError> {
+        self.recognize(buffers, capture, star, seq)
+    }
+}
+
+// (panel number, position, result)
+type WorkerMessage = (u32, ScanPosition, Result<YasScanResult, YasError>);
+
+// panels are numbered in the order they are taken from the queue, which is the
+// order they were sent in. `taken` counts them, so the collector can tell when a
+// worker died before answering for one
+fn spawn_worker(
+    reader: Arc<dyn PanelReader>,
+    queue: Arc<Mutex<Receiver<(RawCaptureImage, u32, ScanPosition)>>>,
+    taken: Arc<AtomicU32>,
+    results: mpsc::Sender<WorkerMessage>,
+) {
+    thread::spawn(move || {
+        let mut buffers = PanelBuffers::default();
+        loop {
+            let (seq, capture, star, position) = {
+                let queue = match queue.lock() {
+                    Ok(v) => v,
+                    Err(_) => return,
+                };
+                match queue.recv() {
+                    Ok((capture, star, position)) => (
+                        taken.fetch_add(1, Ordering::SeqCst),
+                        capture,
+                        star,
+                        position,
+                    ),
+                    // the sender is dropped after the last panel
+                    Err(_) => return,
+                }
+            };
+
+            let result = reader.read_panel(&mut buffers, &capture, star, seq);
+            // the collector has stopped early
+            if results.send((seq, position, result)).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+// the recognition threads, fed with (panel capture, star, position) until the sender is
+// dropped. `config.workers` threads recognise panels from a queue of `config.queue_size`,
+// so `send` blocks when recognition falls behind. The returned thread puts the results
+// back in order, stops early on consecutive duplicates (after which `send` fails) and
+// returns the artifacts. `previous` are results of an interrupted scan, they are
+// deduplicated along with the new ones
+pub(crate) fn spawn_recognizer(
+    reader: Arc<dyn PanelReader>,
+    info: ScanInfo,
+    config: &YasScannerConfig,
+    mut journal: Option<ScanJournal>,
+    previous: Vec<YasScanResult>,
+) -> Result<(RecognizerSender, RecognizerHandle), YasError> {
+    let (tx, rx) =
+        mpsc::sync_channel::<(RawCaptureImage, u32, ScanPosition)>(config.queue_size as usize);
+    let is_verbose = config.verbose;
+    let min_level = config.min_level;
+    let known_fields = KeyFields::of(&config.known_artifacts);
+    let known = key_set(&config.known_artifacts, known_fields);
+    let stop_after_known = config.stop_after_known;
+    let min_confidence = config.min_confidence;
+    let confidence_report = config.confidence_report.clone();
+    if config.dump_mode {
+        fs::create_dir_all("dumps")?;
+    }
+
+    let queue = Arc::new(Mutex::new(rx));
+    let taken = Arc::new(AtomicU32::new(0));
+    let (result_tx, result_rx) = mpsc::channel::<WorkerMessage>();
+    for _ in 0..config.workers.max(1) {
+        spawn_worker(
+            reader.clone(),
+            queue.clone(),
+            taken.clone(),
+            result_tx.clone(),
+        );
+    }
+    drop(result_tx);
+
+    let handle = thread::spawn(move || {
+        let mut results: Vec<InternalArtifact> = Vec::new();
+        let mut error_count = 0;
+        let mut dup_count = 0;
+        let mut invalid_count = 0;
+        let mut hash = HashSet::new();
+        let mut consecutive_dup_count = 0;
+        let mut consecutive_known_count = 0;
+        let mut report: Vec<LowConfidenceField> = Vec::new();
+
+        for result in previous.iter() {
+            match result.to_internal_artifact() {
+                Some(a) => {
+                    if hash.contains(&a) {
+                        dup_count += 1;
+                    } else {
+                        hash.insert(a.clone());
+                        results.push(a);
+                    }
+                }
+                None => error_count += 1,
+            }
+        }
+
+        // results arrive in any order, `pending` holds them until their turn. The
+        // channel closes once every worker has seen the sender dropped
+        let mut pending: BTreeMap<u32, (ScanPosition, Result<YasScanResult, YasError>)> =
+            BTreeMap::new();
+        let mut next = 0;
+        let mut stopped = false;
+        'collect: for (seq, position, result) in result_rx {
+            pending.insert(seq, (position, result));
+
+            while let Some((position, result)) = pending.remove(&next) {
+                next += 1;
+                let result = result?;
+
+                if is_verbose {
+                    info!("{:?}", result);
+                }
+                let low = low_confidence_fields(position, &result, min_confidence);
+                for field in low.iter() {
+                    warn!(
+                        "low confidence {:.3} at row {} col {}: {} = `{}`",
+                        field.confidence,
+                        position.row + 1,
+                        position.col + 1,
+                        field.field,
+                        field.text
+                    );
+                }
+                report.extend(low);
+                if let Some(ref mut j) = journal {
+                    let entry = JournalEntry {
+                        position,
+                        result: result.clone(),
+                    };
+                    if let Err(e) = j.write(&entry) {
+                        warn!("cannot write journal: {}", e);
+                    }
+                }
+                // println!("{:?}", result);
+                let art = result.to_internal_artifact();
+                if let Some(a) = art {
+                    // almost always a misread number, kept but worth checking by hand
+                    let validation = validate(&a);
+                    if !validation.is_valid() {
+                        invalid_count += 1;
+                        for problem in validation.problems.iter() {
+                            warn!(
+                                "invalid artifact at row {} col {}: {}",
+                                position.row + 1,
+                                position.col + 1,
+                                problem
+                            );
+                        }
+                    } else if is_verbose {
+                        let rolls = validation
+                            .rolls
+                            .iter()
+                            .map(|r| format!("{:?} {}-{}", r.name, r.min, r.max))
+                            .collect::<Vec<_>>();
+                        info!("rolls: {}", rolls.join(", "));
+                    }
+                    if known.contains(&match_key(&a, known_fields)) {
+                        consecutive_known_count += 1;
+                    } else {
+                        consecutive_known_count = 0;
+                    }
+                    if hash.contains(&a) {
+                        dup_count += 1;
+                        consecutive_dup_count += 1;
+                        warn!("dup artifact detected: {:?}", result);
+                    } else {
+                        consecutive_dup_count = 0;
+                        hash.insert(a.clone());
+                        results.push(a);
+                    }
+                } else {
+                    error!("wrong detection: {:?}", result);
+                    error_count += 1;
+                    // println!("error parsing results");
+                }
+                if consecutive_dup_count >= info.art_row {
+                    error!("检测到连续多个重复圣遗物，可能为翻页错误，或者为非背包顶部开始扫描");
+                    stopped = true;
+                    break 'collect;
+                }
+                if !known.is_empty()
+                    && stop_after_known > 0
+                    && consecutive_known_count >= stop_after_known
+                {
+                    info!(
+                        "连续{}个圣遗物已在之前的导出中，停止扫描",
+                        consecutive_known_count
+                    );
+                    stopped = true;
+                    break 'collect;
+                }
+            }
+        }
+
+        // a worker thread died without answering for a panel
+        if !stopped && next < taken.load(Ordering::SeqCst) {
+            return Err(YasError::Recognition(format!(
+                "第{}个圣遗物没有识别结果",
+                next + 1
+            )));
+        }
+
+        info!("error count: {}", error_count);
+        info!("dup count: {}", dup_count);
+        info!("invalid count: {}", invalid_count);
+        if let Some(ref path) = confidence_report {
+            info!("{}个低置信度字段，已写入{}", report.len(), path);
+            if let Err(e) = save_report(path, &report) {
+                warn!("cannot write {}: {}", path, e);
+            }
+        }
+
+        if min_level > 0 {
+            Ok(results
+                .into_iter()
+                .filter(|result| result.level >= min_level)
+                .collect::<Vec<_>>())
+        } else {
+            Ok(results)
+        }
+    });
+
+    Ok((tx, handle))
+}
+
+fn save_capture(im: &RawImage, name: &str) {
+    let path = format!("captures/{}.png", name);
+    if let Err(e) = im.to_gray_image().save(&path) {
+        warn!("cannot save {}: {}", path, e);
+    }
+}
+
 impl YasScanner {
-    pub fn new(info: ScanInfo, config: YasScannerConfig, is_cloud: bool) -> YasScanner {
+    pub fn new(
+        mut info: ScanInfo,
+        config: YasScannerConfig,
+        is_cloud: bool,
+    ) -> Result<YasScanner, YasError> {
+        config.override_binarization(&mut info);
+        let model = Arc::new(CRNNModel::new(
+            config.model.as_deref(),
+            config.dict.as_deref(),
+        )?);
+        let reader = Arc::new(PanelRecognizer::new(model, info.clone(), &config));
+
+        Ok(YasScanner::with_backends(
+            reader,
+            Box::new(EnigoInput::new()),
+            Box::new(ScreenshotsCapture),
+            info,
+            config,
+            is_cloud,
+        ))
+    }
+
+    // a scanner that reads panels with `reader` and does not touch the screen or
+    // the mouse unless the backends do
+    pub(crate) fn with_backends(
+        reader: Arc<dyn PanelReader>,
+        input: Box<dyn InputBackend>,
+        capture: Box<dyn CaptureBackend>,
+        info: ScanInfo,
+        config: YasScannerConfig,
+        is_cloud: bool,
+    ) -> YasScanner {
         let row = info.art_row;
         let col = info.art_col;
 
         YasScanner {
-            model: CRNNModel::new(
-                String::from("model_training.onnx"),
-                String::from("index_2_word.json"),
-            ),
-            enigo: Enigo::new(),
+            reader,
+            input,
+            capture,
             info,
             config,
 
@@ -232,99 +921,115 @@ impl YasScanner {
 }
 
 impl YasScanner {
+    // replace the default enigo mouse control, e.g. with xdotool or a recording mock
+    pub fn with_input(mut self, input: Box<dyn InputBackend>) -> YasScanner {
+        self.input = input;
+        self
+    }
+
+    // replace the default screen capture, e.g. with a saved screenshot or a fixture
+    pub fn with_capture(mut self, capture: Box<dyn CaptureBackend>) -> YasScanner {
+        self.capture = capture;
+        self
+    }
+
     pub fn move_to(&mut self, row: u32, col: u32) {
         let info = &self.info;
-        let left = info.left
-            + (info.left_margin + (info.art_width + info.art_gap_x) * col + info.art_width / 2)
-                as i32;
-        let top = info.top
-            + (info.top_margin + (info.art_height + info.art_gap_y) * row + info.art_height / 4)
-                as i32;
-        self.enigo.mouse_move_to(left as i32, top as i32);
+        let (x, y) = info.art_position(row, col);
+        self.input.mouse_move_to(info.left + x, info.top + y);
     }
 
-    pub fn panel_down(&mut self) {
+    pub fn panel_down(&mut self) -> Result<(), YasError> {
         let info = &self.info;
         let max_scroll = 20;
         let mut count = 0;
-        self.enigo.mouse_move_to(
+        self.input.mouse_move_to(
             info.left + info.star_x as i32,
             info.top + info.star_y as i32,
         );
         let level_color = Color::from(57, 67, 79);
-        let mut color = capture::get_color(
-            info.level_position.left as u32,
-            info.level_position.bottom as u32,
-        );
+        let mut color = self
+            .capture
+            .get_color(
+                info.level_position.left as u32,
+                info.level_position.bottom as u32,
+            )
+            .map_err(YasError::Capture)?;
         while !level_color.is_same(&color) && count < max_scroll {
-            #[cfg(windows)]
-            self.enigo.mouse_scroll_y(5);
-            #[cfg(target_os = "linux")]
-            self.enigo.mouse_scroll_y(-1);
+            self.input
+                .mouse_scroll(-self.config.scroll_direction * self.config.scroll_step);
 
             utils::sleep(self.config.scroll_stop);
-            color = capture::get_color(
-                info.level_position.left as u32,
-                info.level_position.bottom as u32,
-            );
+            color = self
+                .capture
+                .get_color(
+                    info.level_position.left as u32,
+                    info.level_position.bottom as u32,
+                )
+                .map_err(YasError::Capture)?;
             count += 1;
         }
+
+        Ok(())
     }
 
-    fn sample_initial_color(&mut self) {
-        self.initial_color = self.get_flag_color();
+    pub fn sample_initial_color(&mut self) -> Result<(), YasError> {
+        self.initial_color = self.get_flag_color()?;
+        Ok(())
     }
 
-    fn get_flag_color(&self) -> Color {
+    fn get_flag_color(&self) -> Result<Color, YasError> {
         let flag_x = self.info.flag_x as i32 + self.info.left;
         let flag_y = self.info.flag_y as i32 + self.info.top;
-        let color = capture::get_color(flag_x as u32, flag_y as u32);
-
-        color
+        self.capture
+            .get_color(flag_x as u32, flag_y as u32)
+            .map_err(YasError::Capture)
     }
 
-    fn get_art_count(&mut self) -> Result<u32, String> {
+    fn get_art_count(&mut self) -> Result<u32, YasError> {
         let count = self.config.number;
         if let 0 = count {
             let info = &self.info;
-            let raw_after_pp = self.info.art_count_position.capture_relative(info).unwrap();
+            let raw_after_pp = self
+                .info
+                .art_count_position
+                .capture_relative(info, self.capture.as_ref())
+                .map_err(YasError::Capture)?;
             // raw_after_pp.to_gray_image().save("count.png");
-            let s = self.model.inference_string(&raw_after_pp);
+            let s = self.reader.read_count(&raw_after_pp)?;
             info!("raw count string: {}", s);
             if s.starts_with("圣遗物") {
                 let chars = s.chars().collect::<Vec<char>>();
-                let count_str = (&chars[4..chars.len() - 5]).iter().collect::<String>();
+                let count_str = chars[4..chars.len() - 5].iter().collect::<String>();
                 let count = match count_str.parse::<u32>() {
                     Ok(v) => v,
                     Err(_) => {
-                        return Err(String::from("无法识别圣遗物数量"));
+                        return Err(YasError::Recognition(String::from("无法识别圣遗物数量")));
                     }
                 };
                 return Ok(count);
             }
-            Err(String::from("无法识别圣遗物数量"))
+            Err(YasError::Recognition(String::from("无法识别圣遗物数量")))
         } else {
-            return Ok(count);
+            Ok(count)
         }
     }
 
-    fn scroll_one_row(&mut self) -> ScrollResult {
+    fn scroll_one_row(&mut self) -> Result<ScrollResult, YasError> {
         let mut state = 0;
         let mut count = 0;
         let max_scroll = 20;
         while count < max_scroll {
             if utils::is_rmb_down() {
-                return ScrollResult::Interrupt;
+                return Ok(ScrollResult::Interrupt);
             }
 
-            #[cfg(windows)]
-            self.enigo.mouse_scroll_y(-5);
-            #[cfg(target_os = "linux")]
-            self.enigo.mouse_scroll_y(1);
+            self.input
+                .mouse_scroll(self.config.scroll_direction * self.config.scroll_step);
 
             utils::sleep(self.config.scroll_stop);
             count += 1;
-            let color: Color = self.get_flag_color();
+            let color: Color = self.get_flag_color()?;
             // println!("{:?}", color);
             if state == 0 && !color.is_same(&self.initial_color) {
                 state = 1;
@@ -334,62 +1039,56 @@ impl YasScanner {
                     / (self.scrolled_rows as f64 + 1.0);
                 info!("avg scroll/row: {}", self.avg_scroll_one_row);
                 self.scrolled_rows += 1;
-                return ScrollResult::Success;
+                return Ok(ScrollResult::Success);
             }
         }
 
-        ScrollResult::TLE
+        Ok(ScrollResult::TLE)
     }
 
-    fn scroll_rows(&mut self, count: u32) -> ScrollResult {
+    pub fn scroll_rows(&mut self, count: u32) -> Result<ScrollResult, YasError> {
         if self.scrolled_rows >= 5 {
-            let scroll = ((self.avg_scroll_one_row * count as f64 - 3.0).round() as u32).max(0);
+            let scroll = (self.avg_scroll_one_row * count as f64 - 3.0).round() as u32;
             for _ in 0..scroll {
-                #[cfg(windows)]
-                self.enigo.mouse_scroll_y(-1);
-                #[cfg(target_os = "linux")]
-                self.enigo.mouse_scroll_y(1);
+                self.input.mouse_scroll(self.config.scroll_direction);
             }
             utils::sleep(400);
-            self.align_row();
-            return ScrollResult::Skip;
+            self.align_row()?;
+            return Ok(ScrollResult::Skip);
         }
 
         for _ in 0..count {
-            match self.scroll_one_row() {
-                ScrollResult::TLE => return ScrollResult::TLE,
-                ScrollResult::Interrupt => return ScrollResult::Interrupt,
+            match self.scroll_one_row()? {
+                ScrollResult::TLE => return Ok(ScrollResult::TLE),
+                ScrollResult::Interrupt => return Ok(ScrollResult::Interrupt),
                 _ => (),
             }
         }
 
-        ScrollResult::Success
+        Ok(ScrollResult::Success)
     }
 
-    fn align_row(&mut self) -> bool {
+    pub fn align_row(&mut self) -> Result<bool, YasError> {
         let mut count = 0;
         while count < 10 {
-            let color = self.get_flag_color();
+            let color = self.get_flag_color()?;
             if color.is_same(&self.initial_color) {
-                return true;
+                return Ok(true);
             }
 
-            #[cfg(windows)]
-            self.enigo.mouse_scroll_y(-1);
-            #[cfg(target_os = "linux")]
-            self.enigo.mouse_scroll_y(1);
+            self.input.mouse_scroll(self.config.scroll_direction);
 
             utils::sleep(self.config.scroll_stop);
             count += 1;
         }
 
-        false
+        Ok(false)
     }
 
-    fn wait_until_switched(&mut self) -> bool {
+    pub fn wait_until_switched(&mut self) -> Result<bool, YasError> {
         if self.is_cloud {
             utils::sleep(self.config.cloud_wait_switch_artifact);
-            return true;
+            return Ok(true);
         }
         let now = SystemTime::now();
 
@@ -398,12 +1097,12 @@ impl YasScanner {
         while now.elapsed().unwrap().as_millis() < self.config.max_wait_switch_artifact as u128 {
             // let pool_start = SystemTime::now();
             let rect = PixelRect {
-                left: self.info.left as i32 + self.info.pool_position.left,
-                top: self.info.top as i32 + self.info.pool_position.top,
+                left: self.info.left + self.info.pool_position.left,
+                top: self.info.top + self.info.pool_position.top,
                 width: self.info.pool_position.right - self.info.pool_position.left,
                 height: self.info.pool_position.bottom - self.info.pool_position.top,
             };
-            let im = capture::capture_absolute(&rect).unwrap();
+            let im = self.capture.capture(&rect).map_err(YasError::Capture)?;
             let pool = calc_pool(&im);
             // info!("pool: {}", pool);
             // println!("pool time: {}ms", pool_start.elapsed().unwrap().as_millis());
@@ -431,26 +1130,26 @@ impl YasScanner {
                             + now.elapsed().unwrap().as_millis() as f64)
                             / (self.scanned_count as f64 + 1.0);
                         self.scanned_count += 1;
-                        return true;
+                        return Ok(true);
                     }
                 }
             }
         }
 
-        false
+        Ok(false)
     }
 
-    fn capture_panel(&mut self) -> Result<RawCaptureImage, String> {
+    fn capture_panel(&mut self) -> Result<RawCaptureImage, YasError> {
         // let now = SystemTime::now();
         let w = self.info.panel_position.right - self.info.panel_position.left;
         let h = self.info.panel_position.bottom - self.info.panel_position.top;
         let rect: PixelRect = PixelRect {
-            left: self.info.left as i32 + self.info.panel_position.left,
-            top: self.info.top as i32 + self.info.panel_position.top,
+            left: self.info.left + self.info.panel_position.left,
+            top: self.info.top + self.info.panel_position.top,
             width: w,
             height: h,
         };
-        let u8_arr = capture::capture_absolute(&rect)?;
+        let u8_arr = self.capture.capture(&rect).map_err(YasError::Capture)?;
         // info!("capture time: {}ms", now.elapsed().unwrap().as_millis());
         Ok(RawCaptureImage {
             data: u8_arr,
@@ -459,42 +1158,25 @@ impl YasScanner {
         })
     }
 
-    fn get_star(&self) -> u32 {
-        let color = capture::get_color(
-            (self.info.star_x as i32 + self.info.left) as u32,
-            (self.info.star_y as i32 + self.info.top) as u32,
-        );
-
-        let color_1 = Color::from(113, 119, 139);
-        let color_2 = Color::from(42, 143, 114);
-        let color_3 = Color::from(81, 127, 203);
-        let color_4 = Color::from(161, 86, 224);
-        let color_5 = Color::from(188, 105, 50);
-
-        let min_dis: u32 = color_1.dis_2(&color);
-        let mut star = 1_u32;
-        if color_2.dis_2(&color) < min_dis {
-            star = 2;
-        }
-        if color_3.dis_2(&color) < min_dis {
-            star = 3;
-        }
-        if color_4.dis_2(&color) < min_dis {
-            star = 4;
-        }
-        if color_5.dis_2(&color) < min_dis {
-            star = 5;
-        }
-
-        star
+    fn get_star_color(&self) -> Result<Color, YasError> {
+        self.capture
+            .get_color(
+                (self.info.star_x as i32 + self.info.left) as u32,
+                (self.info.star_y as i32 + self.info.top) as u32,
+            )
+            .map_err(YasError::Capture)
     }
 
-    fn start_capture_only(&mut self) {
-        fs::create_dir("captures").unwrap();
+    fn start_capture_only(&mut self) -> Result<(), YasError> {
+        fs::create_dir_all("captures")?;
         let info = &self.info.clone();
 
-        let count = self.info.art_count_position.capture_relative(info).unwrap();
-        count.to_gray_image().save("captures/count.png");
+        let count = self
+            .info
+            .art_count_position
+            .capture_relative(info, self.capture.as_ref())
+            .map_err(YasError::Capture)?;
+        save_capture(&count, "count");
 
         let convert_rect = |rect: &PixelRectBound| PixelRect {
             left: rect.left - info.panel_position.left,
@@ -503,84 +1185,94 @@ impl YasScanner {
             height: rect.bottom - rect.top,
         };
 
-        let panel = self.capture_panel().unwrap();
-        let im_title = pre_process(panel.crop_to_raw_img(&convert_rect(&info.title_position)));
+        let panel = self.capture_panel()?;
+        let im_title = pre_process_with(
+            panel.crop_to_raw_img(&convert_rect(&info.title_position)),
+            info.binarization.title,
+        );
         if let Some(im) = im_title {
-            im.to_gray_image().save("captures/title.png").expect("Err");
+            save_capture(&im, "title");
         }
 
-        let im_main_stat_name =
-            pre_process(panel.crop_to_raw_img(&convert_rect(&info.main_stat_name_position)));
+        let im_main_stat_name = pre_process_with(
+            panel.crop_to_raw_img(&convert_rect(&info.main_stat_name_position)),
+            info.binarization.main_stat_name,
+        );
         if let Some(im) = im_main_stat_name {
-            im.to_gray_image()
-                .save("captures/main_stat_name.png")
-                .expect("Err");
+            save_capture(&im, "main_stat_name");
         }
 
-        let im_main_stat_value =
-            pre_process(panel.crop_to_raw_img(&convert_rect(&info.main_stat_value_position)));
+        let im_main_stat_value = pre_process_with(
+            panel.crop_to_raw_img(&convert_rect(&info.main_stat_value_position)),
+            info.binarization.main_stat_value,
+        );
         if let Some(im) = im_main_stat_value {
-            im.to_gray_image()
-                .save("captures/main_stat_value.png")
-                .expect("Err");
+            save_capture(&im, "main_stat_value");
         }
 
-        let im_sub_stat_1 =
-            pre_process(panel.crop_to_raw_img(&convert_rect(&info.sub_stat1_position)));
+        let im_sub_stat_1 = pre_process_with(
+            panel.crop_to_raw_img(&convert_rect(&info.sub_stat1_position)),
+            info.binarization.sub_stat,
+        );
         if let Some(im) = im_sub_stat_1 {
-            im.to_gray_image()
-                .save("captures/sub_stat_1.png")
-                .expect("Err");
+            save_capture(&im, "sub_stat_1");
         }
 
-        let im_sub_stat_2 =
-            pre_process(panel.crop_to_raw_img(&convert_rect(&info.sub_stat2_position)));
+        let im_sub_stat_2 = pre_process_with(
+            panel.crop_to_raw_img(&convert_rect(&info.sub_stat2_position)),
+            info.binarization.sub_stat,
+        );
         if let Some(im) = im_sub_stat_2 {
-            im.to_gray_image()
-                .save("captures/sub_stat_2.png")
-                .expect("Err");
+            save_capture(&im, "sub_stat_2");
         }
 
-        let im_sub_stat_3 =
-            pre_process(panel.crop_to_raw_img(&convert_rect(&info.sub_stat3_position)));
+        let im_sub_stat_3 = pre_process_with(
+            panel.crop_to_raw_img(&convert_rect(&info.sub_stat3_position)),
+            info.binarization.sub_stat,
+        );
         if let Some(im) = im_sub_stat_3 {
-            im.to_gray_image()
-                .save("captures/sub_stat_3.png")
-                .expect("Err");
+            save_capture(&im, "sub_stat_3");
         }
 
-        let im_sub_stat_4 =
-            pre_process(panel.crop_to_raw_img(&convert_rect(&info.sub_stat4_position)));
+        let im_sub_stat_4 = pre_process_with(
+            panel.crop_to_raw_img(&convert_rect(&info.sub_stat4_position)),
+            info.binarization.sub_stat,
+        );
         if let Some(im) = im_sub_stat_4 {
-            im.to_gray_image()
-                .save("captures/sub_stat_4.png")
-                .expect("Err");
+            save_capture(&im, "sub_stat_4");
         }
 
-        let im_level = pre_process(panel.crop_to_raw_img(&convert_rect(&info.level_position)));
+        let im_level = pre_process_with(
+            panel.crop_to_raw_img(&convert_rect(&info.level_position)),
+            info.binarization.level,
+        );
         if let Some(im) = im_level {
-            im.to_gray_image().save("captures/level.png").expect("Err");
+            save_capture(&im, "level");
         }
 
-        let im_equip = pre_process(panel.crop_to_raw_img(&convert_rect(&info.equip_position)));
+        let im_equip = pre_process_with(
+            panel.crop_to_raw_img(&convert_rect(&info.equip_position)),
+            info.binarization.equip,
+        );
         if let Some(im) = im_equip {
-            im.to_gray_image().save("captures/equip.png").expect("Err");
+            save_capture(&im, "equip");
         }
+
+        Ok(())
     }
 
-    pub fn start(&mut self) -> Vec<InternalArtifact> {
-        self.panel_down();
+    pub fn start(&mut self) -> Result<Vec<InternalArtifact>, YasError> {
+        self.panel_down()?;
         if self.config.capture_only {
-            self.start_capture_only();
-            return Vec::new();
+            self.start_capture_only()?;
+            return Ok(Vec::new());
         }
 
-        let mut count = match self.get_art_count() {
-            Ok(v) => v,
-            Err(_) => 1500,
-        };
+        let mut count = self.get_art_count().unwrap_or(1500);
 
+        #[allow(clippy::manual_div_ceil)]
         let total_row = (count + self.col - 1) / self.col;
+        #[allow(clippy::manual_is_multiple_of)]
         let last_row_col = if count % self.col == 0 {
             self.col
         } else {
@@ -598,169 +1290,93 @@ impl YasScanner {
         info!("total row: {}", total_row);
         info!("last column: {}", last_row_col);
 
-        let (tx, rx) = mpsc::channel::<Option<(RawCaptureImage, u32)>>();
-        let info_2 = self.info.clone();
-        // v bvvmnvbm
-        let is_verbose = self.config.verbose;
-        let is_dump_mode = self.config.dump_mode;
-        let min_level = self.config.min_level;
-        let handle = thread::spawn(move || {
-            let mut results: Vec<InternalArtifact> = Vec::new();
-            let mut model = CRNNModel::new(
-                String::from("model_training.onnx"),
-                String::from("index_2_word.json"),
-            );
-            let mut error_count = 0;
-            let mut dup_count = 0;
-            let mut hash = HashSet::new();
-            let mut consecutive_dup_count = 0;
-            let info = info_2;
-
-            let mut cnt = 0;
-            if is_dump_mode {
-                fs::create_dir("dumps").expect("Err");
+        let mut previous: Vec<YasScanResult> = Vec::new();
+        let mut resume_at = ScanPosition {
+            index: 0,
+            row: 0,
+            col: 0,
+        };
+        let journal = match self.config.journal {
+            Some(ref path) if self.config.resume => {
+                let entries = load_journal(path)?;
+                resume_at = resume_position(&entries, self.col);
+                info!(
+                    "从断点继续：已识别{}个圣遗物，从第{}行第{}列开始",
+                    entries.len(),
+                    resume_at.row + 1,
+                    resume_at.col + 1
+                );
+                previous = entries.into_iter().map(|e| e.result).collect();
+                Some(ScanJournal::append_to(path)?)
             }
+            Some(ref path) => Some(ScanJournal::create(path, self.config.overwrite_journal)?),
+            None => None,
+        };
 
-            let convert_rect = |rect: &PixelRectBound| PixelRect {
-                left: rect.left - info.panel_position.left,
-                top: rect.top - info.panel_position.top,
-                width: rect.right - rect.left,
-                height: rect.bottom - rect.top,
-            };
-
-            for i in rx {
-                let (capture, star) = match i {
-                    Some(v) => v,
-                    None => break,
-                };
-                // info!("raw capture image: width = {}, height = {}", capture.w, capture.h);
-                // capture.save("raw0.png");
-                // let now = SystemTime::now();
-
-                let model_inference = |pos: &PixelRectBound, name: &str, cnt: i32| -> String {
-                    let raw_img = capture.crop_to_raw_img(&convert_rect(pos));
-                    // raw_img.to_gray_image().save("raw.png");
-                    // info!("raw_img: width = {}, height = {}", raw_img.w, raw_img.h);
-
-                    if is_dump_mode {
-                        raw_img
-                            .grayscale_to_gray_image()
-                            .save(format!("dumps/{}_{}.png", name, cnt))
-                            .expect("Err");
-                    }
+        let mut scanned_row = 0_u32;
+        let mut scanned_count = 0_u32;
+        let mut start_row = 0_u32;
+        let mut start_col = 0_u32;
 
-                    let processed_img = match pre_process(raw_img) {
-                        Some(im) => im,
-                        None => {
-                            return String::new();
-                        }
-                    };
-                    if is_dump_mode {
-                        processed_img
-                            .to_gray_image()
-                            .save(format!("dumps/p_{}_{}.png", name, cnt))
-                            .expect("Err");
+        self.move_to(0, 0);
+        self.input.mouse_click();
+        utils::sleep(1000);
+        // self.wait_until_switched();
+        self.sample_initial_color()?;
+
+        if resume_at.index > 0 {
+            // near the end the list stops scrolling, the remaining rows are lower in the view
+            let skip = resume_at.row.min(total_row.saturating_sub(self.row));
+            let mut remain = skip;
+            while remain > 0 {
+                let n = remain.min(self.row);
+                match self.scroll_rows(n)? {
+                    ScrollResult::TLE => {
+                        return Err(YasError::Scroll(String::from("无法翻到上次扫描的位置")))
                     }
-
-                    let inference_result = model.inference_string(&processed_img);
-                    if is_dump_mode {
-                        fs::write(format!("dumps/{}_{}.txt", name, cnt), &inference_result)
-                            .expect("Err");
+                    // nothing new is scanned, the journaled results are still returned
+                    ScrollResult::Interrupt => {
+                        count = 0;
+                        break;
                     }
-
-                    inference_result
-                };
-
-                let str_title = model_inference(&info.title_position, "title", cnt);
-                let str_main_stat_name =
-                    model_inference(&info.main_stat_name_position, "main_stat_name", cnt);
-                let str_main_stat_value =
-                    model_inference(&info.main_stat_value_position, "main_stat_value", cnt);
-
-                let str_sub_stat_1 = model_inference(&info.sub_stat1_position, "sub_stat_1", cnt);
-                let str_sub_stat_2 = model_inference(&info.sub_stat2_position, "sub_stat_2", cnt);
-                let str_sub_stat_3 = model_inference(&info.sub_stat3_position, "sub_stat_3", cnt);
-                let str_sub_stat_4 = model_inference(&info.sub_stat4_position, "sub_stat_4", cnt);
-
-                let str_level = model_inference(&info.level_position, "level", cnt);
-                let str_equip = model_inference(&info.equip_position, "equip", cnt);
-
-                cnt += 1;
-
-                // let predict_time = now.elapsed().unwrap().as_millis();
-                // println!("predict time: {}ms", predict_time);
-
-                let result = YasScanResult {
-                    name: str_title,
-                    main_stat_name: str_main_stat_name,
-                    main_stat_value: str_main_stat_value,
-                    sub_stat_1: str_sub_stat_1,
-                    sub_stat_2: str_sub_stat_2,
-                    sub_stat_3: str_sub_stat_3,
-                    sub_stat_4: str_sub_stat_4,
-                    level: str_level,
-                    equip: str_equip,
-                    star,
-                };
-                if is_verbose {
-                    info!("{:?}", result);
-                }
-                // println!("{:?}", result);
-                let art = result.to_internal_artifact();
-                if let Some(a) = art {
-                    if hash.contains(&a) {
-                        dup_count += 1;
-                        consecutive_dup_count += 1;
-                        warn!("dup artifact detected: {:?}", result);
-                    } else {
-                        consecutive_dup_count = 0;
-                        hash.insert(a.clone());
-                        results.push(a);
-                    }
-                } else {
-                    error!("wrong detection: {:?}", result);
-                    error_count += 1;
-                    // println!("error parsing results");
-                }
-                if consecutive_dup_count >= info.art_row {
-                    error!("检测到连续多个重复圣遗物，可能为翻页错误，或者为非背包顶部开始扫描");
-                    break;
+                    _ => (),
                 }
+                remain -= n;
             }
+            utils::sleep(100);
 
-            info!("error count: {}", error_count);
-            info!("dup count: {}", dup_count);
-
-            if min_level > 0 {
-                results
-                    .into_iter()
-                    .filter(|result| result.level >= min_level)
-                    .collect::<Vec<_>>()
-            } else {
-                results
-            }
-        });
+            scanned_row = resume_at.row;
+            scanned_count = resume_at.index;
+            start_row = resume_at.row - skip;
+            start_col = resume_at.col;
+        }
 
-        let mut scanned_row = 0_u32;
-        let mut scanned_count = 0_u32;
-        let mut start_row = 0_u32;
+        let (tx, handle) = spawn_recognizer(
+            self.reader.clone(),
+            self.info.clone(),
+            &self.config,
+            journal,
+            previous,
+        )?;
+        let mut recorder = match self.config.save_captures {
+            Some(ref dir) => Some(CaptureRecorder::new(dir, &self.info)?),
+            None => None,
+        };
 
-        self.move_to(0, 0);
-        self.enigo.mouse_click(MouseButton::Left);
-        utils::sleep(1000);
-        // self.wait_until_switched();
-        self.sample_initial_color();
+        // errors inside the loop stop the scan, the recognizer is still joined
+        // so that nothing is left running when we return
+        let mut scan_error: Option<YasError> = None;
 
         'outer: while scanned_count < count {
-            'row: for row in start_row..self.row {
+            for row in start_row..self.row {
                 let c = if scanned_row == total_row - 1 {
                     last_row_col
                 } else {
                     self.col
                 };
-                'col: for col in 0..c {
+                for col in start_col..c {
                     // 大于最大数量则退出
-                    if scanned_count > count {
+                    if scanned_count >= count {
                         break 'outer;
                     }
 
@@ -770,20 +1386,44 @@ impl YasScanner {
                     }
 
                     self.move_to(row, col);
-                    self.enigo.mouse_click(MouseButton::Left);
-
-                    self.wait_until_switched();
-
-                    let capture = self.capture_panel().unwrap();
-                    let star = self.get_star();
+                    self.input.mouse_click();
+
+                    let panel = self.wait_until_switched().and_then(|_| {
+                        let capture = self.capture_panel()?;
+                        let star_color = self.get_star_color()?;
+                        Ok((capture, star_color))
+                    });
+                    let (capture, star_color) = match panel {
+                        Ok(v) => v,
+                        Err(e) => {
+                            scan_error = Some(e);
+                            break 'outer;
+                        }
+                    };
+                    let star = star_from_color(&star_color);
                     if star < self.config.min_star {
                         break 'outer;
                     }
-                    tx.send(Some((capture, star))).unwrap();
+                    if let Some(ref mut r) = recorder {
+                        if let Err(e) = r.record(&capture, star_color, scanned_row, col) {
+                            scan_error = Some(e);
+                            break 'outer;
+                        }
+                    }
+                    // the recognizer has quit on its own (too many duplicates)
+                    let position = ScanPosition {
+                        index: scanned_count,
+                        row: scanned_row,
+                        col,
+                    };
+                    if tx.send((capture, star, position)).is_err() {
+                        break 'outer;
+                    }
 
                     scanned_count += 1;
                 } // end 'col
 
+                start_col = 0;
                 scanned_row += 1;
 
                 if scanned_row >= self.config.max_row {
@@ -793,27 +1433,44 @@ impl YasScanner {
             } // end 'row
 
             let remain = count - scanned_count;
+            #[allow(clippy::manual_div_ceil)]
             let remain_row = (remain + self.col - 1) / self.col;
             let scroll_row = remain_row.min(self.row);
             start_row = self.row - scroll_row;
             match self.scroll_rows(scroll_row) {
-                ScrollResult::TLE => {
-                    error!("翻页出现问题");
+                Ok(ScrollResult::TLE) => {
+                    scan_error = Some(YasError::Scroll(String::from("翻页出现问题")));
+                    break 'outer;
+                }
+                Ok(ScrollResult::Interrupt) => break 'outer,
+                Ok(_) => (),
+                Err(e) => {
+                    scan_error = Some(e);
                     break 'outer;
                 }
-                ScrollResult::Interrupt => break 'outer,
-                _ => (),
             }
 
             utils::sleep(100);
         }
 
-        tx.send(None).unwrap();
+        drop(tx);
+        if let Some(ref r) = recorder {
+            if let Err(e) = r.finish() {
+                scan_error = scan_error.or(Some(e));
+            }
+        }
 
         info!("扫描结束，等待识别线程结束，请勿关闭程序");
-        let results: Vec<InternalArtifact> = handle.join().unwrap();
+        let results = match handle.join() {
+            Ok(v) => v,
+            Err(_) => Err(YasError::Recognition(String::from("识别线程异常退出"))),
+        };
+        if let Some(e) = scan_error {
+            return Err(e);
+        }
+        let results = results?;
         info!("count: {}", results.len());
-        results
+        Ok(results)
     }
 }
 
@@ -850,3 +1507,176 @@ impl YasScanner {
     //     Ok(result)
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+    use crate::capture::MemoryCapture;
+    use crate::input::{InputAction, RecordingInput};
+
+    struct NoReader;
+
+    impl PanelReader for NoReader {
+        fn read_count(&self, _image: &RawImage) -> Result<String, YasError> {
+            Ok(String::new())
+        }
+
+        fn read_panel(
+            &self,
+            _buffers: &mut PanelBuffers,
+            _capture: &RawCaptureImage,
+            _star: u32,
+            _seq: u32,
+        ) -> Result<YasScanResult, YasError> {
+            unreachable!("navigation does not read panels")
+        }
+    }
+
+    // the flag pixel leaves its colour while a row is half way and gets it back
+    // after every `ticks_per_row` wheel ticks
+    struct ScrollingCapture {
+        input: RecordingInput,
+        ticks_per_row: i32,
+    }
+
+    impl CaptureBackend for ScrollingCapture {
+        fn capture(&self, rect: &PixelRect) -> Result<Vec<u8>, String> {
+            let v = if self.input.total_scroll() % self.ticks_per_row == 0 {
+                200
+            } else {
+                20
+            };
+            Ok(vec![v; (rect.width * rect.height * 4) as usize])
+        }
+    }
+
+    fn info() -> ScanInfo {
+        ScanInfo::from_16_9(1600, 900, 0, 0)
+    }
+
+    fn test_scanner(
+        capture: impl CaptureBackend + 'static,
+        input: &RecordingInput,
+        info: ScanInfo,
+    ) -> YasScanner {
+        let config = YasScannerConfig {
+            scroll_stop: 0,
+            scroll_direction: -1,
+            scroll_step: 5,
+            ..YasScannerConfig::default()
+        };
+        YasScanner::with_backends(
+            Arc::new(NoReader),
+            Box::new(input.clone()),
+            Box::new(capture),
+            info,
+            config,
+            false,
+        )
+    }
+
+    fn blank() -> MemoryCapture {
+        MemoryCapture::new(RgbImage::from_pixel(1600, 900, Rgb([200, 200, 200])))
+    }
+
+    #[test]
+    fn move_to_is_relative_to_the_window() {
+        let input = RecordingInput::new();
+        let info = ScanInfo::from_16_9(1600, 900, 100, 50);
+        let (x, y) = info.art_position(1, 2);
+        let mut scanner = test_scanner(blank(), &input, info);
+
+        scanner.move_to(1, 2);
+        scanner.input.mouse_click();
+        assert_eq!(
+            input.actions(),
+            vec![InputAction::MoveTo(100 + x, 50 + y), InputAction::Click]
+        );
+    }
+
+    #[test]
+    fn panel_down_scrolls_until_the_level_shows() {
+        let info = info();
+        let star = InputAction::MoveTo(info.star_x as i32, info.star_y as i32);
+
+        let input = RecordingInput::new();
+        let mut scanner = test_scanner(blank(), &input, info.clone());
+        scanner.panel_down().unwrap();
+        let mut expected = vec![star.clone()];
+        expected.extend(vec![InputAction::Scroll(5); 20]);
+        assert_eq!(input.actions(), expected);
+
+        let mut frame = RgbImage::from_pixel(1600, 900, Rgb([200, 200, 200]));
+        let level = (
+            info.level_position.left as u32,
+            info.level_position.bottom as u32,
+        );
+        frame.put_pixel(level.0, level.1, Rgb([57, 67, 79]));
+        let input = RecordingInput::new();
+        let mut scanner = test_scanner(MemoryCapture::new(frame), &input, info);
+        scanner.panel_down().unwrap();
+        assert_eq!(input.actions(), vec![star]);
+    }
+
+    #[test]
+    fn scroll_one_row_stops_when_the_flag_returns() {
+        let input = RecordingInput::new();
+        let capture = ScrollingCapture {
+            input: input.clone(),
+            ticks_per_row: 15,
+        };
+        let mut scanner = test_scanner(capture, &input, info());
+        scanner.sample_initial_color().unwrap();
+
+        assert_eq!(scanner.scroll_one_row().unwrap(), ScrollResult::Success);
+        assert_eq!(input.actions(), vec![InputAction::Scroll(-5); 3]);
+        assert_eq!(scanner.scrolled_rows, 1);
+        assert!((scanner.avg_scroll_one_row - 3.0).abs() < 1e-9);
+
+        input.clear();
+        assert_eq!(scanner.scroll_rows(2).unwrap(), ScrollResult::Success);
+        assert_eq!(input.actions(), vec![InputAction::Scroll(-5); 6]);
+    }
+
+    #[test]
+    fn scroll_one_row_gives_up_on_a_still_screen() {
+        let input = RecordingInput::new();
+        let mut scanner = test_scanner(blank(), &input, info());
+        scanner.sample_initial_color().unwrap();
+
+        assert_eq!(scanner.scroll_one_row().unwrap(), ScrollResult::TLE);
+        assert_eq!(input.actions(), vec![InputAction::Scroll(-5); 20]);
+        assert_eq!(scanner.scrolled_rows, 0);
+    }
+
+    #[test]
+    fn scroll_rows_uses_the_average_after_five_rows() {
+        let input = RecordingInput::new();
+        let mut scanner = test_scanner(blank(), &input, info());
+        scanner.sample_initial_color().unwrap();
+        scanner.scrolled_rows = 5;
+        scanner.avg_scroll_one_row = 4.0;
+
+        // 4 * 2 - 3 single ticks, then the flag is already aligned
+        assert_eq!(scanner.scroll_rows(2).unwrap(), ScrollResult::Skip);
+        assert_eq!(input.actions(), vec![InputAction::Scroll(-1); 5]);
+    }
+
+    #[test]
+    fn align_row_scrolls_single_ticks() {
+        let input = RecordingInput::new();
+        let capture = ScrollingCapture {
+            input: input.clone(),
+            ticks_per_row: 3,
+        };
+        let mut scanner = test_scanner(capture, &input, info());
+        scanner.sample_initial_color().unwrap();
+        // a third of a row too little
+        scanner.input.mouse_scroll(-1);
+
+        assert!(scanner.align_row().unwrap());
+        assert_eq!(input.actions(), vec![InputAction::Scroll(-1); 3]);
+    }
+}